@@ -0,0 +1,100 @@
+//! The `theme` setting and the Windows app mode it may follow. Which theme
+//! name applies is decided here without the registry or the renderer: the
+//! app mode comes through `AppModeSource`, so selection is tested on any host.
+
+use serde::Deserialize;
+
+/// The built-in light scheme's name; `theme = "auto"` uses it in light mode.
+pub const DEFAULT_LIGHT: &str = "default-light";
+
+/// The `theme` setting: a theme by name, or one for each Windows app mode.
+/// `"auto"` follows the mode with the built-in schemes, `"wallpaper"` takes
+/// the colors from the desktop.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(untagged)]
+pub enum ThemeSetting {
+    Name(String),
+    PerMode { light: String, dark: String },
+}
+
+/// Windows' app mode, "Choose your app mode" in the color settings.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AppMode {
+    Light,
+    Dark,
+}
+
+/// Where the app mode is read from: the registry, or a stand-in in tests.
+pub trait AppModeSource {
+    /// `AppsUseLightTheme` under `HKCU\...\Themes\Personalize`: 0 for dark,
+    /// 1 for light, None when it isn't set.
+    fn apps_use_light_theme(&self) -> Option<u32>;
+}
+
+/// The app mode `source` reports; light when unset, as Windows defaults to.
+pub fn app_mode(source: &dyn AppModeSource) -> AppMode {
+    match source.apps_use_light_theme() {
+        Some(0) => AppMode::Dark,
+        _ => AppMode::Light,
+    }
+}
+
+impl ThemeSetting {
+    /// The name of the theme to use in `mode`.
+    pub fn select(&self, mode: AppMode) -> &str {
+        match (self, mode) {
+            (ThemeSetting::Name(name), AppMode::Light) if name == "auto" => DEFAULT_LIGHT,
+            (ThemeSetting::Name(name), AppMode::Dark) if name == "auto" => "default",
+            (ThemeSetting::Name(name), _) => name,
+            (ThemeSetting::PerMode { light, .. }, AppMode::Light) => light,
+            (ThemeSetting::PerMode { dark, .. }, AppMode::Dark) => dark,
+        }
+    }
+
+    /// Whether the theme depends on the app mode.
+    pub fn follows_mode(&self) -> bool {
+        match self {
+            ThemeSetting::Name(name) => name == "auto",
+            ThemeSetting::PerMode { .. } => true,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{app_mode, AppMode, AppModeSource, ThemeSetting};
+
+    struct Registry(Option<u32>);
+
+    impl AppModeSource for Registry {
+        fn apps_use_light_theme(&self) -> Option<u32> {
+            self.0
+        }
+    }
+
+    #[test]
+    fn theme_follows_the_app_mode() {
+        assert_eq!(app_mode(&Registry(Some(0))), AppMode::Dark);
+        assert_eq!(app_mode(&Registry(Some(1))), AppMode::Light);
+        assert_eq!(app_mode(&Registry(None)), AppMode::Light);
+
+        #[derive(serde::Deserialize)]
+        struct Config {
+            theme: ThemeSetting,
+        }
+        let setting = |toml: &str| toml::from_str::<Config>(toml).unwrap().theme;
+        let per_mode = setting("theme = { light = \"nord-light\", dark = \"nord\" }");
+        assert!(per_mode.follows_mode());
+        assert_eq!(per_mode.select(app_mode(&Registry(Some(1)))), "nord-light");
+        assert_eq!(per_mode.select(app_mode(&Registry(Some(0)))), "nord");
+
+        let auto = setting("theme = \"auto\"");
+        assert!(auto.follows_mode());
+        assert_eq!((auto.select(AppMode::Light), auto.select(AppMode::Dark)), ("default-light", "default"));
+
+        let nord = setting("theme = \"nord\"");
+        assert!(!nord.follows_mode());
+        assert_eq!((nord.select(AppMode::Light), nord.select(AppMode::Dark)), ("nord", "nord"));
+        assert!(toml::from_str::<Config>("theme = { light = \"nord-light\" }").is_err());
+    }
+}
@@ -109,7 +109,7 @@ pub fn print_reparse_points_info() {
                     println!("No reparse points found in Windows Apps directory");
                 } else {
                     println!("Found {} reparse points:", reparse_points.len());
-                    println!("{:<30} {:<10} {:<30} {}", "Name", "Length", "Attributes", "FullName");
+                    println!("{:<30} {:<10} {:<30} FullName", "Name", "Length", "Attributes");
                     println!("{}", "-".repeat(100));
 
                     for rp in reparse_points {
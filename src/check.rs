@@ -0,0 +1,753 @@
+//! `windmenu config check`: strict validation of windmenu.toml and its
+//! imports. Startup stays lenient — unknown keys are ignored and bad values
+//! warn and fall back to defaults — so this is where a typo like
+//! `centre = false` surfaces, with file, line and column.
+
+use std::fmt;
+use std::fs;
+use std::io;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use toml::de::{DeTable, DeValue};
+use toml::Spanned;
+
+use crate::appmode;
+use crate::menu::{self, Hotkey, Menu, MenuConfig, Pack};
+use crate::theme::{self, LowContrast};
+use crate::wlines::FontQuality;
+
+/// Top-level keys `MenuConfig` reads, besides the palette keys.
+const ROOT_KEYS: &[&str] = &[
+    "hotkey", "next", "prev", "matching", "case_sensitive", "normalize", "history",
+    "horizontal", "lines", "width", "center", "padding", "border_width", "font", "font_quality", "prompt",
+    "theme", "themes", "min_contrast", "commands", "aliases", "import", "nested_imports",
+];
+/// Color keys, valid at the top level and in `[themes.*]`.
+const PALETTE_KEYS: &[&str] = &[
+    "bg", "fg", "bg_select", "fg_select", "bg_input", "fg_input", "fg_match", "fg_match_select",
+    "bg_prompt", "fg_prompt", "border", "separator",
+];
+const THEME_KEYS: &[&str] = &["extends"];
+const COMMAND_KEYS: &[&str] = &["name", "args", "keys", "aliases", "keywords"];
+/// The only keys an imported pack reads.
+const PACK_KEYS: &[&str] = &["pack", "themes", "commands", "import"];
+const PACK_INFO_KEYS: &[&str] = &["name", "version", "author", "min_version"];
+const MATCHING_MODES: &[&str] = &["complete", "keywords", "fuzzy"];
+const HISTORY_RANKINGS: &[&str] = &["count", "frecency"];
+
+/// One problem found in a config file, positioned 1-based.
+#[derive(Debug, PartialEq)]
+pub struct Diagnostic {
+    pub file: String,
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}:{}: {}", self.file, self.line, self.column, self.message)
+    }
+}
+
+/// Line and column (1-based, in chars) of a byte offset.
+fn position(text: &str, offset: usize) -> (usize, usize) {
+    let before = &text[..offset.min(text.len())];
+    let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+    let line = before.matches('\n').count() + 1;
+    (line, before[line_start..].chars().count() + 1)
+}
+
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut diag = row[0];
+        row[0] = i + 1;
+        for (j, &cb) in b.iter().enumerate() {
+            let next = (diag + usize::from(ca != cb)).min(row[j] + 1).min(row[j + 1] + 1);
+            diag = row[j + 1];
+            row[j + 1] = next;
+        }
+    }
+    row[b.len()]
+}
+
+/// The closest candidate, if it's close enough to be a plausible typo.
+fn did_you_mean<'a>(word: &str, candidates: impl IntoIterator<Item = &'a str>) -> Option<&'a str> {
+    let limit = 2.max(word.chars().count() / 3);
+    candidates
+        .into_iter()
+        .map(|c| (edit_distance(&word.to_lowercase(), c), c))
+        .filter(|&(distance, _)| distance <= limit)
+        .min_by_key(|&(distance, _)| distance)
+        .map(|(_, c)| c)
+}
+
+fn suggestion<'a>(word: &str, candidates: impl IntoIterator<Item = &'a str>) -> String {
+    did_you_mean(word, candidates)
+        .map(|c| format!("; did you mean '{}'?", c))
+        .unwrap_or_default()
+}
+
+/// The items of an array value; none for anything else.
+fn items<'a, 'i>(value: &'a DeValue<'i>) -> &'a [Spanned<DeValue<'i>>] {
+    value.as_array().map_or(&[], |items| &items[..])
+}
+
+/// A theme's `extends`, checked once every file's themes are known.
+struct Extends {
+    at: Diagnostic,
+    theme: String,
+    parent: String,
+}
+
+/// Problems found in one file, kept as byte offsets until `finish`.
+struct FileCheck<'a> {
+    file: String,
+    text: &'a str,
+    found: Vec<(usize, String)>,
+}
+
+impl<'a> FileCheck<'a> {
+    fn new(file: &str, text: &'a str) -> Self {
+        FileCheck { file: file.to_string(), text, found: Vec::new() }
+    }
+
+    fn report(&mut self, span: Range<usize>, message: String) {
+        self.found.push((span.start, message));
+    }
+
+    fn diagnostic(&self, offset: usize, message: String) -> Diagnostic {
+        let (line, column) = position(self.text, offset);
+        Diagnostic { file: self.file.clone(), line, column, message }
+    }
+
+    fn finish(mut self) -> Vec<Diagnostic> {
+        self.found.sort_by_key(|&(offset, _)| offset);
+        let found = std::mem::take(&mut self.found);
+        found.into_iter().map(|(offset, message)| self.diagnostic(offset, message)).collect()
+    }
+
+    /// Parse the file, reporting a syntax error (then there's nothing more
+    /// to check) or a type mismatch against `T`.
+    fn parse<T: serde::de::DeserializeOwned>(&mut self) -> Option<Spanned<DeTable<'a>>> {
+        match DeTable::parse(self.text) {
+            Ok(table) => {
+                if let Err(e) = toml::from_str::<T>(self.text) {
+                    self.report(e.span().unwrap_or(0..0), e.message().to_string());
+                }
+                Some(table)
+            }
+            Err(e) => {
+                self.report(e.span().unwrap_or(0..0), e.message().to_string());
+                None
+            }
+        }
+    }
+
+    fn unknown_keys(&mut self, table: &DeTable<'_>, known: &[&str], context: &str) {
+        for key in table.keys() {
+            let name = key.get_ref().as_ref();
+            if !known.contains(&name) {
+                let hint = suggestion(name, known.iter().copied());
+                self.report(key.span(), format!("unknown key '{}'{}{}", name, context, hint));
+            }
+        }
+    }
+
+    /// Evaluate each color of a palette table, references and cycles within
+    /// it included. Keys it leaves unset come from elsewhere: any color will do.
+    fn palette(&mut self, table: &DeTable<'_>) {
+        let value = |key: &str| table.get(key)?.get_ref().as_str().map(String::from);
+        for (key, item) in table {
+            let key = key.get_ref().as_ref();
+            if PALETTE_KEYS.contains(&key) && item.get_ref().is_str() {
+                if let Err(e) = theme::evaluate(key, &value, &|_| Ok([0; 3])) {
+                    self.report(item.span(), format!("invalid color for {}: {}", key, e));
+                }
+            }
+        }
+    }
+
+    fn themes(&mut self, value: &DeValue<'_>, names: &mut Vec<String>, extends: &mut Vec<Extends>) {
+        for (name, theme) in value.as_table().into_iter().flatten() {
+            if let Some(theme) = theme.get_ref().as_table() {
+                let context = format!(" in theme '{}'", name.get_ref());
+                let known: Vec<&str> = PALETTE_KEYS.iter().chain(THEME_KEYS).copied().collect();
+                self.unknown_keys(theme, &known, &context);
+                self.palette(theme);
+                if let Some(parent) = theme.get("extends") {
+                    if let Some(parent_name) = parent.get_ref().as_str() {
+                        extends.push(Extends {
+                            at: self.diagnostic(parent.span().start, String::new()),
+                            theme: name.get_ref().to_string(),
+                            parent: parent_name.to_string(),
+                        });
+                    }
+                }
+            }
+            names.push(name.get_ref().to_string());
+        }
+    }
+
+    /// Report unknown key names one by one, then whether the combination as a
+    /// whole is usable.
+    fn key_names<T>(&mut self, value: &Spanned<DeValue<'_>>, combo: impl Fn(&[String]) -> Result<T, menu::MenuError>) {
+        let mut keys = Vec::new();
+        let mut known = true;
+        for item in items(value.get_ref()) {
+            let Some(key) = item.get_ref().as_str() else { return };
+            if Menu::parse_key_name_to_vk_code(key).is_err() {
+                self.report(item.span(), format!("unknown key '{}'", key));
+                known = false;
+            }
+            keys.push(key.to_string());
+        }
+        if known {
+            if let Err(e) = combo(&keys) {
+                self.report(value.span(), e.to_string());
+            }
+        }
+    }
+
+    fn commands(&mut self, value: &DeValue<'_>) {
+        for command in items(value) {
+            let Some(command) = command.get_ref().as_table() else { continue };
+            self.unknown_keys(command, COMMAND_KEYS, " in [[commands]]");
+            if let Some(keys) = command.get("keys") {
+                self.key_names(keys, |_| Ok::<(), menu::MenuError>(()));
+            }
+        }
+    }
+
+    /// Whether `min_contrast` is a ratio colors can reach: 1 to 21, or 0
+    /// (anything up to 1) for no check.
+    fn min_contrast(&mut self, value: &Spanned<DeValue<'_>>) {
+        let ratio = match value.get_ref() {
+            DeValue::Integer(i) => i.as_str().parse::<f64>().ok(),
+            DeValue::Float(f) => f.as_str().parse::<f64>().ok(),
+            _ => None,
+        };
+        if let Some(ratio) = ratio.filter(|r| !(0.0..=21.0).contains(r)) {
+            let message = format!("min_contrast {} is not a contrast ratio; use 1 to 21, or 0 to turn the check off", ratio);
+            self.report(value.span(), message);
+        }
+    }
+
+    /// Whether a string setting is one of `allowed` (case-insensitive).
+    fn one_of(&mut self, key: &str, value: &Spanned<DeValue<'_>>, allowed: &[&str]) {
+        if let Some(s) = value.get_ref().as_str() {
+            if !allowed.contains(&s.to_lowercase().as_str()) {
+                let hint = suggestion(s, allowed.iter().copied());
+                self.report(
+                    value.span(),
+                    format!("{} '{}' is not one of {}{}", key, s, allowed.join(", "), hint),
+                );
+            }
+        }
+    }
+}
+
+/// Check a root config and the packs it imports. `read` loads an import
+/// (resolved against `base_dir`), so tests can check without touching disk.
+fn check_config(
+    file: &str,
+    text: &str,
+    base_dir: &Path,
+    read: &dyn Fn(&Path) -> io::Result<String>,
+) -> Vec<Diagnostic> {
+    let mut root = FileCheck::new(file, text);
+    let Some(table) = root.parse::<MenuConfig>() else {
+        return root.finish();
+    };
+    let table = table.get_ref();
+    let known: Vec<&str> = ROOT_KEYS.iter().chain(PALETTE_KEYS).copied().collect();
+    root.unknown_keys(table, &known, "");
+    root.palette(table);
+
+    let mut themes = vec!["default".to_string(), appmode::DEFAULT_LIGHT.to_string()];
+    let mut extends = Vec::new();
+    for (key, value) in table {
+        match key.get_ref().as_ref() {
+            "hotkey" => root.key_names(value, Hotkey::to_registration),
+            "next" | "prev" => root.key_names(value, menu::parse_key_combo),
+            // The digits are wlines' numeric aliases, still accepted
+            "matching" if !matches!(value.get_ref().as_str(), Some("0" | "1" | "2")) => {
+                root.one_of("matching", value, MATCHING_MODES)
+            }
+            "history" => root.one_of("history", value, HISTORY_RANKINGS),
+            "font_quality" => root.one_of("font_quality", value, &FontQuality::NAMES),
+            "min_contrast" => root.min_contrast(value),
+            "themes" => root.themes(value.get_ref(), &mut themes, &mut extends),
+            "commands" => root.commands(value.get_ref()),
+            _ => {}
+        }
+    }
+
+    let mut walk = ImportWalk {
+        read,
+        themes,
+        extends,
+        diagnostics: Vec::new(),
+        seen: Vec::new(),
+        root: menu::import_key(Path::new(file)),
+        stack: Vec::new(),
+        nested: table.get("nested_imports").and_then(|v| v.get_ref().as_bool()).unwrap_or(false),
+    };
+    walk.imports(&mut root, table, base_dir);
+    let themes = walk.themes;
+    let extends = walk.extends;
+
+    // `theme` names one theme, "auto", or one per app mode; "wallpaper" is
+    // taken from the desktop and needs no table
+    let selected: Vec<&Spanned<DeValue<'_>>> = match table.get("theme") {
+        Some(theme) => match theme.get_ref().as_table() {
+            Some(modes) => {
+                root.unknown_keys(modes, &["light", "dark"], " in theme");
+                ["light", "dark"].into_iter().filter_map(|mode| modes.get(mode)).collect()
+            }
+            None if theme.get_ref().as_str() == Some("auto") => Vec::new(),
+            None => vec![theme],
+        },
+        None => Vec::new(),
+    };
+    for theme in selected {
+        if let Some(name) = theme.get_ref().as_str() {
+            if name != theme::WALLPAPER && !themes.iter().any(|t| t == name) {
+                let hint = suggestion(name, themes.iter().map(String::as_str).chain([theme::WALLPAPER]));
+                root.report(theme.span(), format!("theme '{}' not found in [themes.*]{}", name, hint));
+            }
+        }
+    }
+
+    let mut diagnostics = root.finish();
+    diagnostics.extend(walk.diagnostics);
+    diagnostics.extend(check_extends(extends, &themes));
+    diagnostics
+}
+
+/// Report `extends` naming a theme no file defines, or leading back to the
+/// theme itself.
+fn check_extends(extends: Vec<Extends>, themes: &[String]) -> Vec<Diagnostic> {
+    let parents: Vec<(String, String)> = extends.iter().map(|e| (e.theme.clone(), e.parent.clone())).collect();
+    let parent_of = |theme: &str| parents.iter().rev().find(|(t, _)| t == theme).map(|(_, p)| p.as_str());
+    let mut diagnostics = Vec::new();
+    for Extends { mut at, theme, parent } in extends {
+        if !themes.contains(&parent) {
+            let hint = suggestion(&parent, themes.iter().map(String::as_str));
+            at.message = format!("theme '{}' extends unknown theme '{}'{}", theme, parent, hint);
+            diagnostics.push(at);
+            continue;
+        }
+        let mut chain = vec![theme.as_str(), parent.as_str()];
+        while let Some(next) = parent_of(chain[chain.len() - 1]) {
+            if chain.contains(&next) {
+                if next == theme {
+                    chain.push(next);
+                    at.message = format!("theme '{}' extends itself ({})", theme, chain.join(" -> "));
+                    diagnostics.push(at);
+                }
+                break;
+            }
+            chain.push(next);
+        }
+    }
+    diagnostics
+}
+
+/// Place the low-contrast pairs of the effective colors in the root config:
+/// at the key there setting either color, else at `theme`, else at the top.
+/// The colors may come from any pack; each message names where.
+fn check_contrast(file: &str, text: &str, low: Vec<(LowContrast, String)>) -> Vec<Diagnostic> {
+    let mut root = FileCheck::new(file, text);
+    let table = DeTable::parse(text).ok();
+    for (pair, message) in low {
+        let span = table.as_ref().and_then(|table| {
+            let table = table.get_ref();
+            [pair.text, pair.background, "theme"].into_iter().find_map(|key| table.get(key)).map(|v| v.span())
+        });
+        root.report(span.unwrap_or(0..0), message);
+    }
+    root.finish()
+}
+
+/// Follows imports the way startup does — depth-first, each pack once,
+/// globs expanded — checking every pack and collecting the themes they define.
+struct ImportWalk<'r> {
+    read: &'r dyn Fn(&Path) -> io::Result<String>,
+    themes: Vec<String>,
+    extends: Vec<Extends>,
+    diagnostics: Vec<Diagnostic>,
+    // Import keys (see `menu::import_key`) already checked
+    seen: Vec<PathBuf>,
+    // The root config's key, and the packs importing the current one, like
+    // the stack startup keeps
+    root: PathBuf,
+    stack: Vec<PathBuf>,
+    // Whether packs' own imports are followed (`nested_imports`)
+    nested: bool,
+}
+
+impl ImportWalk<'_> {
+    /// Check the packs `table` imports, reporting problems with the entries
+    /// themselves in `file`.
+    fn imports(&mut self, file: &mut FileCheck<'_>, table: &DeTable<'_>, base_dir: &Path) {
+        for item in table.get("import").map_or(&[][..], |v| items(v.get_ref())) {
+            let Some(entry) = item.get_ref().as_str() else { continue };
+            let paths = menu::expand_import(base_dir, entry);
+            if paths.is_empty() {
+                file.report(item.span(), format!("import '{}' matched no files", entry));
+            }
+            for path in paths {
+                let key = menu::import_key(&path);
+                if key == self.root || self.stack.contains(&key) {
+                    file.report(item.span(), format!("import '{}' forms a cycle", entry));
+                    continue;
+                }
+                if self.seen.contains(&key) {
+                    continue;
+                }
+                if self.stack.len() >= menu::MAX_IMPORT_DEPTH {
+                    let message = format!(
+                        "import '{}' nests deeper than {} levels and is skipped",
+                        entry,
+                        menu::MAX_IMPORT_DEPTH
+                    );
+                    file.report(item.span(), message);
+                    continue;
+                }
+                self.seen.push(key.clone());
+                match (self.read)(&path) {
+                    Ok(text) => self.pack(&path, key, &text),
+                    Err(_) => file.report(item.span(), format!("import '{}' not found", entry)),
+                }
+            }
+        }
+    }
+
+    /// Check an imported pack, then the packs it imports. Its own problems
+    /// are listed before theirs.
+    fn pack(&mut self, path: &Path, key: PathBuf, text: &str) {
+        let mut pack = FileCheck::new(&path.display().to_string(), text);
+        let at = self.diagnostics.len();
+        if let Some(table) = pack.parse::<Pack>() {
+            let table = table.get_ref();
+            pack.unknown_keys(table, PACK_KEYS, " (an imported pack only reads pack, themes, commands and import)");
+            if let Some(info) = table.get("pack").and_then(|v| v.get_ref().as_table()) {
+                pack.unknown_keys(info, PACK_INFO_KEYS, " in [pack]");
+                if let Some(min) = info.get("min_version") {
+                    if let Some(problem) = min.get_ref().as_str().and_then(menu::min_version_problem) {
+                        pack.report(min.span(), problem);
+                    }
+                }
+            }
+            if let Some(value) = table.get("themes") {
+                pack.themes(value.get_ref(), &mut self.themes, &mut self.extends);
+            }
+            if let Some(value) = table.get("commands") {
+                pack.commands(value.get_ref());
+            }
+            if !self.nested {
+                if let Some(import) = table.get("import") {
+                    let message = "import in a pack is ignored unless the root config sets nested_imports = true";
+                    pack.report(import.span(), message.to_string());
+                }
+            } else {
+                self.stack.push(key);
+                self.imports(&mut pack, table, path.parent().unwrap_or_else(|| Path::new(".")));
+                self.stack.pop();
+            }
+        }
+        self.diagnostics.splice(at..at, pack.finish());
+    }
+}
+
+/// `config check`: validate the config in effect and print every problem.
+/// Returns a process exit code: nonzero when anything was found.
+pub fn run() -> i32 {
+    let Some(path) = MenuConfig::resolve_path() else {
+        eprintln!("config check: no windmenu.toml found — windmenu uses built-in defaults");
+        return 1;
+    };
+    let text = match fs::read_to_string(&path) {
+        Ok(text) => text,
+        Err(e) => {
+            eprintln!("config check: failed to read {}: {}", path.display(), e);
+            return 1;
+        }
+    };
+    let base_dir = path.parent().unwrap_or_else(|| Path::new("."));
+    let file = path.display().to_string();
+    let mut diagnostics = check_config(&file, &text, base_dir, &|p| fs::read_to_string(p));
+    diagnostics.extend(check_contrast(&file, &text, menu::low_contrast(&path)));
+
+    for diagnostic in &diagnostics {
+        println!("{}", diagnostic);
+    }
+    match diagnostics.len() {
+        0 => {
+            println!("{}: no problems found", path.display());
+            0
+        }
+        1 => {
+            println!("1 problem found");
+            1
+        }
+        n => {
+            println!("{} problems found", n);
+            1
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{check_config, check_contrast, did_you_mean, position, Diagnostic};
+    use crate::color;
+    use crate::theme::LowContrast;
+    use crate::menu::{DEFAULT_CONFIG, MAX_IMPORT_DEPTH};
+    use std::fs;
+    use std::io;
+    use std::path::Path;
+
+    fn check(text: &str, packs: &[(&str, &str)]) -> Vec<Diagnostic> {
+        let read = |path: &Path| {
+            packs
+                .iter()
+                .find(|(name, _)| Path::new("cfg").join(name) == path)
+                .map(|(_, text)| text.to_string())
+                .ok_or_else(|| io::Error::from(io::ErrorKind::NotFound))
+        };
+        check_config("windmenu.toml", text, Path::new("cfg"), &read)
+    }
+
+    fn messages(diagnostics: &[Diagnostic]) -> Vec<String> {
+        diagnostics.iter().map(|d| format!("{}:{} {}", d.line, d.column, d.message)).collect()
+    }
+
+    #[test]
+    fn shipped_config_is_clean() {
+        assert_eq!(check(DEFAULT_CONFIG, &[]), vec![]);
+    }
+
+    #[test]
+    fn positions_are_one_based() {
+        let text = "a = 1\nbé = 2";
+        assert_eq!(position(text, 0), (1, 1));
+        assert_eq!(position(text, 6), (2, 1));
+        assert_eq!(position(text, text.rfind('=').unwrap()), (2, 4));
+    }
+
+    #[test]
+    fn suggestions_only_for_near_misses() {
+        assert_eq!(did_you_mean("centre", ["center", "width"]), Some("center"));
+        assert_eq!(did_you_mean("bg_selected", ["bg", "bg_select"]), Some("bg_select"));
+        assert_eq!(did_you_mean("colour_scheme", ["center", "width"]), None);
+    }
+
+    #[test]
+    fn unknown_keys_are_reported_with_suggestions() {
+        let diagnostics = check(
+            "centre = false\n\n[themes.dark]\nbg_selected = \"#000000\"\n",
+            &[],
+        );
+        assert_eq!(messages(&diagnostics), vec![
+            "1:1 unknown key 'centre'; did you mean 'center'?",
+            "4:1 unknown key 'bg_selected' in theme 'dark'; did you mean 'bg_select'?",
+        ]);
+        assert_eq!(diagnostics[0].file, "windmenu.toml");
+    }
+
+    #[test]
+    fn bad_values_are_reported_where_they_are() {
+        let text = r##"matching = "fuzy"
+history = "recent"
+bg = "#12345"
+hotkey = ["CTRL", "SPCE"]
+next = ["ALT", "J"]
+
+[[commands]]
+name = "Show Desktop"
+keys = ["WIN", "DD"]
+"##;
+        let bad_color = format!(
+            "3:6 invalid color for bg: '#12345' is not a color or palette key (expected {}, or {})",
+            color::SYNTAX,
+            color::EXPRESSIONS
+        );
+        assert_eq!(messages(&check(text, &[])), vec![
+            "1:12 matching 'fuzy' is not one of complete, keywords, fuzzy; did you mean 'fuzzy'?",
+            "2:11 history 'recent' is not one of count, frecency",
+            bad_color.as_str(),
+            "4:19 unknown key 'SPCE'",
+            "5:8 Invalid arguments: navigation combo [\"ALT\", \"J\"] uses ALT/WIN, which are unsupported; \
+             use CTRL/SHIFT plus exactly one other key",
+            "9:16 unknown key 'DD'",
+        ]);
+        assert_eq!(messages(&check("font_quality = \"cleartipe\"\n", &[])), vec![
+            "1:16 font_quality 'cleartipe' is not one of cleartype, antialiased, none; did you mean 'cleartype'?",
+        ]);
+    }
+
+    #[test]
+    fn color_expressions_are_evaluated() {
+        let text = r##"bg = "#2e3440"
+bg_input = "lighten(bg, 8%)"
+fg_input = "fg"
+
+[themes.loop]
+bg_select = "mix(fg_select, bg, 50%)"
+fg_select = "darken(bg_select, 20%)"
+fg_match = "lighten(bg, 8)"
+"##;
+        assert_eq!(messages(&check(text, &[])), vec![
+            "6:13 invalid color for bg_select: bg_select -> fg_select -> bg_select forms a cycle",
+            "7:13 invalid color for fg_select: fg_select -> bg_select -> fg_select forms a cycle",
+            "8:12 invalid color for fg_match: '8' is not a percentage like 8%",
+        ]);
+    }
+
+    #[test]
+    fn min_contrast_must_be_reachable() {
+        assert_eq!(check("min_contrast = 4.5\n", &[]), vec![]);
+        assert_eq!(check("min_contrast = 0\n", &[]), vec![]);
+        assert_eq!(messages(&check("min_contrast = 45\n", &[])), vec![
+            "1:16 min_contrast 45 is not a contrast ratio; use 1 to 21, or 0 to turn the check off",
+        ]);
+    }
+
+    #[test]
+    fn low_contrast_is_placed_where_the_colors_are_chosen() {
+        let low = |text, background| (LowContrast { text, background, ratio: 1.2 }, format!("{} on {}", text, background));
+        let text = "theme = \"nord\"\nbg_select = \"#88c0d0\"\n";
+        let diagnostics = check_contrast("windmenu.toml", text, vec![low("fg_select", "bg_select"), low("fg", "bg")]);
+        assert_eq!(messages(&diagnostics), vec!["1:9 fg on bg", "2:13 fg_select on bg_select"]);
+
+        let diagnostics = check_contrast("windmenu.toml", "lines = 8\n", vec![low("fg", "bg")]);
+        assert_eq!(messages(&diagnostics), vec!["1:1 fg on bg"]);
+    }
+
+    #[test]
+    fn type_and_syntax_errors_are_positioned() {
+        let typed = check("lines = \"twelve\"\n", &[]);
+        assert_eq!((typed.len(), typed[0].line, typed[0].column), (1, 1, 9));
+
+        let broken = check("lines = 12\nwidth = \n", &[]);
+        assert_eq!((broken.len(), broken[0].line), (1, 2));
+    }
+
+    #[test]
+    fn imports_and_themes_are_checked_across_files() {
+        let text = "import = [\"packs/nord.toml\", \"missing.toml\"]\ntheme = \"nrod\"\n";
+        let pack = "lines = 5\n\n[themes.nord]\nbg = \"#2e3440\"\nfg = \"nope\"\n";
+        let diagnostics = check(text, &[("packs/nord.toml", pack)]);
+        let bad_color = format!(
+            "5:6 invalid color for fg: 'nope' is not a color or palette key (expected {}, or {})",
+            color::SYNTAX,
+            color::EXPRESSIONS
+        );
+        assert_eq!(messages(&diagnostics), vec![
+            "1:30 import 'missing.toml' not found",
+            "2:9 theme 'nrod' not found in [themes.*]; did you mean 'nord'?",
+            "1:1 unknown key 'lines' (an imported pack only reads pack, themes, commands and import)",
+            bad_color.as_str(),
+        ]);
+        assert_eq!(diagnostics[2].file, Path::new("cfg").join("packs/nord.toml").display().to_string());
+
+        // A theme from an import satisfies `theme`
+        assert!(check("import = [\"n.toml\"]\ntheme = \"nord\"\n", &[("n.toml", pack)])
+            .iter()
+            .all(|d| !d.message.starts_with("theme")));
+    }
+
+    #[test]
+    fn theme_may_follow_the_app_mode() {
+        assert_eq!(check("theme = \"auto\"\n", &[]), vec![]);
+        assert_eq!(check("theme = { light = \"default-light\", dark = \"default\" }\n", &[]), vec![]);
+        assert_eq!(check("theme = { light = \"wallpaper\", dark = \"default\" }\n", &[]), vec![]);
+        assert_eq!(messages(&check("theme = \"walpaper\"\n", &[])), vec![
+            "1:9 theme 'walpaper' not found in [themes.*]; did you mean 'wallpaper'?",
+        ]);
+        let text = "theme = { light = \"paper\", dark = \"nrod\", dusk = \"nord\" }\n\n[themes.nord]\nbg = \"#2e3440\"\n";
+        assert_eq!(messages(&check(text, &[])), vec![
+            "1:19 theme 'paper' not found in [themes.*]",
+            "1:35 theme 'nrod' not found in [themes.*]; did you mean 'nord'?",
+            "1:43 unknown key 'dusk' in theme; did you mean 'dark'?",
+        ]);
+    }
+
+    #[test]
+    fn theme_parents_are_checked_across_files() {
+        let text = "import = [\"base.toml\"]\n[themes.mine]\nextends = \"bsae\"\n[themes.ok]\nextends = \"base\"\n";
+        let pack = "[themes.base]\nbg = \"#000000\"\n[themes.x]\nextends = \"y\"\n[themes.y]\nextends = \"x\"\n";
+        let diagnostics = check(text, &[("base.toml", pack)]);
+        assert_eq!(messages(&diagnostics), vec![
+            "3:11 theme 'mine' extends unknown theme 'bsae'; did you mean 'base'?",
+            "4:11 theme 'x' extends itself (x -> y -> x)",
+            "6:11 theme 'y' extends itself (y -> x -> y)",
+        ]);
+    }
+
+    #[test]
+    fn pack_headers_are_checked() {
+        let pack = "[pack]\nnmae = \"power\"\nmin_version = \"999.0\"\n";
+        let diagnostics = check("import = [\"power.toml\"]\n", &[("power.toml", pack)]);
+        assert_eq!(messages(&diagnostics)[0], "2:1 unknown key 'nmae' in [pack]; did you mean 'name'?");
+        assert!(messages(&diagnostics)[1].starts_with("3:15 needs windmenu 999.0 or newer"));
+    }
+
+    #[test]
+    fn nested_imports_are_checked_in_their_own_files() {
+        let team = "import = [\"nord.toml\", \"gone.toml\", \"team.toml\"]\n";
+        let nord = "[themes.nord]\nbg = \"#2e3440\"\n";
+        let packs = [("packs/team.toml", team), ("packs/nord.toml", nord)];
+        let diagnostics = check("import = [\"packs/team.toml\"]\nnested_imports = true\ntheme = \"nord\"\n", &packs);
+        assert_eq!(messages(&diagnostics), vec![
+            "1:24 import 'gone.toml' not found",
+            "1:37 import 'team.toml' forms a cycle",
+        ]);
+        assert!(diagnostics.iter().all(|d| d.file.ends_with("team.toml")));
+
+        // Not followed unless the root opts in
+        let diagnostics = check("import = [\"packs/team.toml\"]\n", &packs);
+        assert_eq!(messages(&diagnostics), vec![
+            "1:10 import in a pack is ignored unless the root config sets nested_imports = true",
+        ]);
+    }
+
+    #[test]
+    fn imports_nest_as_deep_as_startup_reads_them() {
+        // The root imports p1.toml, which imports p2.toml, ... down to p9.toml
+        let names: Vec<String> = (1..=MAX_IMPORT_DEPTH + 1).map(|i| format!("p{}.toml", i)).collect();
+        let mut packs: Vec<(String, String)> =
+            names.windows(2).map(|w| (w[0].clone(), format!("import = [\"{}\"]\n", w[1]))).collect();
+        // Never checked: it nests one level too deep
+        packs.push((names[MAX_IMPORT_DEPTH].clone(), "lines = 5\n".to_string()));
+        let packs: Vec<(&str, &str)> = packs.iter().map(|(n, t)| (n.as_str(), t.as_str())).collect();
+        let diagnostics = check("import = [\"p1.toml\"]\nnested_imports = true\n", &packs);
+        let too_deep = &names[MAX_IMPORT_DEPTH];
+        assert_eq!(messages(&diagnostics), vec![format!(
+            "1:11 import '{}' nests deeper than {} levels and is skipped",
+            too_deep, MAX_IMPORT_DEPTH
+        )]);
+        assert!(diagnostics[0].file.ends_with(&format!("p{}.toml", MAX_IMPORT_DEPTH)));
+    }
+
+    #[test]
+    fn import_cycles_through_parent_dirs_are_found() {
+        let dir = std::env::temp_dir().join(format!("windmenu-check-{}", std::process::id()));
+        fs::create_dir_all(dir.join("packs/sub")).unwrap();
+        fs::write(dir.join("packs/team.toml"), "import = [\"sub/base.toml\"]\n").unwrap();
+        fs::write(dir.join("packs/sub/base.toml"), "import = [\"../team.toml\"]\n").unwrap();
+        let root = dir.join("windmenu.toml");
+        fs::write(&root, "import = [\"packs/team.toml\"]\nnested_imports = true\n").unwrap();
+
+        let read = |path: &Path| fs::read_to_string(path);
+        let text = fs::read_to_string(&root).unwrap();
+        let diagnostics = check_config(&root.display().to_string(), &text, &dir, &read);
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(messages(&diagnostics), vec!["1:11 import '../team.toml' forms a cycle"]);
+        assert!(diagnostics[0].file.ends_with("base.toml"));
+    }
+}
@@ -0,0 +1,361 @@
+//! Color syntax for config values: the hex forms, CSS `rgb()`/`hsl()`
+//! functions and CSS color names. Alpha is parsed and handed back alongside
+//! the color, for renderers that can use it; GDI drawing ignores it. Palette
+//! values may also derive one color from others with `lighten`, `darken` and
+//! `mix`.
+
+/// What `parse` accepts, for error messages.
+pub const SYNTAX: &str =
+    "#rgb, #rrggbb (or #rgba, #rrggbbaa), rgb(r, g, b), rgba(r, g, b, a), hsl(h, s%, l%), hsla(h, s%, l%, a) \
+     or a CSS color name";
+
+/// What `evaluate` accepts besides `SYNTAX`, for error messages.
+pub const EXPRESSIONS: &str =
+    "another color key of the palette, lighten(color, 8%), darken(color, 8%) or mix(color, color, 70%)";
+
+/// CSS named colors as 0xrrggbb, sorted for binary search.
+const NAMES: &[(&str, u32)] = &[
+    ("aliceblue", 0xf0f8ff), ("antiquewhite", 0xfaebd7), ("aqua", 0x00ffff),
+    ("aquamarine", 0x7fffd4), ("azure", 0xf0ffff), ("beige", 0xf5f5dc), ("bisque", 0xffe4c4),
+    ("black", 0x000000), ("blanchedalmond", 0xffebcd), ("blue", 0x0000ff),
+    ("blueviolet", 0x8a2be2), ("brown", 0xa52a2a), ("burlywood", 0xdeb887),
+    ("cadetblue", 0x5f9ea0), ("chartreuse", 0x7fff00), ("chocolate", 0xd2691e),
+    ("coral", 0xff7f50), ("cornflowerblue", 0x6495ed), ("cornsilk", 0xfff8dc),
+    ("crimson", 0xdc143c), ("cyan", 0x00ffff), ("darkblue", 0x00008b), ("darkcyan", 0x008b8b),
+    ("darkgoldenrod", 0xb8860b), ("darkgray", 0xa9a9a9), ("darkgreen", 0x006400),
+    ("darkgrey", 0xa9a9a9), ("darkkhaki", 0xbdb76b), ("darkmagenta", 0x8b008b),
+    ("darkolivegreen", 0x556b2f), ("darkorange", 0xff8c00), ("darkorchid", 0x9932cc),
+    ("darkred", 0x8b0000), ("darksalmon", 0xe9967a), ("darkseagreen", 0x8fbc8f),
+    ("darkslateblue", 0x483d8b), ("darkslategray", 0x2f4f4f), ("darkslategrey", 0x2f4f4f),
+    ("darkturquoise", 0x00ced1), ("darkviolet", 0x9400d3), ("deeppink", 0xff1493),
+    ("deepskyblue", 0x00bfff), ("dimgray", 0x696969), ("dimgrey", 0x696969),
+    ("dodgerblue", 0x1e90ff), ("firebrick", 0xb22222), ("floralwhite", 0xfffaf0),
+    ("forestgreen", 0x228b22), ("fuchsia", 0xff00ff), ("gainsboro", 0xdcdcdc),
+    ("ghostwhite", 0xf8f8ff), ("gold", 0xffd700), ("goldenrod", 0xdaa520), ("gray", 0x808080),
+    ("green", 0x008000), ("greenyellow", 0xadff2f), ("grey", 0x808080), ("honeydew", 0xf0fff0),
+    ("hotpink", 0xff69b4), ("indianred", 0xcd5c5c), ("indigo", 0x4b0082), ("ivory", 0xfffff0),
+    ("khaki", 0xf0e68c), ("lavender", 0xe6e6fa), ("lavenderblush", 0xfff0f5),
+    ("lawngreen", 0x7cfc00), ("lemonchiffon", 0xfffacd), ("lightblue", 0xadd8e6),
+    ("lightcoral", 0xf08080), ("lightcyan", 0xe0ffff), ("lightgoldenrodyellow", 0xfafad2),
+    ("lightgray", 0xd3d3d3), ("lightgreen", 0x90ee90), ("lightgrey", 0xd3d3d3),
+    ("lightpink", 0xffb6c1), ("lightsalmon", 0xffa07a), ("lightseagreen", 0x20b2aa),
+    ("lightskyblue", 0x87cefa), ("lightslategray", 0x778899), ("lightslategrey", 0x778899),
+    ("lightsteelblue", 0xb0c4de), ("lightyellow", 0xffffe0), ("lime", 0x00ff00),
+    ("limegreen", 0x32cd32), ("linen", 0xfaf0e6), ("magenta", 0xff00ff), ("maroon", 0x800000),
+    ("mediumaquamarine", 0x66cdaa), ("mediumblue", 0x0000cd), ("mediumorchid", 0xba55d3),
+    ("mediumpurple", 0x9370db), ("mediumseagreen", 0x3cb371), ("mediumslateblue", 0x7b68ee),
+    ("mediumspringgreen", 0x00fa9a), ("mediumturquoise", 0x48d1cc),
+    ("mediumvioletred", 0xc71585), ("midnightblue", 0x191970), ("mintcream", 0xf5fffa),
+    ("mistyrose", 0xffe4e1), ("moccasin", 0xffe4b5), ("navajowhite", 0xffdead),
+    ("navy", 0x000080), ("oldlace", 0xfdf5e6), ("olive", 0x808000), ("olivedrab", 0x6b8e23),
+    ("orange", 0xffa500), ("orangered", 0xff4500), ("orchid", 0xda70d6),
+    ("palegoldenrod", 0xeee8aa), ("palegreen", 0x98fb98), ("paleturquoise", 0xafeeee),
+    ("palevioletred", 0xdb7093), ("papayawhip", 0xffefd5), ("peachpuff", 0xffdab9),
+    ("peru", 0xcd853f), ("pink", 0xffc0cb), ("plum", 0xdda0dd), ("powderblue", 0xb0e0e6),
+    ("purple", 0x800080), ("rebeccapurple", 0x663399), ("red", 0xff0000),
+    ("rosybrown", 0xbc8f8f), ("royalblue", 0x4169e1), ("saddlebrown", 0x8b4513),
+    ("salmon", 0xfa8072), ("sandybrown", 0xf4a460), ("seagreen", 0x2e8b57),
+    ("seashell", 0xfff5ee), ("sienna", 0xa0522d), ("silver", 0xc0c0c0), ("skyblue", 0x87ceeb),
+    ("slateblue", 0x6a5acd), ("slategray", 0x708090), ("slategrey", 0x708090),
+    ("snow", 0xfffafa), ("springgreen", 0x00ff7f), ("steelblue", 0x4682b4), ("tan", 0xd2b48c),
+    ("teal", 0x008080), ("thistle", 0xd8bfd8), ("tomato", 0xff6347), ("turquoise", 0x40e0d0),
+    ("violet", 0xee82ee), ("wheat", 0xf5deb3), ("white", 0xffffff), ("whitesmoke", 0xf5f5f5),
+    ("yellow", 0xffff00), ("yellowgreen", 0x9acd32),
+];
+
+/// Parse a color into its red, green and blue channels and its alpha (255 =
+/// opaque). Case-insensitive; bare `rrggbb` without `#` is still accepted.
+pub fn parse(s: &str) -> Option<([u8; 3], u8)> {
+    let s = s.trim().to_ascii_lowercase();
+    if let Some(hex) = s.strip_prefix('#') {
+        return parse_hex(hex);
+    }
+    if let Some((function, args)) = s.strip_suffix(')').and_then(|s| s.split_once('(')) {
+        return parse_function(function.trim(), args);
+    }
+    if s == "transparent" {
+        return Some(([0, 0, 0], 0));
+    }
+    if let Ok(i) = NAMES.binary_search_by_key(&s.as_str(), |&(name, _)| name) {
+        let [_, r, g, b] = NAMES[i].1.to_be_bytes();
+        return Some(([r, g, b], 255));
+    }
+    match s.len() {
+        6 | 8 => parse_hex(&s),
+        _ => None,
+    }
+}
+
+/// Format channels as `#rrggbb`.
+pub fn hex([r, g, b]: [u8; 3]) -> String {
+    format!("#{:02x}{:02x}{:02x}", r, g, b)
+}
+
+/// Resolves a palette key named in an expression: None when the name isn't a
+/// key, else its color or why it has none.
+pub type KeyLookup<'a> = dyn FnMut(&str) -> Option<Result<[u8; 3], String>> + 'a;
+
+/// Evaluate a palette value: a color, another key of the palette (`key`
+/// resolves it, None meaning the name isn't a key), or `lighten(color,
+/// amount)`, `darken(color, amount)` or `mix(color, color[, weight])` over
+/// them, nested freely. `lighten`/`darken` move the HSL lightness by the
+/// amount; `mix` takes `weight` (default 50%) of the first color, as in Sass.
+/// Alpha is dropped, as drawing does.
+pub fn evaluate(expression: &str, key: &mut KeyLookup<'_>) -> Result<[u8; 3], String> {
+    let expression = expression.trim().to_ascii_lowercase();
+    if let Some((function, args)) = expression.strip_suffix(')').and_then(|s| s.split_once('(')) {
+        let function = function.trim();
+        if matches!(function, "lighten" | "darken" | "mix") {
+            let args = split_args(args);
+            return match (function, args.as_slice()) {
+                ("lighten", [color, amount]) => Ok(add_lightness(evaluate(color, key)?, amount_of(amount)?)),
+                ("darken", [color, amount]) => Ok(add_lightness(evaluate(color, key)?, -amount_of(amount)?)),
+                ("mix", [a, b]) => Ok(mix(evaluate(a, key)?, evaluate(b, key)?, 0.5)),
+                ("mix", [a, b, weight]) => Ok(mix(evaluate(a, key)?, evaluate(b, key)?, amount_of(weight)?)),
+                ("mix", _) => Err("mix takes two colors and an optional weight, like mix(bg, #0078d4, 70%)".to_string()),
+                _ => Err(format!("{} takes a color and an amount, like {}(bg, 8%)", function, function)),
+            };
+        }
+    }
+    if let Some((rgb, _)) = parse(&expression) {
+        return Ok(rgb);
+    }
+    if let Some(result) = key(&expression) {
+        return result;
+    }
+    Err(format!("'{}' is not a color or palette key (expected {}, or {})", expression, SYNTAX, EXPRESSIONS))
+}
+
+/// Split function arguments at the commas outside nested parentheses.
+fn split_args(args: &str) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let (mut depth, mut start) = (0, 0);
+    for (i, c) in args.char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' => depth -= 1,
+            ',' if depth == 0 => {
+                parts.push(args[start..i].trim());
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    parts.push(args[start..].trim());
+    parts
+}
+
+fn amount_of(s: &str) -> Result<f64, String> {
+    parse_percent(s).ok_or_else(|| format!("'{}' is not a percentage like 8%", s))
+}
+
+/// Move the HSL lightness by `delta` (a fraction), clamped to black and white.
+fn add_lightness(rgb: [u8; 3], delta: f64) -> [u8; 3] {
+    let (hue, saturation, lightness) = rgb_to_hsl(rgb);
+    hsl_to_rgb(hue, saturation, (lightness + delta).clamp(0.0, 1.0))
+}
+
+/// `weight` of `a`, the rest of `b`, channel by channel.
+pub fn mix(a: [u8; 3], b: [u8; 3], weight: f64) -> [u8; 3] {
+    [0, 1, 2].map(|i| (f64::from(a[i]) * weight + f64::from(b[i]) * (1.0 - weight)).round() as u8)
+}
+
+/// WCAG relative luminance: 0 for black, 1 for white.
+fn luminance(rgb: [u8; 3]) -> f64 {
+    let [r, g, b] = rgb.map(|c| {
+        let c = f64::from(c) / 255.0;
+        if c <= 0.04045 {
+            c / 12.92
+        } else {
+            ((c + 0.055) / 1.055).powf(2.4)
+        }
+    });
+    0.2126 * r + 0.7152 * g + 0.0722 * b
+}
+
+/// WCAG contrast ratio between two colors, from 1 (the same) to 21 (black
+/// and white). Alpha is ignored, as it is when drawing.
+pub fn contrast(a: [u8; 3], b: [u8; 3]) -> f64 {
+    let (a, b) = (luminance(a), luminance(b));
+    (a.max(b) + 0.05) / (a.min(b) + 0.05)
+}
+
+fn parse_hex(hex: &str) -> Option<([u8; 3], u8)> {
+    if !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return None;
+    }
+    let digits: Vec<u8> = match hex.len() {
+        // Each digit doubled: #f80 is #ff8800
+        3 | 4 => hex.bytes().map(|b| (b as char).to_digit(16).unwrap() as u8 * 0x11).collect(),
+        6 | 8 => (0..hex.len()).step_by(2).map(|i| u8::from_str_radix(&hex[i..i + 2], 16).unwrap()).collect(),
+        _ => return None,
+    };
+    Some(([digits[0], digits[1], digits[2]], digits.get(3).copied().unwrap_or(255)))
+}
+
+/// `rgb()`/`rgba()`/`hsl()`/`hsla()` arguments, comma-separated or in the
+/// CSS4 space-separated form with an optional `/ alpha`.
+fn parse_function(function: &str, args: &str) -> Option<([u8; 3], u8)> {
+    let (channels, slash_alpha) = match args.split_once('/') {
+        Some((channels, alpha)) => (channels, Some(alpha.trim())),
+        None => (args, None),
+    };
+    let mut parts: Vec<&str> = channels
+        .split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|part| !part.is_empty())
+        .collect();
+    let alpha = match (slash_alpha, parts.len()) {
+        (Some(alpha), 3) => parse_alpha(alpha)?,
+        (None, 4) => parse_alpha(parts.pop()?)?,
+        (None, 3) => 255,
+        _ => return None,
+    };
+    let rgb = match function {
+        "rgb" | "rgba" => [parse_channel(parts[0])?, parse_channel(parts[1])?, parse_channel(parts[2])?],
+        "hsl" | "hsla" => hsl_to_rgb(parse_hue(parts[0])?, parse_percent(parts[1])?, parse_percent(parts[2])?),
+        _ => return None,
+    };
+    Some((rgb, alpha))
+}
+
+fn parse_number(s: &str) -> Option<f64> {
+    s.parse::<f64>().ok().filter(|n| n.is_finite())
+}
+
+/// A fraction from `0%`..`100%`.
+fn parse_percent(s: &str) -> Option<f64> {
+    let percent = parse_number(s.strip_suffix('%')?)?;
+    (0.0..=100.0).contains(&percent).then_some(percent / 100.0)
+}
+
+/// An `rgb()` channel: `0`..`255` or a percentage.
+fn parse_channel(s: &str) -> Option<u8> {
+    let value = match parse_percent(s) {
+        Some(fraction) => fraction * 255.0,
+        None => parse_number(s).filter(|n| (0.0..=255.0).contains(n))?,
+    };
+    Some(value.round() as u8)
+}
+
+/// Alpha as `0`..`1` or a percentage.
+fn parse_alpha(s: &str) -> Option<u8> {
+    let fraction = match parse_percent(s) {
+        Some(fraction) => fraction,
+        None => parse_number(s).filter(|n| (0.0..=1.0).contains(n))?,
+    };
+    Some((fraction * 255.0).round() as u8)
+}
+
+/// Hue in degrees (`deg` optional), wrapped into `0..360`.
+fn parse_hue(s: &str) -> Option<f64> {
+    parse_number(s.strip_suffix("deg").unwrap_or(s)).map(|h| h.rem_euclid(360.0))
+}
+
+fn hsl_to_rgb(hue: f64, saturation: f64, lightness: f64) -> [u8; 3] {
+    let chroma = (1.0 - (2.0 * lightness - 1.0).abs()) * saturation;
+    let sector = hue / 60.0;
+    let x = chroma * (1.0 - (sector % 2.0 - 1.0).abs());
+    let (r, g, b) = match sector as u32 {
+        0 => (chroma, x, 0.0),
+        1 => (x, chroma, 0.0),
+        2 => (0.0, chroma, x),
+        3 => (0.0, x, chroma),
+        4 => (x, 0.0, chroma),
+        _ => (chroma, 0.0, x),
+    };
+    let m = lightness - chroma / 2.0;
+    [r, g, b].map(|v| ((v + m) * 255.0).round() as u8)
+}
+
+fn rgb_to_hsl(rgb: [u8; 3]) -> (f64, f64, f64) {
+    let [r, g, b] = rgb.map(|c| f64::from(c) / 255.0);
+    let (max, min) = (r.max(g).max(b), r.min(g).min(b));
+    let lightness = (max + min) / 2.0;
+    let chroma = max - min;
+    if chroma == 0.0 {
+        return (0.0, 0.0, lightness);
+    }
+    let saturation = chroma / (1.0 - (2.0 * lightness - 1.0).abs());
+    let hue = if max == r {
+        60.0 * ((g - b) / chroma).rem_euclid(6.0)
+    } else if max == g {
+        60.0 * ((b - r) / chroma + 2.0)
+    } else {
+        60.0 * ((r - g) / chroma + 4.0)
+    };
+    (hue, saturation, lightness)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{contrast, evaluate, parse, NAMES};
+
+    #[test]
+    fn hex_forms() {
+        assert_eq!(parse("#2e3440"), Some(([0x2e, 0x34, 0x40], 255)));
+        assert_eq!(parse("2E3440"), Some(([0x2e, 0x34, 0x40], 255)));
+        assert_eq!(parse("#f80"), Some(([0xff, 0x88, 0x00], 255)));
+        assert_eq!(parse("#f808"), Some(([0xff, 0x88, 0x00], 0x88)));
+        assert_eq!(parse("#2e344080"), Some(([0x2e, 0x34, 0x40], 0x80)));
+        assert_eq!(parse("#12345"), None);
+        assert_eq!(parse("#ggg"), None);
+        assert_eq!(parse("f80"), None);
+    }
+
+    #[test]
+    fn rgb_and_hsl_functions() {
+        assert_eq!(parse("rgb(46, 52, 64)"), Some(([46, 52, 64], 255)));
+        assert_eq!(parse("RGB(100%, 0%, 50%)"), Some(([255, 0, 128], 255)));
+        assert_eq!(parse("rgba(46, 52, 64, 0.5)"), Some(([46, 52, 64], 128)));
+        assert_eq!(parse("rgb(46 52 64 / 25%)"), Some(([46, 52, 64], 64)));
+        assert_eq!(parse("hsl(120, 100%, 25%)"), Some(([0, 128, 0], 255)));
+        assert_eq!(parse("hsl(210deg 50% 50% / 1)"), Some(([64, 128, 191], 255)));
+        assert_eq!(parse("hsla(-120, 100%, 50%, 0)"), Some(([0, 0, 255], 0)));
+        assert_eq!(parse("rgb(256, 0, 0)"), None);
+        assert_eq!(parse("rgb(1, 2)"), None);
+        assert_eq!(parse("hsl(0, 50, 50)"), None);
+        assert_eq!(parse("cmyk(0, 0, 0)"), None);
+    }
+
+    #[test]
+    fn css_names() {
+        assert!(NAMES.windows(2).all(|w| w[0].0 < w[1].0));
+        assert_eq!(parse("RebeccaPurple"), Some(([0x66, 0x33, 0x99], 255)));
+        assert_eq!(parse("slategrey"), parse("slategray"));
+        assert_eq!(parse("transparent"), Some(([0, 0, 0], 0)));
+        assert_eq!(parse("nope"), None);
+    }
+
+    #[test]
+    fn contrast_ratios() {
+        assert!((contrast([0, 0, 0], [255, 255, 255]) - 21.0).abs() < 1e-9);
+        assert_eq!(contrast([0x12, 0x34, 0x56], [0x12, 0x34, 0x56]), 1.0);
+        assert_eq!(contrast([255, 255, 255], [0, 0x78, 0xd4]), contrast([0, 0x78, 0xd4], [255, 255, 255]));
+        // #767676 on white is the classic just-passes-AA gray
+        assert!((contrast([0x76, 0x76, 0x76], [255, 255, 255]) - 4.54).abs() < 0.01);
+    }
+
+    #[test]
+    fn expressions_derive_colors() {
+        let mut keys = |name: &str| match name {
+            "bg" => Some(Ok([0x80, 0, 0])),
+            "fg" => Some(Ok([0, 0, 0xff])),
+            "fg_select" => Some(Err("broken".to_string())),
+            _ => None,
+        };
+        let mut eval = |s: &str| evaluate(s, &mut keys);
+        assert_eq!(eval("#2e3440"), Ok([0x2e, 0x34, 0x40]));
+        assert_eq!(eval("bg"), Ok([0x80, 0, 0]));
+        assert_eq!(eval("lighten(bg, 20%)"), Ok([0xe6, 0, 0]));
+        assert_eq!(eval("Darken(#ffffff, 100%)"), Ok([0, 0, 0]));
+        assert_eq!(eval("lighten(#2e3440, 0%)"), Ok([0x2e, 0x34, 0x40]));
+        assert_eq!(eval("mix(#ff0000, fg)"), Ok([128, 0, 128]));
+        assert_eq!(eval("mix(#ff0000, fg, 75%)"), Ok([191, 0, 64]));
+        assert_eq!(eval("darken(mix(rgb(255, 0, 0), hsl(240, 100%, 50%)), 10%)"), Ok([77, 0, 77]));
+        assert_eq!(eval("fg_select"), Err("broken".to_string()));
+        assert_eq!(eval("lighten(bg)"), Err("lighten takes a color and an amount, like lighten(bg, 8%)".to_string()));
+        assert_eq!(eval("mix(bg, fg, 1, 2)").unwrap_err(), "mix takes two colors and an optional weight, like mix(bg, #0078d4, 70%)");
+        assert_eq!(eval("lighten(bg, 8)"), Err("'8' is not a percentage like 8%".to_string()));
+        assert!(eval("lighten(nope, 8%)").unwrap_err().starts_with("'nope' is not a color or palette key (expected #rgb"));
+    }
+}
@@ -10,6 +10,8 @@ use winapi::um::synchapi::{OpenMutexW, OpenEventW, SetEvent};
 use winapi::um::handleapi::CloseHandle;
 use winapi::um::winnt::{SYNCHRONIZE, EVENT_MODIFY_STATE};
 
+use crate::menu;
+
 #[derive(Debug, Clone)]
 pub enum DaemonError {
     AlreadyRunning,
@@ -74,8 +76,11 @@ impl WindmenuDaemon {
         let current_exe = env::current_exe()
             .map_err(|e| DaemonError::StartupFailed(format!("Failed to get current executable path: {}", e)))?;
         let mut cmd = Command::new(&current_exe);
-        cmd.arg("--start-daemon-self-detached")  // <-- main reason for this
-            .creation_flags(DETACHED_PROCESS | CREATE_NEW_PROCESS_GROUP)
+        cmd.arg("--start-daemon-self-detached");  // <-- main reason for this
+        if let Some(path) = menu::config_flag() {
+            cmd.arg("--config").arg(path);
+        }
+        cmd.creation_flags(DETACHED_PROCESS | CREATE_NEW_PROCESS_GROUP)
             .stdin(Stdio::null())
             .stdout(Stdio::null())
             .stderr(Stdio::null());
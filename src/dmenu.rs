@@ -0,0 +1,247 @@
+//! `windmenu dmenu`: a dmenu-compatible one-shot mode. Reads newline-separated
+//! entries from stdin, shows the native menu, and prints the chosen line (or
+//! the Shift+Enter custom text) to stdout. Cancelling exits with status 1, as
+//! dmenu does, so existing dmenu scripts work unchanged.
+//!
+//! The menu starts from the resolved windmenu.toml (theme, font, matching),
+//! switched to dmenu's layout: a full-width bar pinned to the top, matching
+//! case-sensitively unless `-i` is given. The classic flags are single-dash
+//! and multi-letter (`-nb`, `-fn`), which clap can't express, so they arrive
+//! raw and are parsed here.
+
+use std::collections::HashMap;
+use std::io::{self, Read};
+
+use crate::menu;
+use crate::theme;
+use crate::wlines::{self, Settings};
+
+const USAGE: &str = "usage: windmenu dmenu [-bfiv] [-l lines] [-p prompt] [-fn font] [-m monitor]
+                      [-nb color] [-nf color] [-sb color] [-sf color] [-w windowid]";
+
+/// What the command line asks for once the flags are applied.
+#[derive(Debug, PartialEq)]
+enum Action {
+    Run,
+    Version,
+}
+
+/// Switch `settings` to dmenu's layout, then apply the dmenu flags on top.
+/// `-m` and `-w` are accepted for compatibility but ignored: the menu always
+/// opens on the monitor under the cursor, and there is no X window to embed in.
+fn apply_args(args: &[String], settings: &mut Settings) -> Result<Action, String> {
+    settings.horizontal = true;
+    settings.width = 0;
+    settings.center_window = false;
+    settings.case_sensitive = true;
+
+    let mut iter = args.iter();
+    while let Some(flag) = iter.next() {
+        match flag.as_str() {
+            "-b" => settings.bottom = true,
+            "-f" => {} // the window only appears once stdin is read anyway
+            "-i" => settings.case_sensitive = false,
+            "-v" => return Ok(Action::Version),
+            "-l" | "-p" | "-fn" | "-m" | "-w" | "-nb" | "-nf" | "-sb" | "-sf" => {
+                let value = iter
+                    .next()
+                    .ok_or_else(|| format!("option '{}' requires an argument", flag))?;
+                match flag.as_str() {
+                    "-l" => {
+                        let lines: usize = value
+                            .parse()
+                            .map_err(|_| format!("invalid line count '{}'", value))?;
+                        // dmenu draws a horizontal bar for -l 0
+                        settings.horizontal = lines == 0;
+                        if lines > 0 {
+                            settings.line_count = lines;
+                        }
+                    }
+                    "-p" => settings.prompt = Some(value.clone()),
+                    "-fn" => apply_font(settings, value),
+                    "-nb" => {
+                        let color = parse_color(flag, value)?;
+                        settings.bg = color;
+                        settings.bg_edit = color;
+                    }
+                    "-nf" => {
+                        let color = parse_color(flag, value)?;
+                        settings.fg = color;
+                        settings.fg_edit = color;
+                    }
+                    "-sb" => settings.bg_select = parse_color(flag, value)?,
+                    "-sf" => settings.fg_select = parse_color(flag, value)?,
+                    _ => {} // -m, -w
+                }
+            }
+            other => return Err(format!("unknown option '{}'", other)),
+        }
+    }
+    Ok(Action::Run)
+}
+
+fn parse_color(flag: &str, value: &str) -> Result<u32, String> {
+    wlines::parse_color(value).ok_or_else(|| format!("invalid color '{}' for {}", value, flag))
+}
+
+/// Apply a `-fn` font: either an Xft pattern as dmenu takes it
+/// (`monospace:size=10`, only the first of a comma-separated list) or
+/// windmenu's own "Family Size" spec, fallbacks included. Xft `size` is in
+/// points, converted to the pixel height windmenu uses; `pixelsize` is taken
+/// as-is.
+fn apply_font(settings: &mut Settings, spec: &str) {
+    let Some((family, props)) = spec.split(',').next().and_then(|first| first.split_once(':')) else {
+        theme::apply_font(settings, spec, &wlines::font_installed);
+        return;
+    };
+    let family = family.trim();
+    if !family.is_empty() {
+        settings.font_name = family.to_string();
+    }
+    for prop in props.split(':') {
+        let Some((key, value)) = prop.split_once('=') else { continue };
+        let Ok(value) = value.trim().parse::<f32>() else { continue };
+        match key.trim() {
+            "size" => settings.font_size = (value * 96.0 / 72.0).round() as i32,
+            "pixelsize" => settings.font_size = value.round() as i32,
+            _ => {}
+        }
+    }
+}
+
+/// Split stdin into entries, one per line. CRLF line endings are accepted;
+/// empty lines are kept as (empty) entries, as dmenu does.
+fn parse_entries(input: &str) -> Vec<String> {
+    input.lines().map(|line| line.trim_end_matches('\r').to_string()).collect()
+}
+
+/// Run dmenu mode with the raw arguments after `dmenu`. Returns the process
+/// exit code: 0 when something was selected, 1 on cancel or bad usage.
+pub fn run(args: &[String]) -> i32 {
+    let mut settings = menu::load_settings();
+    match apply_args(args, &mut settings) {
+        Ok(Action::Run) => {}
+        Ok(Action::Version) => {
+            println!("windmenu-{}", env!("CARGO_PKG_VERSION"));
+            return 0;
+        }
+        Err(e) => {
+            eprintln!("windmenu dmenu: {}", e);
+            eprintln!("{}", USAGE);
+            return 1;
+        }
+    }
+
+    let mut input = Vec::new();
+    if let Err(e) = io::stdin().read_to_end(&mut input) {
+        eprintln!("windmenu dmenu: failed to read stdin: {}", e);
+        return 1;
+    }
+    let entries = parse_entries(&String::from_utf8_lossy(&input));
+
+    match wlines::show(&settings, &entries, &HashMap::new(), None) {
+        Some(selection) => {
+            println!("{}", selection.text);
+            0
+        }
+        None => 1,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{apply_args, parse_entries, Action};
+    use crate::wlines::{parse_color, Settings};
+
+    fn apply(args: &[&str]) -> (Result<Action, String>, Settings) {
+        let mut settings = Settings::default();
+        let args: Vec<String> = args.iter().map(|s| s.to_string()).collect();
+        let action = apply_args(&args, &mut settings);
+        (action, settings)
+    }
+
+    #[test]
+    fn defaults_to_case_sensitive_top_bar() {
+        let (action, settings) = apply(&[]);
+        assert_eq!(action, Ok(Action::Run));
+        assert!(settings.horizontal);
+        assert!(!settings.center_window);
+        assert!(!settings.bottom);
+        assert_eq!(settings.width, 0);
+        assert!(settings.case_sensitive);
+    }
+
+    #[test]
+    fn lines_switch_to_vertical() {
+        let (_, settings) = apply(&["-l", "10"]);
+        assert!(!settings.horizontal);
+        assert_eq!(settings.line_count, 10);
+
+        let (_, settings) = apply(&["-l", "0"]);
+        assert!(settings.horizontal);
+    }
+
+    #[test]
+    fn flags_map_onto_settings() {
+        let (action, settings) = apply(&[
+            "-b", "-i", "-p", "Run:", "-nb", "#222222", "-nf", "#bbbbbb", "-sb", "#005577",
+            "-sf", "#eeeeee",
+        ]);
+        assert_eq!(action, Ok(Action::Run));
+        assert!(settings.bottom);
+        assert!(!settings.case_sensitive);
+        assert_eq!(settings.prompt.as_deref(), Some("Run:"));
+        assert_eq!(settings.bg, parse_color("#222222").unwrap());
+        assert_eq!(settings.bg_edit, parse_color("#222222").unwrap());
+        assert_eq!(settings.fg, parse_color("#bbbbbb").unwrap());
+        assert_eq!(settings.fg_edit, parse_color("#bbbbbb").unwrap());
+        assert_eq!(settings.bg_select, parse_color("#005577").unwrap());
+        assert_eq!(settings.fg_select, parse_color("#eeeeee").unwrap());
+    }
+
+    #[test]
+    fn xft_and_native_font_specs() {
+        let (_, settings) = apply(&["-fn", "monospace:size=12"]);
+        assert_eq!(settings.font_name, "monospace");
+        assert_eq!(settings.font_size, 16);
+
+        let (_, settings) = apply(&["-fn", "Terminus:pixelsize=14,emoji"]);
+        assert_eq!(settings.font_name, "Terminus");
+        assert_eq!(settings.font_size, 14);
+
+        let (_, settings) = apply(&["-fn", "Cascadia Code 18"]);
+        assert_eq!(settings.font_name, "Cascadia Code");
+        assert_eq!(settings.font_size, 18);
+
+        let (_, settings) = apply(&["-fn", "Cascadia Code Bold, Consolas 18"]);
+        assert_eq!(settings.font_name, "Cascadia Code");
+        assert_eq!(settings.font_fallbacks, ["Consolas"]);
+        assert_eq!(settings.font_weight, 700);
+    }
+
+    #[test]
+    fn ignored_flags_still_consume_their_argument() {
+        let (action, settings) = apply(&["-m", "1", "-w", "0x123", "-f", "-p", "x"]);
+        assert_eq!(action, Ok(Action::Run));
+        assert_eq!(settings.prompt.as_deref(), Some("x"));
+    }
+
+    #[test]
+    fn bad_usage_is_an_error() {
+        assert!(apply(&["-x"]).0.is_err());
+        assert!(apply(&["-l"]).0.is_err());
+        assert!(apply(&["-l", "many"]).0.is_err());
+        assert!(apply(&["-nb", "red"]).0.is_err());
+    }
+
+    #[test]
+    fn version_flag() {
+        assert_eq!(apply(&["-v"]).0, Ok(Action::Version));
+    }
+
+    #[test]
+    fn entries_split_on_lf_and_crlf() {
+        assert_eq!(parse_entries("a\r\nb\n\nc"), vec!["a", "b", "", "c"]);
+        assert!(parse_entries("").is_empty());
+    }
+}
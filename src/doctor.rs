@@ -1,6 +1,6 @@
 //! `windmenu doctor`: one-shot diagnostics of everything windmenu can see —
-//! config resolution, binary location/PATH, daemon state, and auto-start
-//! method. Consolidates the former `status` and `config path` commands.
+//! config resolution, the Windows app mode, binary location/PATH, daemon
+//! state, and auto-start method. Consolidates the former `status` and `config path` commands.
 //!
 //! House style matches the other CLI commands: plain `println!`, no color,
 //! "enabled"/"not set" and "yes"/"no", full paths via `.display()`.
@@ -9,6 +9,7 @@ use std::env;
 use std::path::PathBuf;
 
 // use crate::apps; // re-enable with the Windows Store app count at the end of run()
+use crate::appmode::{self, AppMode, AppModeSource};
 use crate::daemon::WindmenuDaemon;
 use crate::menu;
 
@@ -16,10 +17,18 @@ use crate::menu;
 /// (`autostart/autostart.ps1`). Probed read-only.
 const RUN_VALUE_NAME: &str = "WindmenuDaemon";
 const RUN_SUBKEY: &str = "Software\\Microsoft\\Windows\\CurrentVersion\\Run";
+/// Where Windows keeps the app mode `theme = "auto"` follows.
+const PERSONALIZE_SUBKEY: &str = "Software\\Microsoft\\Windows\\CurrentVersion\\Themes\\Personalize";
+const LIGHT_THEME_VALUE: &str = "AppsUseLightTheme";
 
 pub fn run(daemon: &WindmenuDaemon) {
     println!("Config");
     menu::config_diagnostics();
+    match (Registry.apps_use_light_theme(), appmode::app_mode(&Registry)) {
+        (None, _) => println!("App mode: light ({} not set)", LIGHT_THEME_VALUE),
+        (Some(_), AppMode::Light) => println!("App mode: light"),
+        (Some(_), AppMode::Dark) => println!("App mode: dark"),
+    }
     println!();
 
     println!("Binary");
@@ -116,12 +125,41 @@ fn startup_shortcut_path() -> Option<PathBuf> {
 }
 
 /// Read-only probe of `HKCU\...\Run\WindmenuDaemon`. Returns the value's string
-/// data if the key/value exists, `None` otherwise. `KEY_READ` only — no writes,
-/// so this does not trip AV heuristics.
+/// data if the key/value exists, `None` otherwise.
 fn read_run_key() -> Option<String> {
+    use winapi::um::winnt::REG_SZ;
+
+    let (value_type, data) = read_value(RUN_SUBKEY, RUN_VALUE_NAME)?;
+    if value_type != REG_SZ || data.is_empty() {
+        return None;
+    }
+    // The data is UTF-16; trim the trailing NUL(s) REG_SZ includes.
+    let wide: Vec<u16> = data.chunks_exact(2).map(|c| u16::from_le_bytes([c[0], c[1]])).collect();
+    let end = wide.iter().position(|&c| c == 0).unwrap_or(wide.len());
+    Some(String::from_utf16_lossy(&wide[..end]))
+}
+
+/// The app mode as Windows stores it, probed read-only like the Run key.
+pub struct Registry;
+
+impl AppModeSource for Registry {
+    fn apps_use_light_theme(&self) -> Option<u32> {
+        use winapi::um::winnt::REG_DWORD;
+
+        match read_value(PERSONALIZE_SUBKEY, LIGHT_THEME_VALUE)? {
+            (REG_DWORD, data) if data.len() == 4 => Some(u32::from_le_bytes([data[0], data[1], data[2], data[3]])),
+            _ => None,
+        }
+    }
+}
+
+/// Type and raw data of a value under `HKCU\<subkey>`, `None` if the key or
+/// value doesn't exist. `KEY_READ` only — no writes, so this does not trip AV
+/// heuristics.
+fn read_value(subkey: &str, name: &str) -> Option<(u32, Vec<u8>)> {
     use std::os::windows::ffi::OsStrExt;
     use winapi::shared::winerror::ERROR_SUCCESS;
-    use winapi::um::winnt::{KEY_READ, REG_SZ, WCHAR};
+    use winapi::um::winnt::KEY_READ;
     use winapi::um::winreg::{RegCloseKey, RegOpenKeyExW, RegQueryValueExW, HKEY_CURRENT_USER};
 
     fn wide(s: &str) -> Vec<u16> {
@@ -131,8 +169,8 @@ fn read_run_key() -> Option<String> {
             .collect()
     }
 
-    let subkey = wide(RUN_SUBKEY);
-    let value_name = wide(RUN_VALUE_NAME);
+    let subkey = wide(subkey);
+    let value_name = wide(name);
 
     unsafe {
         let mut hkey = std::ptr::null_mut();
@@ -153,29 +191,25 @@ fn read_run_key() -> Option<String> {
             std::ptr::null_mut(),
             &mut data_len,
         );
-        if status as u32 != ERROR_SUCCESS || value_type != REG_SZ || data_len == 0 {
+        if status as u32 != ERROR_SUCCESS {
             RegCloseKey(hkey);
             return None;
         }
 
-        // data_len is in bytes; read into a WCHAR buffer.
-        let wchar_len = (data_len as usize).div_ceil(std::mem::size_of::<WCHAR>());
-        let mut buf: Vec<WCHAR> = vec![0; wchar_len];
+        let mut buf: Vec<u8> = vec![0; data_len as usize];
         let status = RegQueryValueExW(
             hkey,
             value_name.as_ptr(),
             std::ptr::null_mut(),
             std::ptr::null_mut(),
-            buf.as_mut_ptr() as *mut u8,
+            buf.as_mut_ptr(),
             &mut data_len,
         );
         RegCloseKey(hkey);
         if status as u32 != ERROR_SUCCESS {
             return None;
         }
-
-        // Trim the trailing NUL(s) REG_SZ includes.
-        let end = buf.iter().position(|&c| c == 0).unwrap_or(buf.len());
-        Some(String::from_utf16_lossy(&buf[..end]))
+        buf.truncate(data_len as usize);
+        Some((value_type, buf))
     }
 }
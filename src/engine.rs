@@ -0,0 +1,1655 @@
+//! Platform-neutral menu engine: filtering, selection, paging, Tab completion
+//! and the final result, driven by abstract input events. `wlines` is the GDI
+//! frontend: it translates window messages into `Input`s, reacts to the
+//! returned `Outcome`, and asks for the visible rows when painting, keeping
+//! only pixels and the native EDIT control for itself. Nothing here touches a
+//! window, so menu behavior can be tested by scripting key events.
+//!
+//! Filtering can run on a worker thread (`spawn_worker`): each query change
+//! supersedes the job in flight, and the frontend collects finished results
+//! with `poll` when the worker notifies it.
+
+use std::collections::HashMap;
+use std::ops::Range;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{mpsc, Arc};
+use std::thread;
+
+use unicode_normalization::char::{decompose_compatible, is_combining_mark};
+
+/// Windows virtual-key codes for the keys the engine understands. Frontends
+/// pass raw key codes through `Input::Key`, so navigation combos configured
+/// as VK codes match without translation.
+pub mod vk {
+    pub const TAB: u16 = 0x09;
+    pub const RETURN: u16 = 0x0D;
+    pub const ESCAPE: u16 = 0x1B;
+    pub const PRIOR: u16 = 0x21; // Page Up
+    pub const NEXT: u16 = 0x22; // Page Down
+    pub const END: u16 = 0x23;
+    pub const HOME: u16 = 0x24;
+    pub const LEFT: u16 = 0x25;
+    pub const UP: u16 = 0x26;
+    pub const RIGHT: u16 = 0x27;
+    pub const DOWN: u16 = 0x28;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FilterMode {
+    Complete,
+    Keywords,
+    Fuzzy,
+}
+
+impl FilterMode {
+    pub fn parse(s: &str) -> FilterMode {
+        match s.to_ascii_lowercase().as_str() {
+            "complete" | "0" => FilterMode::Complete,
+            "keywords" | "1" => FilterMode::Keywords,
+            "fuzzy" | "2" => FilterMode::Fuzzy,
+            _ => FilterMode::Keywords,
+        }
+    }
+
+    /// The config name of the mode, as `parse` reads it.
+    pub fn name(self) -> &'static str {
+        match self {
+            FilterMode::Complete => "complete",
+            FilterMode::Keywords => "keywords",
+            FilterMode::Fuzzy => "fuzzy",
+        }
+    }
+}
+
+/// A navigation keybinding: Ctrl/Shift modifiers plus a virtual-key code.
+/// Alt/Win are intentionally unsupported — those keydowns arrive as WM_SYSKEYDOWN,
+/// which the edit proc does not handle.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct KeyCombo {
+    pub ctrl: bool,
+    pub shift: bool,
+    pub vk: u16,
+}
+
+impl KeyCombo {
+    /// True if this combo is satisfied by the current key + modifier state.
+    fn matches(&self, vk: u16, mods: Mods) -> bool {
+        self.vk == vk && self.ctrl == mods.ctrl && self.shift == mods.shift
+    }
+
+    /// The control character the EDIT control would insert for a Ctrl+<letter>
+    /// combo, so WM_CHAR can swallow it (Ctrl+A=1 .. Ctrl+Z=26; e.g. Ctrl+J=0x0A,
+    /// Ctrl+K=0x0B). None when this isn't a Ctrl+letter combo.
+    pub fn char_to_swallow(&self) -> Option<u32> {
+        if self.ctrl && (0x41..=0x5A).contains(&self.vk) {
+            Some((self.vk & 0x1F) as u32)
+        } else {
+            None
+        }
+    }
+}
+
+/// Modifier state accompanying a key press.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Mods {
+    pub ctrl: bool,
+    pub shift: bool,
+}
+
+/// An abstract input event.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(not(test), allow(dead_code))] // Char/Backspace: the GDI frontend edits natively
+pub enum Input {
+    /// A typed character, inserted at the caret (replacing any selected text).
+    /// Frontends with a native input box sync it through `set_query` instead.
+    Char(char),
+    /// Delete the selected text, or the character before the caret; like
+    /// `Char`, only for frontends without a native input box.
+    Backspace,
+    /// A key press, by virtual-key code (see `vk`).
+    Key(u16, Mods),
+    /// A click on result `idx` (the frontend does the hit-testing).
+    Click(usize),
+    /// Mouse wheel, in notches; positive scrolls up.
+    Wheel(i32),
+}
+
+/// What the frontend should do after `Engine::handle`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Outcome {
+    /// Not consumed: a native input box should process the event itself.
+    Ignored,
+    /// Consumed; the rows may have changed, so repaint.
+    Redraw,
+    /// Consumed and the query was replaced (Tab completion): copy `query()`
+    /// into the input box with the caret at the end, then repaint.
+    SetQuery,
+    /// The menu is finished; `take_result` has the selection, if any.
+    Done,
+}
+
+/// How results are split into pages.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Paging {
+    /// Fixed pages of this many rows (vertical mode).
+    Rows(usize),
+    /// Pages packed greedily into `avail` pixels, from each entry's cell
+    /// width (horizontal mode). `widths` is indexed by entry, not by result.
+    Cells { avail: i32, widths: Vec<i32> },
+}
+
+/// The behavior-related subset of the renderer settings.
+#[derive(Debug, Clone)]
+pub struct Behavior {
+    pub filter_mode: FilterMode,
+    pub case_sensitive: bool,
+    pub normalize: bool, // Match accent- and width-insensitively (see `fold`)
+    pub next: KeyCombo, // Move selection down
+    pub prev: KeyCombo, // Move selection up
+}
+
+/// Learned preferences for a query: entry text -> weight, from past picks
+/// made with the same or a shorter query (see `History::query_weights`).
+pub type Adaptive = Box<dyn Fn(&str) -> HashMap<String, f64>>;
+
+/// Ranking points for a learned weight. Logarithmic, so the first few picks
+/// count most and a long-standing habit can't bury a much better match: one
+/// pick is worth about a word-boundary bonus, ten picks about five chars.
+fn adaptive_bonus(weight: f64) -> i32 {
+    const ADAPTIVE_SCALE: f64 = 32.0;
+    (ADAPTIVE_SCALE * (1.0 + weight).ln()).round() as i32
+}
+
+/// A result row on the current page, for drawing.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Row<'a> {
+    pub index: usize, // into the results
+    pub entry: usize, // into the entries the engine was built from
+    pub text: &'a str,
+    pub selected: bool,
+    pub matches: Vec<usize>, // char indices of `text` the query matched, ascending
+}
+
+/// A string in the form the matchers compare: folded when normalizing, plus
+/// its lowercase for case-insensitive substring checks.
+#[derive(Clone)]
+struct Term {
+    text: String,
+    lower: String,
+}
+
+impl Term {
+    fn new(text: &str, normalize: bool) -> Term {
+        let text = if normalize { fold(text) } else { text.to_string() };
+        Term { lower: text.to_lowercase(), text }
+    }
+}
+
+#[derive(Clone)]
+struct Entry {
+    text: String,
+    search: Term,
+    aliases: Vec<Term>, // matched, never shown
+}
+
+pub struct Engine {
+    behavior: Behavior,
+    paging: Paging,
+    entries: Arc<Vec<Entry>>, // shared with the worker
+    adaptive: Option<Adaptive>,
+    worker: Option<Worker>,
+
+    query: String,
+    caret: usize,  // in chars
+    anchor: usize, // other end of the selected text; == caret when none
+
+    results: Vec<usize>,     // indices into `entries`
+    results_needle: String,  // the needle `results` were filtered for
+    selected: Option<usize>, // index into `results`
+    generation: u64,         // bumped per filtering job
+    pending: Option<u64>,    // job the worker is running for the current query
+
+    done: bool,
+    result: Option<String>,
+}
+
+/// Greedily pack cell widths into pages of at most `avail` width, returning
+/// the first index of each page. Every page holds at least one cell, so a
+/// cell wider than `avail` gets a page of its own (drawn ellipsized).
+fn pack_pages(widths: &[i32], avail: i32) -> Vec<usize> {
+    let mut starts = Vec::new();
+    let mut used = 0;
+    for (i, &w) in widths.iter().enumerate() {
+        if starts.is_empty() || used + w > avail {
+            starts.push(i);
+            used = 0;
+        }
+        used += w;
+    }
+    starts
+}
+
+impl Engine {
+    /// All entries start visible, with the first one selected.
+    pub fn new(entries: &[String], behavior: Behavior) -> Engine {
+        let entries: Vec<Entry> = entries
+            .iter()
+            .map(|text| Entry {
+                text: text.clone(),
+                search: Term::new(text, behavior.normalize),
+                aliases: Vec::new(),
+            })
+            .collect();
+        let results: Vec<usize> = (0..entries.len()).collect();
+        let selected = if results.is_empty() { None } else { Some(0) };
+        Engine {
+            behavior,
+            paging: Paging::Rows(entries.len()),
+            entries: Arc::new(entries),
+            adaptive: None,
+            worker: None,
+            query: String::new(),
+            caret: 0,
+            anchor: 0,
+            results,
+            results_needle: String::new(),
+            selected,
+            generation: 0,
+            pending: None,
+            done: false,
+            result: None,
+        }
+    }
+
+    pub fn set_paging(&mut self, paging: Paging) {
+        self.paging = paging;
+    }
+
+    /// Hidden search terms per entry text (aliases, keywords). They match
+    /// like the text itself but are never displayed. Set before typing starts.
+    pub fn set_aliases(&mut self, aliases: &HashMap<String, Vec<String>>) {
+        let normalize = self.behavior.normalize;
+        for entry in Arc::make_mut(&mut self.entries) {
+            if let Some(terms) = aliases.get(&entry.text) {
+                entry.aliases = terms.iter().map(|alias| Term::new(alias, normalize)).collect();
+            }
+        }
+    }
+
+    /// Boost entries picked before for the typed query, on top of the
+    /// match ranking. Takes effect from the next query change.
+    pub fn set_adaptive(&mut self, adaptive: Adaptive) {
+        self.adaptive = Some(adaptive);
+    }
+
+    /// Feed one input event. Events after the menu is done are ignored.
+    pub fn handle(&mut self, input: Input) -> Outcome {
+        if self.done {
+            return Outcome::Ignored;
+        }
+        match input {
+            Input::Char(c) => {
+                let (start, end) = self.selection_bounds();
+                let mut query: Vec<char> = self.query.chars().collect();
+                query.splice(start..end, [c]);
+                self.replace_query(query.into_iter().collect(), start + 1);
+                Outcome::Redraw
+            }
+            Input::Backspace => {
+                let (mut start, end) = self.selection_bounds();
+                if start == end {
+                    if start == 0 {
+                        return Outcome::Redraw;
+                    }
+                    start -= 1;
+                }
+                let mut query: Vec<char> = self.query.chars().collect();
+                query.drain(start..end);
+                self.replace_query(query.into_iter().collect(), start);
+                Outcome::Redraw
+            }
+            Input::Key(vk, mods) => self.key(vk, mods),
+            Input::Click(idx) => {
+                if idx >= self.results.len() {
+                    return Outcome::Ignored;
+                }
+                if self.selected == Some(idx) {
+                    // Second click on the same entry - select it
+                    let text = self.selected_text().map(str::to_string);
+                    self.finish(text);
+                    Outcome::Done
+                } else {
+                    self.select(idx);
+                    Outcome::Redraw
+                }
+            }
+            Input::Wheel(notches) => {
+                self.move_selection(-(notches as isize));
+                Outcome::Redraw
+            }
+        }
+    }
+
+    fn key(&mut self, vk: u16, mods: Mods) -> Outcome {
+        // Configurable next/prev navigation combos take precedence over the
+        // hardcoded keys below (arrows/Home/End/PageUp/PageDown stay fixed).
+        if self.behavior.next.matches(vk, mods) {
+            self.move_selection(1);
+            return Outcome::Redraw;
+        }
+        if self.behavior.prev.matches(vk, mods) {
+            self.move_selection(-1);
+            return Outcome::Redraw;
+        }
+
+        let plain = !mods.ctrl && !mods.shift;
+        let len = self.query.chars().count();
+        match vk {
+            vk::RETURN => {
+                self.settle();
+                // If no results or shift is held: return input, else: return selection
+                let result = match self.selected_text() {
+                    Some(text) if !mods.shift => text.to_string(),
+                    _ => self.query.clone(),
+                };
+                self.finish(Some(result));
+                Outcome::Done
+            }
+            vk::ESCAPE => {
+                self.finish(None);
+                Outcome::Done
+            }
+            vk::TAB => {
+                self.settle();
+                // Autocomplete with the selected entry
+                match self.selected_text() {
+                    Some(text) => {
+                        let text = text.to_string();
+                        let end = text.chars().count();
+                        self.replace_query(text, end);
+                        Outcome::SetQuery
+                    }
+                    None => Outcome::Redraw,
+                }
+            }
+            vk::UP => {
+                self.move_selection(-1);
+                Outcome::Redraw
+            }
+            vk::DOWN => {
+                self.move_selection(1);
+                Outcome::Redraw
+            }
+            // Edge-triggered navigation (dmenu's rule): an unmodified
+            // Left/Right moves the selection once the caret can't travel
+            // any further in that direction, and moves the caret otherwise.
+            // Ctrl/Shift+arrows stay pure text operations.
+            vk::LEFT if plain && (self.caret, self.anchor) == (0, 0) => {
+                self.move_selection(-1);
+                Outcome::Redraw
+            }
+            vk::RIGHT if plain && (self.caret, self.anchor) == (len, len) => {
+                self.move_selection(1);
+                Outcome::Redraw
+            }
+            vk::LEFT if plain => {
+                let (start, end) = self.selection_bounds();
+                self.caret = if start == end { start - 1 } else { start };
+                self.anchor = self.caret;
+                Outcome::Ignored
+            }
+            vk::RIGHT if plain => {
+                let (start, end) = self.selection_bounds();
+                self.caret = if start == end { end + 1 } else { end };
+                self.anchor = self.caret;
+                Outcome::Ignored
+            }
+            vk::HOME => {
+                self.select(0);
+                Outcome::Redraw
+            }
+            vk::END => {
+                if let Some(last) = self.results.len().checked_sub(1) {
+                    self.select(last);
+                }
+                Outcome::Redraw
+            }
+            vk::PRIOR => {
+                // Page Up - Start of the previous page
+                if let Some(page) = self.selected_page() {
+                    let starts = self.page_starts();
+                    self.select(starts[page.saturating_sub(1)]);
+                }
+                Outcome::Redraw
+            }
+            vk::NEXT => {
+                // Page Down - Start of the next page (or the last entry)
+                if let Some(page) = self.selected_page() {
+                    let target = self
+                        .page_starts()
+                        .get(page + 1)
+                        .copied()
+                        .unwrap_or(self.results.len() - 1);
+                    self.select(target);
+                }
+                Outcome::Redraw
+            }
+            _ => Outcome::Ignored,
+        }
+    }
+
+    /// Sync the query from a native input box, with the caret and the other
+    /// end of its text selection in chars. Results are recomputed only when
+    /// the text actually changed, so a caret-only sync keeps the selection.
+    pub fn set_query(&mut self, query: &str, caret: usize, anchor: usize) {
+        if query != self.query {
+            self.query = query.to_string();
+            self.update_results();
+        }
+        let len = self.query.chars().count();
+        self.caret = caret.min(len);
+        self.anchor = anchor.min(len);
+    }
+
+    /// End the menu without a selection (focus loss, window closed).
+    pub fn cancel(&mut self) {
+        self.finish(None);
+    }
+
+    pub fn query(&self) -> &str {
+        &self.query
+    }
+
+    pub fn results(&self) -> &[usize] {
+        &self.results
+    }
+
+    pub fn selected_text(&self) -> Option<&str> {
+        let sel = self.selected?;
+        Some(&self.entries[self.results[sel]].text)
+    }
+
+    /// The selection (or custom text) once done; None when cancelled.
+    pub fn take_result(&mut self) -> Option<String> {
+        self.result.take()
+    }
+
+    /// First result index of each page. Empty when nothing can be shown.
+    pub fn page_starts(&self) -> Vec<usize> {
+        let count = self.results.len();
+        if count == 0 {
+            return Vec::new();
+        }
+        match &self.paging {
+            Paging::Rows(0) => Vec::new(),
+            Paging::Rows(rows) => (0..count).step_by(*rows).collect(),
+            Paging::Cells { avail, widths } => {
+                let cells: Vec<i32> = self.results.iter().map(|&i| widths[i]).collect();
+                pack_pages(&cells, *avail)
+            }
+        }
+    }
+
+    /// The page of results containing the selection.
+    pub fn visible_range(&self) -> Range<usize> {
+        let starts = self.page_starts();
+        if starts.is_empty() {
+            return 0..0;
+        }
+        let sel = self.selected.unwrap_or(0);
+        let page = starts.partition_point(|&s| s <= sel) - 1;
+        let end = starts.get(page + 1).copied().unwrap_or(self.results.len());
+        starts[page]..end
+    }
+
+    /// The rows on the current page, in display order.
+    pub fn visible_rows(&self) -> Vec<Row<'_>> {
+        self.visible_range()
+            .map(|index| {
+                let entry = self.results[index];
+                let text = &self.entries[entry].text;
+                Row {
+                    index,
+                    entry,
+                    text,
+                    selected: self.selected == Some(index),
+                    matches: self.match_positions(text),
+                }
+            })
+            .collect()
+    }
+
+    /// Char indices of `text` matched by the query, for highlighting. Empty
+    /// when the entry only matched through an alias.
+    fn match_positions(&self, text: &str) -> Vec<usize> {
+        if self.query.is_empty() {
+            return Vec::new();
+        }
+        if !self.behavior.normalize {
+            return self.folded_positions(&self.query, text);
+        }
+        // Match folded forms, then map back: a ligature covers several
+        // folded chars, a dropped accent none
+        let (folded, origins) = fold_with_origins(text);
+        let mut positions: Vec<usize> = self
+            .folded_positions(&fold(&self.query), &folded)
+            .into_iter()
+            .map(|i| origins[i])
+            .collect();
+        positions.dedup();
+        positions
+    }
+
+    /// Char indices of `text` matched by `query`, both already in the form
+    /// the filter compared.
+    fn folded_positions(&self, query: &str, text: &str) -> Vec<usize> {
+        let case_sensitive = self.behavior.case_sensitive;
+        match self.behavior.filter_mode {
+            FilterMode::Complete => substring_positions(query, text, case_sensitive),
+            FilterMode::Keywords => {
+                let mut positions: Vec<usize> = query
+                    .split(' ')
+                    .flat_map(|word| substring_positions(word, text, case_sensitive))
+                    .collect();
+                positions.sort_unstable();
+                positions.dedup();
+                positions
+            }
+            FilterMode::Fuzzy => fuzzy_positions(query, text, case_sensitive),
+        }
+    }
+
+    /// Select result `idx`, clamped to the last result.
+    pub fn select(&mut self, idx: usize) {
+        if let Some(last) = self.results.len().checked_sub(1) {
+            self.selected = Some(idx.min(last));
+        }
+    }
+
+    /// Move the selection by `delta` (wrapping), matching the C modulo behavior.
+    fn move_selection(&mut self, delta: isize) {
+        let count = self.results.len();
+        if count == 0 {
+            return;
+        }
+        let cur = self.selected.unwrap_or(0) as isize;
+        self.selected = Some((cur + delta).rem_euclid(count as isize) as usize);
+    }
+
+    /// Page index holding the selection, if there is one.
+    fn selected_page(&self) -> Option<usize> {
+        let sel = self.selected?;
+        let starts = self.page_starts();
+        if starts.is_empty() {
+            return None;
+        }
+        Some(starts.partition_point(|&s| s <= sel) - 1)
+    }
+
+    /// Ordered (start, end) of the text selection, in chars.
+    fn selection_bounds(&self) -> (usize, usize) {
+        (self.caret.min(self.anchor), self.caret.max(self.anchor))
+    }
+
+    fn replace_query(&mut self, query: String, caret: usize) {
+        self.query = query;
+        self.caret = caret;
+        self.anchor = caret;
+        self.update_results();
+    }
+
+    fn finish(&mut self, result: Option<String>) {
+        if !self.done {
+            self.done = true;
+            self.result = result;
+        }
+    }
+
+    /// Recompute the results for the current query: right away, or on the
+    /// worker when one is running (`poll` picks the results up).
+    fn update_results(&mut self) {
+        let job = self.job();
+        match &self.worker {
+            Some(worker) => {
+                self.pending = Some(job.generation);
+                let _ = worker.jobs.send(job);
+            }
+            None => self.run_here(job),
+        }
+    }
+
+    /// Describe filtering for the current query. When it extends the query
+    /// the current results were filtered for, only those need re-checking:
+    /// every mode's matches for the longer query are a subset.
+    fn job(&mut self) -> Job {
+        let weights = match &self.adaptive {
+            Some(adaptive) if !self.query.is_empty() => adaptive(&self.query),
+            _ => HashMap::new(),
+        };
+        let needle = if self.behavior.normalize { fold(&self.query) } else { self.query.clone() };
+        let candidates = if needle.starts_with(self.results_needle.as_str()) {
+            // Back to entry order, which the substring modes rank by
+            let mut candidates = self.results.clone();
+            candidates.sort_unstable();
+            candidates
+        } else {
+            (0..self.entries.len()).collect()
+        };
+        self.generation += 1;
+        if let Some(worker) = &self.worker {
+            // Supersedes whatever the worker is busy with
+            worker.latest.store(self.generation, Ordering::Relaxed);
+        }
+        Job {
+            generation: self.generation,
+            needle,
+            filter_mode: self.behavior.filter_mode,
+            case_sensitive: self.behavior.case_sensitive,
+            candidates,
+            weights,
+        }
+    }
+
+    fn run_here(&mut self, mut job: Job) {
+        let results = filter(&self.entries, &mut job, &|| false).unwrap_or_default();
+        self.apply_results(job.needle, results);
+    }
+
+    fn apply_results(&mut self, needle: String, results: Vec<usize>) {
+        self.results = results;
+        self.results_needle = needle;
+        self.selected = if self.results.is_empty() { None } else { Some(0) };
+    }
+
+    /// Finish pending filtering on this thread, so Enter and Tab act on the
+    /// results for what is typed rather than on the ones still shown.
+    fn settle(&mut self) {
+        if self.pending.take().is_some() {
+            let job = self.job();
+            self.run_here(job);
+        }
+    }
+
+    /// Filter on a background thread from now on, keeping the frontend
+    /// responsive with very large entry lists. `notify` runs on the worker
+    /// whenever results are ready; the frontend should then call `poll` on
+    /// its own thread. Set aliases before starting the worker.
+    pub fn spawn_worker(&mut self, notify: impl Fn() + Send + 'static) {
+        let (jobs, job_rx) = mpsc::channel::<Job>();
+        let (done_tx, done) = mpsc::channel();
+        let latest = Arc::new(AtomicU64::new(self.generation));
+        let entries = Arc::clone(&self.entries);
+        let current = Arc::clone(&latest);
+        thread::spawn(move || {
+            while let Ok(mut job) = job_rx.recv() {
+                // Only the newest queued query matters
+                while let Ok(newer) = job_rx.try_recv() {
+                    job = newer;
+                }
+                let generation = job.generation;
+                let cancelled = || current.load(Ordering::Relaxed) != generation;
+                if let Some(results) = filter(&entries, &mut job, &cancelled) {
+                    if done_tx.send((job.generation, job.needle, results)).is_err() {
+                        break;
+                    }
+                    notify();
+                }
+            }
+        });
+        self.worker = Some(Worker { jobs, done, latest });
+    }
+
+    /// Take up results the worker finished. Returns whether they were for
+    /// the current query, i.e. the rows changed and need a repaint.
+    pub fn poll(&mut self) -> bool {
+        let Some(worker) = &self.worker else { return false };
+        let mut finished = None;
+        while let Ok((generation, needle, results)) = worker.done.try_recv() {
+            if self.pending == Some(generation) {
+                finished = Some((needle, results));
+            }
+        }
+        match finished {
+            Some((needle, results)) => {
+                self.pending = None;
+                self.apply_results(needle, results);
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+/// Filtering work for one query, self-contained so it can run on the worker.
+struct Job {
+    generation: u64,
+    needle: String, // the query as the matchers compare it (folded if normalizing)
+    filter_mode: FilterMode,
+    case_sensitive: bool,
+    candidates: Vec<usize>, // entries to check, in entry order
+    weights: HashMap<String, f64>,
+}
+
+/// Background filtering thread handle. Dropping it cancels the running job
+/// and lets the thread exit.
+struct Worker {
+    jobs: mpsc::Sender<Job>,
+    done: mpsc::Receiver<(u64, String, Vec<usize>)>,
+    latest: Arc<AtomicU64>, // generation of the newest job; others abort
+}
+
+impl Drop for Worker {
+    fn drop(&mut self) {
+        self.latest.store(u64::MAX, Ordering::Relaxed);
+    }
+}
+
+/// Entries checked between looks at the cancellation flag.
+const CANCEL_CHECK_INTERVAL: usize = 1024;
+
+/// Run `job` over `entries`: the matching candidates in ranked order, or None
+/// when `cancelled` turned true partway through.
+fn filter(entries: &[Entry], job: &mut Job, cancelled: &dyn Fn() -> bool) -> Option<Vec<usize>> {
+    let mut results = std::mem::take(&mut job.candidates);
+    let bonus = |entry: &Entry| job.weights.get(&entry.text).map_or(0, |&w| adaptive_bonus(w));
+    match job.filter_mode {
+        FilterMode::Complete => {
+            results = filter_reduce(entries, results, &job.needle, job.case_sensitive, cancelled)?;
+        }
+        FilterMode::Keywords => {
+            for word in job.needle.split(' ') {
+                results = filter_reduce(entries, results, word, job.case_sensitive, cancelled)?;
+            }
+        }
+        FilterMode::Fuzzy => {
+            results = filter_fuzzy(entries, results, &job.needle, job.case_sensitive, bonus, cancelled)?;
+        }
+    }
+    if job.filter_mode != FilterMode::Fuzzy && !job.weights.is_empty() {
+        // Substring modes have no score to blend with: learned picks
+        // move up, everything else keeps its order
+        results.sort_by_key(|&i| std::cmp::Reverse(bonus(&entries[i])));
+    }
+    Some(results)
+}
+
+fn filter_reduce(
+    entries: &[Entry],
+    candidates: Vec<usize>,
+    needle: &str,
+    case_sensitive: bool,
+    cancelled: &dyn Fn() -> bool,
+) -> Option<Vec<usize>> {
+    if needle.is_empty() {
+        return Some(candidates);
+    }
+    let lower = needle.to_lowercase();
+    let hit = |term: &Term| {
+        if case_sensitive {
+            term.text.contains(needle)
+        } else {
+            term.lower.contains(&lower)
+        }
+    };
+    let mut kept = Vec::new();
+    for chunk in candidates.chunks(CANCEL_CHECK_INTERVAL) {
+        if cancelled() {
+            return None;
+        }
+        kept.extend(chunk.iter().copied().filter(|&i| {
+            let entry = &entries[i];
+            std::iter::once(&entry.search).chain(&entry.aliases).any(hit)
+        }));
+    }
+    Some(kept)
+}
+
+/// Keep the fuzzy matches, ranked by match score plus `bonus`. An entry
+/// scores its best match across the text and its aliases, so a hit on a
+/// short alias ranks like a strong match on the name.
+fn filter_fuzzy(
+    entries: &[Entry],
+    candidates: Vec<usize>,
+    needle: &str,
+    case_sensitive: bool,
+    bonus: impl Fn(&Entry) -> i32,
+    cancelled: &dyn Fn() -> bool,
+) -> Option<Vec<usize>> {
+    if needle.is_empty() {
+        return Some(candidates);
+    }
+    let needle: Vec<char> = needle.chars().collect();
+    let mut scorer = Scorer::default();
+    let mut scored: Vec<(i32, usize)> = Vec::new();
+    for chunk in candidates.chunks(CANCEL_CHECK_INTERVAL) {
+        if cancelled() {
+            return None;
+        }
+        scored.extend(chunk.iter().filter_map(|&i| {
+            let entry = &entries[i];
+            let score = std::iter::once(&entry.search)
+                .chain(&entry.aliases)
+                .filter_map(|term| scorer.score(&needle, &term.text, case_sensitive))
+                .max()?;
+            Some((score + bonus(entry), i))
+        }));
+    }
+    // Descending by score, original entry order as tie-break
+    scored.sort_by(|a, b| b.0.cmp(&a.0).then(a.1.cmp(&b.1)));
+    Some(scored.into_iter().map(|(_, i)| i).collect())
+}
+
+/// Fold `s` for accent- and width-insensitive matching: compatibility
+/// decomposition (NFKD) with combining marks dropped, so `Café` and
+/// `Cafe\u{301}` become `Cafe`, full-width `ＡＢＣ` becomes `ABC` and the `ﬁ`
+/// ligature becomes `fi`. Case is kept; the matchers handle that.
+fn fold(s: &str) -> String {
+    let mut folded = String::with_capacity(s.len());
+    for c in s.chars() {
+        decompose_compatible(c, |d| {
+            if !is_combining_mark(d) {
+                folded.push(d);
+            }
+        });
+    }
+    folded
+}
+
+/// `fold`, plus the char index in `s` each folded char came from.
+fn fold_with_origins(s: &str) -> (String, Vec<usize>) {
+    let mut folded = String::with_capacity(s.len());
+    let mut origins = Vec::with_capacity(s.len());
+    for (i, c) in s.chars().enumerate() {
+        decompose_compatible(c, |d| {
+            if !is_combining_mark(d) {
+                folded.push(d);
+                origins.push(i);
+            }
+        });
+    }
+    (folded, origins)
+}
+
+const SCORE_MATCH: i32 = 16;
+const BONUS_BOUNDARY: i32 = 16;
+const BONUS_CAMEL: i32 = 12;
+const BONUS_CONSECUTIVE: i32 = 8;
+const PENALTY_GAP_START: i32 = -3;
+const PENALTY_GAP_EXTEND: i32 = -1;
+const UNMATCHED: i32 = i32::MIN / 2; // headroom so additions can't overflow
+
+#[inline]
+fn chars_match(nc: char, hc: char, case_sensitive: bool) -> bool {
+    if case_sensitive {
+        hc == nc
+    } else if nc.is_ascii() && hc.is_ascii() {
+        nc.eq_ignore_ascii_case(&hc)
+    } else {
+        hc.to_lowercase().eq(nc.to_lowercase())
+    }
+}
+
+/// Positional bonus for a match at each haystack index: word boundaries and
+/// camelCase humps score higher. Written into `bonus`, reusing its storage.
+fn fill_bonus(hay: &[char], bonus: &mut Vec<i32>) {
+    fn is_camel(prev: char, cur: char) -> bool {
+        (prev.is_lowercase() && cur.is_uppercase())
+            || (prev.is_alphabetic() && cur.is_numeric())
+    }
+
+    bonus.clear();
+    bonus.extend(hay.iter().enumerate().map(|(j, &c)| {
+        match if j == 0 { None } else { Some(hay[j - 1]) } {
+            None => BONUS_BOUNDARY,
+            Some(p) if !p.is_alphanumeric() => BONUS_BOUNDARY,
+            Some(p) if is_camel(p, c) => BONUS_CAMEL,
+            _ => 0,
+        }
+    }));
+}
+
+/// Cheap rejection before the DP: whether `needle` is a subsequence of
+/// `haystack` at all, by a greedy scan without allocating.
+fn is_subsequence(needle: &[char], haystack: &str, case_sensitive: bool) -> bool {
+    let mut needle = needle.iter().peekable();
+    for hc in haystack.chars() {
+        match needle.peek() {
+            Some(&&nc) if chars_match(nc, hc, case_sensitive) => {
+                needle.next();
+            }
+            Some(_) => {}
+            None => break,
+        }
+    }
+    needle.peek().is_none()
+}
+
+/// The fuzzy scorer with its working memory kept between calls: scoring a
+/// list allocates only when a haystack longer than any before comes along.
+/// Only two DP rows are live at a time, and the DP is confined to the span
+/// between the first possible match of the needle's first char and the last
+/// possible match of its last char.
+#[derive(Default)]
+struct Scorer {
+    hay: Vec<char>,
+    bonus: Vec<i32>,
+    ending: Vec<i32>,
+    next: Vec<i32>,
+}
+
+impl Scorer {
+    /// Same result as `fuzzy_score`, for a needle already split into chars.
+    fn score(&mut self, needle: &[char], haystack: &str, case_sensitive: bool) -> Option<i32> {
+        self.align(needle, haystack, case_sensitive, None)
+    }
+
+    /// The haystack char indices of the alignment `score` finds, walked back
+    /// through the DP rows kept along the way. Empty when no match.
+    fn positions(&mut self, needle: &[char], haystack: &str, case_sensitive: bool) -> Vec<usize> {
+        let mut rows = Vec::new();
+        match self.align(needle, haystack, case_sensitive, Some(&mut rows)) {
+            Some(_) => self.backtrack(&rows).unwrap_or_default(),
+            None => Vec::new(),
+        }
+    }
+
+    /// Run the DP and return the best score. When `rows` is given, each row
+    /// is kept in it: `rows[i][j]` is the best score matching the first
+    /// `i + 1` needle chars with the last one matched exactly at haystack
+    /// index `j` (`UNMATCHED` when impossible).
+    fn align(
+        &mut self,
+        needle: &[char],
+        haystack: &str,
+        case_sensitive: bool,
+        mut rows: Option<&mut Vec<Vec<i32>>>,
+    ) -> Option<i32> {
+        let (&first, &last) = match (needle.first(), needle.last()) {
+            (Some(first), Some(last)) => (first, last),
+            _ => return Some(0),
+        };
+        if !is_subsequence(needle, haystack, case_sensitive) {
+            return None;
+        }
+        self.hay.clear();
+        self.hay.extend(haystack.chars());
+        let hay = &self.hay;
+        let start = hay.iter().position(|&hc| chars_match(first, hc, case_sensitive))?;
+        let end = hay.iter().rposition(|&hc| chars_match(last, hc, case_sensitive))? + 1;
+        fill_bonus(hay, &mut self.bonus);
+        self.ending.clear();
+        self.ending.resize(hay.len(), UNMATCHED);
+        self.next.clear();
+        self.next.resize(hay.len(), UNMATCHED);
+
+        for (row, &nc) in needle.iter().enumerate() {
+            let (ending, next) = (&self.ending, &mut self.next);
+            next[start..end].fill(UNMATCHED);
+            // Best previous-row score ending strictly before j-1, with affine
+            // gap penalties applied for the unmatched span up to j-1
+            let mut gapped = UNMATCHED;
+            for j in start..end {
+                if chars_match(nc, hay[j], case_sensitive) {
+                    if row == 0 {
+                        next[j] = SCORE_MATCH + self.bonus[j];
+                    } else {
+                        let diag = if j > 0 { ending[j - 1] } else { UNMATCHED };
+                        let best = (diag + BONUS_CONSECUTIVE).max(gapped);
+                        if best > UNMATCHED {
+                            next[j] = best + SCORE_MATCH + self.bonus[j];
+                        }
+                    }
+                }
+                if row > 0 && j > 0 {
+                    gapped = (gapped + PENALTY_GAP_EXTEND).max(ending[j - 1] + PENALTY_GAP_START);
+                }
+            }
+            if let Some(rows) = rows.as_deref_mut() {
+                rows.push(next.clone());
+            }
+            std::mem::swap(&mut self.ending, &mut self.next);
+        }
+
+        let best = self.ending[start..end].iter().copied().max()?;
+        if best > UNMATCHED / 2 { Some(best) } else { None }
+    }
+
+    /// Walk `rows` back from the best end, the earliest on a tie. None when
+    /// a step finds no way back, which consistent rows never give.
+    fn backtrack(&self, rows: &[Vec<i32>]) -> Option<Vec<usize>> {
+        let last = rows.last()?;
+        let (mut j, _) = last.iter().enumerate().max_by_key(|&(j, &s)| (s, std::cmp::Reverse(j)))?;
+        let mut positions = vec![j];
+        for r in (1..rows.len()).rev() {
+            let prev = &rows[r - 1];
+            let base = rows[r][j] - SCORE_MATCH - self.bonus[j];
+            let reachable = |k: usize| prev[k] > UNMATCHED / 2;
+            j = if j > 0 && reachable(j - 1) && prev[j - 1] + BONUS_CONSECUTIVE == base {
+                j - 1
+            } else {
+                // A gap of j-1-k unmatched chars before j
+                (0..j.saturating_sub(1)).rev().find(|&k| {
+                    let gap = (j - 1 - k) as i32;
+                    reachable(k) && prev[k] + PENALTY_GAP_START + PENALTY_GAP_EXTEND * (gap - 1) == base
+                })?
+            };
+            positions.push(j);
+        }
+        positions.reverse();
+        Some(positions)
+    }
+}
+
+/// fzf-style subsequence scorer. Returns None when `needle` is not a
+/// subsequence of `haystack`, otherwise the score of the best-scoring
+/// alignment (dynamic programming, not greedy first-occurrence), favoring
+/// matches at word boundaries and camelCase humps, consecutive runs, and
+/// short gaps. Leading and trailing gaps are free. A test-only wrapper
+/// around `Scorer`, which filtering keeps for the whole list.
+#[cfg(test)]
+fn fuzzy_score(needle: &str, haystack: &str, case_sensitive: bool) -> Option<i32> {
+    let needle: Vec<char> = needle.chars().collect();
+    Scorer::default().score(&needle, haystack, case_sensitive)
+}
+
+/// The haystack char indices of the alignment `fuzzy_score` scored. Empty
+/// when no match.
+fn fuzzy_positions(needle: &str, haystack: &str, case_sensitive: bool) -> Vec<usize> {
+    let needle: Vec<char> = needle.chars().collect();
+    Scorer::default().positions(&needle, haystack, case_sensitive)
+}
+
+/// Char indices of the first occurrence of `needle` in `haystack`, compared
+/// char by char like the fuzzy matcher. Empty when absent.
+fn substring_positions(needle: &str, haystack: &str, case_sensitive: bool) -> Vec<usize> {
+    let needle: Vec<char> = needle.chars().collect();
+    let hay: Vec<char> = haystack.chars().collect();
+    if needle.is_empty() || needle.len() > hay.len() {
+        return Vec::new();
+    }
+    (0..=hay.len() - needle.len())
+        .find(|&start| {
+            needle.iter().zip(&hay[start..]).all(|(&nc, &hc)| chars_match(nc, hc, case_sensitive))
+        })
+        .map(|start| (start..start + needle.len()).collect())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pack_empty() {
+        assert_eq!(pack_pages(&[], 100), Vec::<usize>::new());
+    }
+
+    #[test]
+    fn pack_fills_pages_greedily() {
+        assert_eq!(pack_pages(&[10, 10, 10], 25), vec![0, 2]);
+    }
+
+    #[test]
+    fn pack_exact_fit_stays_on_page() {
+        assert_eq!(pack_pages(&[10, 10], 20), vec![0]);
+    }
+
+    #[test]
+    fn pack_oversized_cell_gets_own_page() {
+        // A cell wider than the bar still lands on a page of its own
+        // (it gets ellipsized when drawn), and never stalls the packing.
+        assert_eq!(pack_pages(&[50, 10], 30), vec![0, 1]);
+        assert_eq!(pack_pages(&[10, 50, 10], 30), vec![0, 1, 2]);
+    }
+
+    #[test]
+    fn non_subsequence_rejected() {
+        assert_eq!(fuzzy_score("xyz", "Google Chrome", false), None);
+        assert_eq!(fuzzy_score("chromee", "Chrome", false), None);
+    }
+
+    #[test]
+    fn empty_needle_matches_everything() {
+        assert_eq!(fuzzy_score("", "anything", false), Some(0));
+    }
+
+    #[test]
+    fn case_insensitive_by_default() {
+        assert!(fuzzy_score("chrome", "Google Chrome", false).is_some());
+        assert_eq!(fuzzy_score("chrome", "Google Chrome", true), None);
+        assert!(fuzzy_score("Chrome", "Google Chrome", true).is_some());
+    }
+
+    #[test]
+    fn boundary_match_beats_mid_word_match() {
+        let boundary = fuzzy_score("code", "Visual Studio Code", false).unwrap();
+        let mid_word = fuzzy_score("code", "Unicodex", false).unwrap();
+        assert!(boundary > mid_word);
+    }
+
+    #[test]
+    fn consecutive_run_beats_scattered_match() {
+        let run = fuzzy_score("term", "Terminal", false).unwrap();
+        let scattered = fuzzy_score("term", "Text Formatter", false).unwrap();
+        assert!(run > scattered);
+    }
+
+    #[test]
+    fn camel_hump_scores_above_plain_mid_word() {
+        let camel = fuzzy_score("pp", "PowerPoint", false).unwrap();
+        let plain = fuzzy_score("pp", "clipper", false).unwrap();
+        assert!(camel > plain);
+    }
+
+    #[test]
+    fn acronym_style_matching_works() {
+        // Classic fzf use case: initials of a multi-word entry beat a
+        // scattered mid-word match
+        let acronym = fuzzy_score("vsc", "Visual Studio Code", false).unwrap();
+        let scattered = fuzzy_score("vsc", "vesicular", false).unwrap();
+        assert!(acronym > scattered);
+    }
+
+    #[test]
+    fn picks_best_alignment_not_first_occurrence() {
+        // Greedy matching would take the 's' in "Visual" and miss the
+        // word-boundary 'S' of "Studio"; the DP must find the better path
+        let boundary = fuzzy_score("st", "Visual Studio", false).unwrap();
+        let mid_word = fuzzy_score("st", "Restart", false).unwrap();
+        assert!(boundary > mid_word);
+    }
+
+    #[test]
+    fn shorter_gap_scores_higher() {
+        let short_gap = fuzzy_score("ab", "acb", false).unwrap();
+        let long_gap = fuzzy_score("ab", "acccccb", false).unwrap();
+        assert!(short_gap > long_gap);
+    }
+
+    // Scripted sessions: drive the engine the way a user would.
+
+    const NO_MODS: Mods = Mods { ctrl: false, shift: false };
+    const SHIFT: Mods = Mods { ctrl: false, shift: true };
+    const CTRL: Mods = Mods { ctrl: true, shift: false };
+
+    fn engine(mode: FilterMode, entries: &[&str]) -> Engine {
+        let entries: Vec<String> = entries.iter().map(|s| s.to_string()).collect();
+        Engine::new(
+            &entries,
+            Behavior {
+                filter_mode: mode,
+                case_sensitive: false,
+                normalize: false,
+                next: KeyCombo { ctrl: true, shift: false, vk: 0x4A }, // Ctrl+J
+                prev: KeyCombo { ctrl: true, shift: false, vk: 0x4B }, // Ctrl+K
+            },
+        )
+    }
+
+    fn type_text(engine: &mut Engine, text: &str) {
+        for c in text.chars() {
+            engine.handle(Input::Char(c));
+        }
+    }
+
+    fn press(engine: &mut Engine, vk: u16) -> Outcome {
+        engine.handle(Input::Key(vk, NO_MODS))
+    }
+
+    fn visible(engine: &Engine) -> Vec<&str> {
+        engine.visible_rows().into_iter().map(|r| r.text).collect()
+    }
+
+    const APPS: &[&str] = &["Calculator", "Paint", "Visual Studio", "Visual Studio Code", "VSCodium"];
+
+    #[test]
+    fn type_navigate_and_enter() {
+        let mut e = engine(FilterMode::Fuzzy, APPS);
+        type_text(&mut e, "vsc");
+        assert_eq!(visible(&e), vec!["Visual Studio Code", "VSCodium"]);
+        press(&mut e, vk::DOWN);
+        press(&mut e, vk::DOWN); // wraps back to the top
+        assert_eq!(press(&mut e, vk::RETURN), Outcome::Done);
+        assert!(e.done);
+        assert_eq!(e.take_result().as_deref(), Some("Visual Studio Code"));
+    }
+
+    #[test]
+    fn keywords_mode_requires_every_word() {
+        let mut e = engine(FilterMode::Keywords, APPS);
+        type_text(&mut e, "stu vis");
+        assert_eq!(visible(&e), vec!["Visual Studio", "Visual Studio Code"]);
+        type_text(&mut e, " co");
+        assert_eq!(visible(&e), vec!["Visual Studio Code"]);
+    }
+
+    #[test]
+    fn complete_mode_matches_substring() {
+        let mut e = engine(FilterMode::Complete, APPS);
+        type_text(&mut e, "studio c");
+        assert_eq!(visible(&e), vec!["Visual Studio Code"]);
+    }
+
+    #[test]
+    fn backspace_widens_results() {
+        let mut e = engine(FilterMode::Complete, APPS);
+        type_text(&mut e, "paintx");
+        assert!(visible(&e).is_empty());
+        assert_eq!(e.selected, None);
+        e.handle(Input::Backspace);
+        assert_eq!(e.query(), "paint");
+        assert_eq!(visible(&e), vec!["Paint"]);
+    }
+
+    #[test]
+    fn shift_enter_returns_typed_text() {
+        let mut e = engine(FilterMode::Fuzzy, APPS);
+        type_text(&mut e, "calc");
+        assert_eq!(e.handle(Input::Key(vk::RETURN, SHIFT)), Outcome::Done);
+        assert_eq!(e.take_result().as_deref(), Some("calc"));
+    }
+
+    #[test]
+    fn enter_without_results_returns_typed_text() {
+        let mut e = engine(FilterMode::Fuzzy, APPS);
+        type_text(&mut e, "notepad");
+        press(&mut e, vk::RETURN);
+        assert_eq!(e.take_result().as_deref(), Some("notepad"));
+    }
+
+    #[test]
+    fn escape_cancels_and_later_input_is_ignored() {
+        let mut e = engine(FilterMode::Fuzzy, APPS);
+        assert_eq!(press(&mut e, vk::ESCAPE), Outcome::Done);
+        assert_eq!(press(&mut e, vk::DOWN), Outcome::Ignored);
+        assert_eq!(e.take_result(), None);
+    }
+
+    #[test]
+    fn tab_completes_selected_entry() {
+        let mut e = engine(FilterMode::Fuzzy, APPS);
+        type_text(&mut e, "pai");
+        assert_eq!(press(&mut e, vk::TAB), Outcome::SetQuery);
+        assert_eq!(e.query(), "Paint");
+        // Typing continues after the completed text
+        type_text(&mut e, "!");
+        assert_eq!(e.query(), "Paint!");
+    }
+
+    #[test]
+    fn navigation_combos_move_selection() {
+        let mut e = engine(FilterMode::Fuzzy, APPS);
+        e.handle(Input::Key(0x4A, CTRL));
+        assert_eq!(e.selected_text(), Some("Paint"));
+        e.handle(Input::Key(0x4B, CTRL));
+        e.handle(Input::Key(0x4B, CTRL));
+        assert_eq!(e.selected_text(), Some("VSCodium"));
+    }
+
+    #[test]
+    fn left_right_are_edge_triggered() {
+        let mut e = engine(FilterMode::Fuzzy, APPS);
+        // Empty query: the caret is at both edges, so arrows navigate
+        assert_eq!(press(&mut e, vk::RIGHT), Outcome::Redraw);
+        assert_eq!(e.selected, Some(1));
+        type_text(&mut e, "a");
+        // Caret at the end: Left moves the caret, Right moves the selection
+        assert_eq!(press(&mut e, vk::LEFT), Outcome::Ignored);
+        assert_eq!(press(&mut e, vk::LEFT), Outcome::Redraw);
+        assert_eq!(e.handle(Input::Key(vk::LEFT, SHIFT)), Outcome::Ignored);
+    }
+
+    #[test]
+    fn rows_paging_with_page_keys() {
+        let mut e = engine(FilterMode::Fuzzy, APPS);
+        e.set_paging(Paging::Rows(2));
+        assert_eq!(visible(&e), vec!["Calculator", "Paint"]);
+        press(&mut e, vk::NEXT);
+        assert_eq!(visible(&e), vec!["Visual Studio", "Visual Studio Code"]);
+        press(&mut e, vk::NEXT);
+        assert_eq!(visible(&e), vec!["VSCodium"]);
+        press(&mut e, vk::NEXT); // last page: stays on the last entry
+        assert_eq!(e.selected_text(), Some("VSCodium"));
+        press(&mut e, vk::PRIOR);
+        assert_eq!(e.selected_text(), Some("Visual Studio"));
+        press(&mut e, vk::HOME);
+        assert_eq!(e.selected, Some(0));
+        press(&mut e, vk::END);
+        assert_eq!(e.selected, Some(4));
+    }
+
+    #[test]
+    fn cell_paging_packs_by_width() {
+        let mut e = engine(FilterMode::Fuzzy, APPS);
+        e.set_paging(Paging::Cells { avail: 25, widths: vec![10, 10, 10, 10, 10] });
+        assert_eq!(e.page_starts(), vec![0, 2, 4]);
+    }
+
+    #[test]
+    fn click_selects_then_activates() {
+        let mut e = engine(FilterMode::Fuzzy, APPS);
+        assert_eq!(e.handle(Input::Click(1)), Outcome::Redraw);
+        assert_eq!(e.handle(Input::Click(1)), Outcome::Done);
+        assert_eq!(e.take_result().as_deref(), Some("Paint"));
+    }
+
+    #[test]
+    fn wheel_scrolls_selection() {
+        let mut e = engine(FilterMode::Fuzzy, APPS);
+        e.handle(Input::Wheel(-2));
+        assert_eq!(e.selected, Some(2));
+        e.handle(Input::Wheel(1));
+        assert_eq!(e.selected, Some(1));
+    }
+
+    #[test]
+    fn caret_only_sync_keeps_selection() {
+        let mut e = engine(FilterMode::Fuzzy, APPS);
+        e.set_query("s", 1, 1);
+        press(&mut e, vk::DOWN);
+        let selected = e.selected;
+        e.set_query("s", 0, 0);
+        assert_eq!(e.selected, selected);
+    }
+
+    /// Learned weights for `entry` under queries extending `prefix`.
+    fn learned(prefix: &'static str, entry: &'static str, weight: f64) -> Adaptive {
+        Box::new(move |query: &str| {
+            let mut weights = HashMap::new();
+            if query.starts_with(prefix) {
+                weights.insert(entry.to_string(), weight);
+            }
+            weights
+        })
+    }
+
+    #[test]
+    fn adaptive_boost_blends_with_fuzzy_score() {
+        let mut e = engine(FilterMode::Fuzzy, APPS);
+        type_text(&mut e, "vsc");
+        assert_eq!(visible(&e)[0], "Visual Studio Code");
+
+        let mut e = engine(FilterMode::Fuzzy, APPS);
+        e.set_adaptive(learned("vs", "VSCodium", 3.0));
+        type_text(&mut e, "vsc");
+        assert_eq!(visible(&e), vec!["VSCodium", "Visual Studio Code"]);
+        // Never resurrects an entry the filter dropped
+        type_text(&mut e, "ode");
+        assert_eq!(visible(&e), vec!["Visual Studio Code"]);
+    }
+
+    #[test]
+    fn adaptive_boost_reorders_substring_modes() {
+        let mut e = engine(FilterMode::Complete, APPS);
+        e.set_adaptive(learned("stu", "Visual Studio Code", 1.0));
+        type_text(&mut e, "st");
+        assert_eq!(visible(&e), vec!["Visual Studio", "Visual Studio Code"]);
+        type_text(&mut e, "u");
+        assert_eq!(visible(&e), vec!["Visual Studio Code", "Visual Studio"]);
+    }
+
+    fn with_aliases(mode: FilterMode) -> Engine {
+        let mut e = engine(mode, &["Task Switcher", "Windows Terminal", "WordPad"]);
+        let aliases = HashMap::from([(
+            "Windows Terminal".to_string(),
+            vec!["wt".to_string(), "console".to_string()],
+        )]);
+        e.set_aliases(&aliases);
+        e
+    }
+
+    #[test]
+    fn aliases_match_but_are_not_shown() {
+        for mode in [FilterMode::Complete, FilterMode::Keywords, FilterMode::Fuzzy] {
+            let mut e = with_aliases(mode);
+            type_text(&mut e, "console");
+            assert_eq!(visible(&e), vec!["Windows Terminal"], "{:?}", mode);
+        }
+        // Keywords may mix name words and aliases
+        let mut e = with_aliases(FilterMode::Keywords);
+        type_text(&mut e, "cons win");
+        assert_eq!(visible(&e), vec!["Windows Terminal"]);
+    }
+
+    #[test]
+    fn alias_hit_ranks_like_strong_name_match() {
+        let mut e = with_aliases(FilterMode::Fuzzy);
+        type_text(&mut e, "wt");
+        // Listed after "Task Switcher", but the alias outranks its mid-word hit
+        assert_eq!(visible(&e), vec!["Windows Terminal", "Task Switcher"]);
+        let alias = fuzzy_score("wt", "wt", false).unwrap();
+        assert!(alias >= fuzzy_score("wt", "Windows Terminal", false).unwrap());
+    }
+
+    #[test]
+    fn fuzzy_positions_follow_best_alignment() {
+        assert_eq!(fuzzy_positions("vsc", "Visual Studio Code", false), vec![0, 7, 14]);
+        assert_eq!(fuzzy_positions("code", "Visual Studio Code", false), vec![14, 15, 16, 17]);
+        // Boundary hits beat the earlier mid-word "t"
+        assert_eq!(fuzzy_positions("wt", "Windows Terminal", false), vec![0, 8]);
+        assert!(fuzzy_positions("xyz", "Visual Studio Code", false).is_empty());
+    }
+
+    #[test]
+    fn fuzzy_positions_agree_with_score() {
+        for (needle, hay) in [("ab", "acccccb"), ("term", "Text Formatter"), ("pp", "PowerPoint")] {
+            let positions = fuzzy_positions(needle, hay, false);
+            assert_eq!(positions.len(), needle.chars().count());
+            // Re-score the recovered alignment from scratch
+            let hay_chars: Vec<char> = hay.chars().collect();
+            let mut bonus = Vec::new();
+            fill_bonus(&hay_chars, &mut bonus);
+            let mut score = 0;
+            for (i, &j) in positions.iter().enumerate() {
+                score += SCORE_MATCH + bonus[j];
+                if i > 0 {
+                    let gap = (j - positions[i - 1] - 1) as i32;
+                    score += match gap {
+                        0 => BONUS_CONSECUTIVE,
+                        g => PENALTY_GAP_START + PENALTY_GAP_EXTEND * (g - 1),
+                    };
+                }
+            }
+            assert_eq!(Some(score), fuzzy_score(needle, hay, false), "{} in {}", needle, hay);
+        }
+    }
+
+    #[test]
+    fn rows_carry_match_positions() {
+        let mut e = engine(FilterMode::Keywords, APPS);
+        type_text(&mut e, "stu code");
+        assert_eq!(e.visible_rows()[0].matches, vec![7, 8, 9, 14, 15, 16, 17]);
+
+        let mut e = engine(FilterMode::Complete, APPS);
+        type_text(&mut e, "paint");
+        assert_eq!(e.visible_rows()[0].matches, vec![0, 1, 2, 3, 4]);
+
+        let mut e = engine(FilterMode::Fuzzy, APPS);
+        assert!(e.visible_rows().iter().all(|r| r.matches.is_empty()));
+        type_text(&mut e, "calc");
+        assert_eq!(e.visible_rows()[0].matches, vec![0, 1, 2, 3]);
+    }
+
+    #[test]
+    fn alias_only_hits_have_no_positions() {
+        let mut e = with_aliases(FilterMode::Complete);
+        type_text(&mut e, "console");
+        assert!(e.visible_rows()[0].matches.is_empty());
+    }
+
+    fn normalizing(mode: FilterMode, entries: &[&str]) -> Engine {
+        let mut e = engine(mode, &[]);
+        e.behavior.normalize = true;
+        let entries: Vec<String> = entries.iter().map(|s| s.to_string()).collect();
+        Engine::new(&entries, e.behavior)
+    }
+
+    #[test]
+    fn fold_strips_accents_width_and_ligatures() {
+        assert_eq!(fold("Café"), "Cafe");
+        assert_eq!(fold("Cafe\u{301}"), "Cafe");
+        assert_eq!(fold("Ｅｘｃｅｌ　２"), "Excel 2");
+        assert_eq!(fold("ﬁle ﬂow"), "file flow");
+        assert_eq!(fold("Paint"), "Paint");
+    }
+
+    #[test]
+    fn accented_entries_match_plain_queries() {
+        for mode in [FilterMode::Complete, FilterMode::Keywords, FilterMode::Fuzzy] {
+            let mut e = normalizing(mode, &["Café Menu", "Éditeur de texte", "Paint"]);
+            type_text(&mut e, "cafe");
+            assert_eq!(visible(&e), vec!["Café Menu"], "{:?}", mode);
+            e.set_query("editeur", 7, 7);
+            assert_eq!(visible(&e), vec!["Éditeur de texte"], "{:?}", mode);
+        }
+        // Without normalize the accent must be typed
+        let mut e = engine(FilterMode::Complete, &["Café Menu"]);
+        type_text(&mut e, "cafe");
+        assert!(visible(&e).is_empty());
+    }
+
+    #[test]
+    fn decomposed_and_precomposed_match_each_other() {
+        let mut e = normalizing(FilterMode::Complete, &["Cafe\u{301}", "Tea"]);
+        type_text(&mut e, "café");
+        assert_eq!(visible(&e), vec!["Cafe\u{301}"]);
+    }
+
+    #[test]
+    fn full_width_and_ligatures_match() {
+        let mut e = normalizing(FilterMode::Keywords, &["Ｅｘｃｅｌ", "ﬁle Explorer", "Word"]);
+        type_text(&mut e, "excel");
+        assert_eq!(visible(&e), vec!["Ｅｘｃｅｌ"]);
+        e.set_query("ｗｏｒｄ", 4, 4);
+        assert_eq!(visible(&e), vec!["Word"]);
+        e.set_query("file", 4, 4);
+        assert_eq!(visible(&e), vec!["ﬁle Explorer"]);
+    }
+
+    #[test]
+    fn normalized_positions_map_to_original_chars() {
+        let mut e = normalizing(FilterMode::Complete, &["Café"]);
+        type_text(&mut e, "fe");
+        assert_eq!(e.visible_rows()[0].matches, vec![2, 3]);
+
+        let mut e = normalizing(FilterMode::Fuzzy, &["ﬁle Explorer"]);
+        type_text(&mut e, "fil");
+        assert_eq!(e.visible_rows()[0].matches, vec![0, 1]);
+
+        let mut e = normalizing(FilterMode::Complete, &["Cafe\u{301}"]);
+        type_text(&mut e, "é");
+        assert_eq!(e.visible_rows()[0].matches, vec![3]);
+    }
+
+    #[test]
+    fn extending_the_query_narrows_to_the_same_results() {
+        for mode in [FilterMode::Complete, FilterMode::Keywords, FilterMode::Fuzzy] {
+            let mut narrowed = engine(mode, APPS);
+            type_text(&mut narrowed, "s");
+            type_text(&mut narrowed, "tu");
+            let mut fresh = engine(mode, APPS);
+            fresh.set_query("stu", 3, 3);
+            assert_eq!(narrowed.results, fresh.results, "{:?}", mode);
+            // Backspacing past the narrowed query widens again
+            narrowed.handle(Input::Backspace);
+            narrowed.handle(Input::Backspace);
+            narrowed.handle(Input::Backspace);
+            assert_eq!(narrowed.results.len(), APPS.len());
+        }
+    }
+
+    #[test]
+    fn cancelled_filtering_yields_nothing() {
+        let e = engine(FilterMode::Fuzzy, APPS);
+        let mut job = Job {
+            generation: 1,
+            needle: "vs".to_string(),
+            filter_mode: FilterMode::Fuzzy,
+            case_sensitive: false,
+            candidates: (0..APPS.len()).collect(),
+            weights: HashMap::new(),
+        };
+        assert_eq!(filter(&e.entries, &mut job, &|| true), None);
+    }
+
+    fn with_worker(entries: &[&str]) -> (Engine, mpsc::Receiver<()>) {
+        let mut e = engine(FilterMode::Fuzzy, entries);
+        let (tx, rx) = mpsc::channel();
+        e.spawn_worker(move || {
+            let _ = tx.send(());
+        });
+        (e, rx)
+    }
+
+    #[test]
+    fn worker_publishes_results_for_latest_query() {
+        let (mut e, ready) = with_worker(APPS);
+        type_text(&mut e, "pai");
+        // Still showing the unfiltered list until the worker reports back
+        while !e.poll() {
+            ready.recv_timeout(std::time::Duration::from_secs(5)).expect("worker reported");
+        }
+        assert_eq!(visible(&e), vec!["Paint"]);
+        assert_eq!(e.pending, None);
+    }
+
+    #[test]
+    fn enter_settles_pending_filtering() {
+        let (mut e, _ready) = with_worker(APPS);
+        type_text(&mut e, "codi");
+        assert_eq!(press(&mut e, vk::RETURN), Outcome::Done);
+        assert_eq!(e.take_result().as_deref(), Some("VSCodium"));
+        // Late worker results for the settled query are ignored
+        assert!(!e.poll());
+    }
+
+    /// App-like names ("Studio Remote Viewer 42") from a fixed-seed LCG, so
+    /// benchmark runs are comparable.
+    fn synthetic_entries(count: usize) -> Vec<String> {
+        const WORDS: &[&str] = &[
+            "Visual", "Studio", "Code", "Remote", "Desktop", "Connection", "Microsoft", "Edge",
+            "Windows", "Terminal", "PowerShell", "Control", "Panel", "Settings", "Paint",
+            "Notepad", "Calculator", "Explorer", "Manager", "Task", "Viewer", "Editor", "Media",
+            "Player", "Photos", "Camera", "Mail", "Calendar", "Office", "Excel", "Word", "Outlook",
+            "Teams", "Firefox", "Chrome", "Git", "Bash", "Python", "Node", "Docker", "Café",
+        ];
+        let mut state: u64 = 0x2545_f491_4f6c_dd1d;
+        let mut next = |bound: usize| {
+            state = state.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+            ((state >> 33) as usize) % bound
+        };
+        (0..count)
+            .map(|_| {
+                let words = 2 + next(3);
+                let mut name: Vec<String> = (0..words).map(|_| WORDS[next(WORDS.len())].to_string()).collect();
+                if next(4) == 0 {
+                    name.push(next(100).to_string());
+                }
+                name.join(" ")
+            })
+            .collect()
+    }
+
+    /// Fuzzy filtering throughput over 10k and 100k synthetic entries, one
+    /// full scan per query (no narrowing). Timing only, so ignored by default:
+    /// `cargo test --release fuzzy_benchmark -- --ignored --nocapture`
+    #[test]
+    #[ignore]
+    fn fuzzy_benchmark() {
+        const QUERIES: &[&str] = &["v", "vs", "vsc", "vscode", "studio code", "remdeskcon", "xyzq"];
+        for count in [10_000, 100_000] {
+            let entries = synthetic_entries(count);
+            let mut total = std::time::Duration::ZERO;
+            for query in QUERIES {
+                let mut e = engine(FilterMode::Fuzzy, &[]);
+                e = Engine::new(&entries, e.behavior);
+                let start = std::time::Instant::now();
+                e.set_query(query, 0, 0);
+                let elapsed = start.elapsed();
+                total += elapsed;
+                println!("{:>7} entries  {:<12} {:>5} matches  {:?}", count, query, e.results.len(), elapsed);
+            }
+            println!("{:>7} entries  total {:?}", count, total);
+        }
+    }
+}
@@ -1,97 +1,280 @@
-//! Selection-frequency history. Every time an entry is chosen its tally goes
-//! up; `sort_entries` then orders the menu most-used-first, with the stable
-//! sort keeping never-used entries in their original (alphabetical) order.
-//! Because the fuzzy sorter tie-breaks by list position, the boost also
-//! decides between equally-scored fuzzy matches.
+//! Selection history. Every time an entry is chosen its tally goes up and its
+//! last-used time is stamped; `sort_entries` then orders the menu best-first,
+//! with the stable sort keeping never-used entries in their original
+//! (alphabetical) order. Because the fuzzy sorter tie-breaks by list position,
+//! the boost also decides between equally-scored fuzzy matches.
 //!
-//! Persisted as plain `count<TAB>name` lines so the file is trivially
-//! inspectable and editable. Writes go through a temp file + rename so a
-//! crash mid-save can't truncate the history.
+//! Two rankings are available. `Count` is the plain most-used-first tally.
+//! `Frecency` ranks by a time-decayed score: each use adds 1 and the total
+//! halves every `HALF_LIFE_SECS`, so last year's favorite fades behind what
+//! is used every day now.
+//!
+//! On top of the global ranking, each pick is remembered against the query it
+//! was made with. `query_weights` hands those back while typing, so the menu
+//! can boost what was chosen for `te` before whenever `te` (or `tex`, ...) is
+//! typed again.
+//!
+//! Persisted as plain text so the file is trivially inspectable and editable:
+//! a `# windmenu history v3` header, then `count<TAB>last_used<TAB>score<TAB>name`
+//! lines (`last_used` in Unix seconds, `score` decayed as of `last_used`) and
+//! `q<TAB>count<TAB>query<TAB>name` lines for the query pairs. Both rankings
+//! share the file, so switching modes keeps the data. v2 files (no query
+//! lines) load as-is; a headerless file is the v1 `count<TAB>name` format and
+//! is migrated on load. Writes go through a temp file + rename so a crash
+//! mid-save can't truncate the history.
 
 use std::collections::HashMap;
 use std::fs;
 use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
 
 pub const HISTORY_FILE: &str = "windmenu_history.txt";
 
-/// Entries kept when saving; the long tail of rarely-used entries is dropped
-/// once the file grows past this.
+const HEADER_V2: &str = "# windmenu history v2";
+const HEADER_V3: &str = "# windmenu history v3";
+
+/// Entries kept when saving; the long tail of lowest-ranked entries is
+/// dropped once the file grows past this.
 const MAX_SAVED_ENTRIES: usize = 500;
 
+/// (query, entry) pairs kept when saving, most-picked first.
+const MAX_SAVED_QUERIES: usize = 500;
+
+/// Time for a frecency score to lose half its weight: one week.
+const HALF_LIFE_SECS: f64 = 7.0 * 24.0 * 60.0 * 60.0;
+
+/// How `sort_entries` orders the menu (`history = true` / `"frecency"`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Ranking {
+    Count,
+    Frecency,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Usage {
+    count: u32,
+    last_used: u64, // Unix seconds
+    score: f64,     // Decayed score as of `last_used`
+}
+
+impl Usage {
+    /// The frecency score decayed from `last_used` to `now`.
+    fn score_at(&self, now: u64) -> f64 {
+        let age = now.saturating_sub(self.last_used) as f64;
+        self.score * 0.5f64.powf(age / HALF_LIFE_SECS)
+    }
+}
+
 pub struct History {
-    counts: HashMap<String, u32>,
+    usage: HashMap<String, Usage>,
+    queries: HashMap<(String, String), u32>, // (lowercased query, entry) -> picks
+    ranking: Ranking,
     path: PathBuf,
 }
 
+fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0)
+}
+
 impl History {
-    pub fn load(path: PathBuf) -> History {
-        let mut counts = HashMap::new();
-        if let Ok(text) = fs::read_to_string(&path) {
-            for line in text.lines() {
-                if let Some((count, name)) = line.split_once('\t') {
-                    if let (Ok(count), false) = (count.trim().parse::<u32>(), name.is_empty()) {
-                        counts.insert(name.to_string(), count);
-                    }
-                }
+    pub fn load(path: PathBuf, ranking: Ranking) -> History {
+        let (usage, queries) = fs::read_to_string(&path)
+            .map(|text| parse(&text, now()))
+            .unwrap_or_default();
+        History { usage, queries, ranking, path }
+    }
+
+    /// Bump the tally for a chosen entry, remember it against the query it
+    /// was picked with, and persist. Save errors are ignored: history is a
+    /// convenience and must never break launching.
+    pub fn record(&mut self, name: &str, query: &str) {
+        self.record_at(name, now());
+        self.record_query(name, query);
+        self.save();
+    }
+
+    fn record_query(&mut self, name: &str, query: &str) {
+        let query = query.trim().to_lowercase();
+        // Tab-completed or typed-out names teach nothing about the query
+        if query.is_empty() || query == name.to_lowercase() {
+            return;
+        }
+        let picks = self.queries.entry((query, name.to_string())).or_insert(0);
+        *picks = picks.saturating_add(1);
+    }
+
+    /// Learned weights for `query`: every entry picked before with this query
+    /// or a prefix of it, weighted by picks. A shorter remembered query says
+    /// less about a longer typed one, so its picks are scaled by the share of
+    /// the typed query it covers.
+    pub fn query_weights(&self, query: &str) -> HashMap<String, f64> {
+        let query = query.trim().to_lowercase();
+        let typed = query.chars().count() as f64;
+        let mut weights = HashMap::new();
+        if query.is_empty() {
+            return weights;
+        }
+        for ((prefix, name), &picks) in &self.queries {
+            if query.starts_with(prefix.as_str()) {
+                let share = prefix.chars().count() as f64 / typed;
+                *weights.entry(name.clone()).or_insert(0.0) += picks as f64 * share;
             }
         }
-        History { counts, path }
+        weights
     }
 
-    /// Bump the tally for a chosen entry and persist. Save errors are ignored:
-    /// history is a convenience and must never break launching.
-    pub fn record(&mut self, name: &str) {
-        let count = self.counts.entry(name.to_string()).or_insert(0);
-        *count = count.saturating_add(1);
-        self.save();
+    fn record_at(&mut self, name: &str, now: u64) {
+        let usage = self.usage.entry(name.to_string()).or_insert(Usage {
+            count: 0,
+            last_used: now,
+            score: 0.0,
+        });
+        usage.count = usage.count.saturating_add(1);
+        usage.score = usage.score_at(now) + 1.0;
+        usage.last_used = now;
     }
 
     fn save(&self) {
-        let mut ranked: Vec<(&String, &u32)> = self.counts.iter().collect();
-        ranked.sort_by(|a, b| b.1.cmp(a.1).then(a.0.cmp(b.0)));
+        let now = now();
+        let mut ranked: Vec<(&String, &Usage)> = self.usage.iter().collect();
+        ranked.sort_by(|a, b| self.compare(b.1, a.1, now).then(a.0.cmp(b.0)));
         ranked.truncate(MAX_SAVED_ENTRIES);
-        let body: String = ranked
-            .into_iter()
-            .map(|(name, count)| format!("{}\t{}\n", count, name))
-            .collect();
+        let mut body = format!("{}\n", HEADER_V3);
+        for (name, usage) in ranked {
+            body.push_str(&format!(
+                "{}\t{}\t{:.4}\t{}\n",
+                usage.count, usage.last_used, usage.score, name
+            ));
+        }
+        let mut pairs: Vec<(&(String, String), &u32)> = self.queries.iter().collect();
+        pairs.sort_by(|a, b| b.1.cmp(a.1).then(a.0.cmp(b.0)));
+        pairs.truncate(MAX_SAVED_QUERIES);
+        for ((query, name), picks) in pairs {
+            body.push_str(&format!("q\t{}\t{}\t{}\n", picks, query, name));
+        }
         let tmp = self.path.with_extension("txt.tmp");
         if fs::write(&tmp, body).is_ok() {
             let _ = fs::rename(&tmp, &self.path);
         }
     }
 
-    /// Stable-sort `entries` by usage count, descending. Entries with no
-    /// history keep their relative order.
+    /// Order two usages by the active ranking, ascending.
+    fn compare(&self, a: &Usage, b: &Usage, now: u64) -> std::cmp::Ordering {
+        match self.ranking {
+            Ranking::Count => a.count.cmp(&b.count),
+            Ranking::Frecency => a.score_at(now).total_cmp(&b.score_at(now)),
+        }
+    }
+
+    /// Stable-sort `entries` by the active ranking, descending. Entries with
+    /// no history keep their relative order.
     pub fn sort_entries(&self, entries: &mut [String]) {
-        if self.counts.is_empty() {
+        self.sort_entries_at(entries, now());
+    }
+
+    fn sort_entries_at(&self, entries: &mut [String], now: u64) {
+        if self.usage.is_empty() {
             return;
         }
-        entries.sort_by_key(|name| std::cmp::Reverse(self.counts.get(name).copied().unwrap_or(0)));
+        match self.ranking {
+            Ranking::Count => entries.sort_by_key(|name| {
+                std::cmp::Reverse(self.usage.get(name).map_or(0, |u| u.count))
+            }),
+            Ranking::Frecency => entries.sort_by(|a, b| {
+                let score = |name: &String| self.usage.get(name).map_or(0.0, |u| u.score_at(now));
+                score(b).total_cmp(&score(a))
+            }),
+        }
     }
 }
 
+type Parsed = (HashMap<String, Usage>, HashMap<(String, String), u32>);
+
+/// Parse a history file of any version. Malformed lines are skipped.
+fn parse(text: &str, now: u64) -> Parsed {
+    let mut lines = text.lines();
+    let mut usage = HashMap::new();
+    let mut queries = HashMap::new();
+    if text.starts_with(HEADER_V3) || text.starts_with(HEADER_V2) {
+        lines.next();
+        for line in lines {
+            let mut fields = line.splitn(4, '\t');
+            let (Some(count), Some(last_used), Some(score), Some(name)) =
+                (fields.next(), fields.next(), fields.next(), fields.next())
+            else {
+                continue;
+            };
+            if count == "q" {
+                // Query pair: q<TAB>picks<TAB>query<TAB>name
+                let (picks, query) = (last_used, score);
+                if let (Ok(picks), false, false) =
+                    (picks.trim().parse::<u32>(), query.is_empty(), name.is_empty())
+                {
+                    queries.insert((query.to_string(), name.to_string()), picks);
+                }
+                continue;
+            }
+            if let (Ok(count), Ok(last_used), Ok(score), false) = (
+                count.trim().parse::<u32>(),
+                last_used.trim().parse::<u64>(),
+                score.trim().parse::<f64>(),
+                name.is_empty(),
+            ) {
+                usage.insert(name.to_string(), Usage { count, last_used, score });
+            }
+        }
+    } else {
+        // v1 `count<TAB>name`: no timestamps, so the tally seeds the score as
+        // of the migration and fades from there at the normal half-life
+        for line in lines {
+            if let Some((count, name)) = line.split_once('\t') {
+                if let (Ok(count), false) = (count.trim().parse::<u32>(), name.is_empty()) {
+                    usage.insert(
+                        name.to_string(),
+                        Usage { count, last_used: now, score: count as f64 },
+                    );
+                }
+            }
+        }
+    }
+    (usage, queries)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    const DAY: u64 = 24 * 60 * 60;
+
     fn temp_path(name: &str) -> PathBuf {
         std::env::temp_dir().join(format!("windmenu-history-test-{}", name))
     }
 
+    fn history(ranking: Ranking, name: &str) -> History {
+        History { usage: HashMap::new(), queries: HashMap::new(), ranking, path: temp_path(name) }
+    }
+
+    fn entries(names: &[&str]) -> Vec<String> {
+        names.iter().map(|s| s.to_string()).collect()
+    }
+
     #[test]
     fn unused_entries_keep_alphabetical_order() {
-        let mut h = History { counts: HashMap::new(), path: temp_path("noop") };
-        h.counts.insert("Notepad".into(), 3);
-        h.counts.insert("Firefox".into(), 7);
-        let mut entries: Vec<String> =
-            ["Calculator", "Firefox", "Notepad", "Paint"].iter().map(|s| s.to_string()).collect();
+        let mut h = history(Ranking::Count, "noop");
+        for _ in 0..3 {
+            h.record_at("Notepad", 0);
+        }
+        for _ in 0..7 {
+            h.record_at("Firefox", 0);
+        }
+        let mut entries = entries(&["Calculator", "Firefox", "Notepad", "Paint"]);
         h.sort_entries(&mut entries);
         assert_eq!(entries, vec!["Firefox", "Notepad", "Calculator", "Paint"]);
     }
 
     #[test]
     fn empty_history_leaves_entries_untouched() {
-        let h = History { counts: HashMap::new(), path: temp_path("empty") };
+        let h = history(Ranking::Count, "empty");
         let mut entries: Vec<String> = vec!["b".into(), "a".into()];
         h.sort_entries(&mut entries);
         assert_eq!(entries, vec!["b", "a"]);
@@ -101,13 +284,13 @@ mod tests {
     fn record_persists_and_reloads() {
         let path = temp_path("roundtrip");
         let _ = fs::remove_file(&path);
-        let mut h = History::load(path.clone());
-        h.record("Firefox");
-        h.record("Firefox");
-        h.record("Visual Studio Code");
-        let reloaded = History::load(path.clone());
-        assert_eq!(reloaded.counts.get("Firefox"), Some(&2));
-        assert_eq!(reloaded.counts.len(), 2);
+        let mut h = History::load(path.clone(), Ranking::Count);
+        h.record("Firefox", "");
+        h.record("Firefox", "fi");
+        h.record("Visual Studio Code", "code");
+        let reloaded = History::load(path.clone(), Ranking::Count);
+        assert_eq!(reloaded.usage.get("Firefox").map(|u| u.count), Some(2));
+        assert_eq!(reloaded.usage.len(), 2);
         let _ = fs::remove_file(&path);
     }
 
@@ -115,9 +298,109 @@ mod tests {
     fn malformed_lines_are_skipped() {
         let path = temp_path("malformed");
         fs::write(&path, "3\tGood\nnot a line\n\t\nx\tBad count\n").unwrap();
-        let h = History::load(path.clone());
-        assert_eq!(h.counts.get("Good"), Some(&3));
-        assert_eq!(h.counts.len(), 1);
+        let h = History::load(path.clone(), Ranking::Count);
+        assert_eq!(h.usage.get("Good").map(|u| u.count), Some(3));
+        assert_eq!(h.usage.len(), 1);
+        let _ = fs::remove_file(&path);
+    }
+
+    #[test]
+    fn frecency_prefers_recent_over_old_heavy_use() {
+        let mut h = history(Ranking::Frecency, "frecency");
+        for _ in 0..300 {
+            h.record_at("Old Favorite", 0);
+        }
+        let now = 365 * DAY;
+        for day in 0..5 {
+            h.record_at("Daily Driver", now - day * DAY);
+        }
+        let mut list = entries(&["Daily Driver", "Old Favorite"]);
+        h.sort_entries_at(&mut list, now);
+        assert_eq!(list, vec!["Daily Driver", "Old Favorite"]);
+
+        // The plain tally still ranks the old favorite first
+        h.ranking = Ranking::Count;
+        h.sort_entries_at(&mut list, now);
+        assert_eq!(list, vec!["Old Favorite", "Daily Driver"]);
+    }
+
+    #[test]
+    fn frecency_score_halves_each_half_life() {
+        let mut h = history(Ranking::Frecency, "halflife");
+        h.record_at("App", 0);
+        h.record_at("App", 0);
+        let usage = &h.usage["App"];
+        assert!((usage.score_at(7 * DAY) - 1.0).abs() < 1e-9);
+        // Recording later adds to the decayed score, not the raw one
+        h.record_at("App", 7 * DAY);
+        assert!((h.usage["App"].score - 2.0).abs() < 1e-9);
+        assert_eq!(h.usage["App"].count, 3);
+    }
+
+    #[test]
+    fn v1_file_migrates_to_v3() {
+        let path = temp_path("migrate");
+        fs::write(&path, "4\tFirefox\n2\tPaint\n").unwrap();
+        let mut h = History::load(path.clone(), Ranking::Frecency);
+        assert_eq!(h.usage["Firefox"].count, 4);
+        assert!((h.usage["Firefox"].score - 4.0).abs() < 1e-9);
+
+        h.record("Paint", "");
+        let text = fs::read_to_string(&path).unwrap();
+        assert!(text.starts_with(HEADER_V3));
+        let reloaded = History::load(path.clone(), Ranking::Frecency);
+        assert_eq!(reloaded.usage["Firefox"].count, 4);
+        assert_eq!(reloaded.usage["Paint"].count, 3);
+        let _ = fs::remove_file(&path);
+    }
+
+    #[test]
+    fn v2_malformed_lines_are_skipped() {
+        let text = format!("{}\n3\t100\t1.5\tGood\n3\t100\tGood\nx\t1\t1\tBad\n", HEADER_V2);
+        let (usage, _) = parse(&text, 0);
+        assert_eq!(usage.len(), 1);
+        assert_eq!(usage["Good"], Usage { count: 3, last_used: 100, score: 1.5 });
+    }
+
+    #[test]
+    fn query_picks_boost_same_and_extended_queries() {
+        let mut h = history(Ranking::Count, "queries");
+        h.record_query("TeXstudio", "te");
+        h.record_query("TeXstudio", "Te ");
+        h.record_query("Terminal", "ter");
+
+        let weights = h.query_weights("te");
+        assert_eq!(weights.get("TeXstudio"), Some(&2.0));
+        assert_eq!(weights.get("Terminal"), None); // "ter" extends "te", not the reverse
+
+        // An extended query still remembers "te", at the share it covers
+        let weights = h.query_weights("tex");
+        assert!((weights["TeXstudio"] - 2.0 * 2.0 / 3.0).abs() < 1e-9);
+        let weights = h.query_weights("term");
+        assert!((weights["Terminal"] - 0.75).abs() < 1e-9);
+
+        assert!(h.query_weights("").is_empty());
+        assert!(h.query_weights("x").is_empty());
+    }
+
+    #[test]
+    fn completed_names_are_not_learned() {
+        let mut h = history(Ranking::Count, "completed");
+        h.record_query("Paint", "paint");
+        h.record_query("Paint", "");
+        assert!(h.queries.is_empty());
+    }
+
+    #[test]
+    fn query_pairs_persist() {
+        let path = temp_path("query-roundtrip");
+        let _ = fs::remove_file(&path);
+        let mut h = History::load(path.clone(), Ranking::Count);
+        h.record("TeXstudio", "te");
+        h.record("TeXstudio", "te");
+        let reloaded = History::load(path.clone(), Ranking::Count);
+        assert_eq!(reloaded.queries.get(&("te".to_string(), "TeXstudio".to_string())), Some(&2));
+        assert_eq!(reloaded.usage["TeXstudio"].count, 2);
         let _ = fs::remove_file(&path);
     }
 }
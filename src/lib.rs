@@ -0,0 +1,9 @@
+//! The platform-neutral parts of windmenu: menu filtering and selection,
+//! selection history, config color syntax and picking the theme for the app
+//! mode. Nothing here touches Win32, so their tests run with
+//! `cargo test --lib` on any host; the binary in `main.rs` builds on them.
+
+pub mod appmode;
+pub mod color;
+pub mod engine;
+pub mod history;
@@ -10,13 +10,19 @@ use std::thread;
 use clap::{CommandFactory, Parser, Subcommand};
 
 mod apps;
+mod check;
 mod daemon;
+mod dmenu;
 mod doctor;
-mod history;
 mod menu;
+mod rasi;
+mod scheme;
 mod theme;
+mod wallpaper;
+mod watch;
 mod wlines;
 
+use windmenu::{appmode, color, engine, history};
 use daemon::{DaemonError, WindmenuDaemon};
 use apps::print_reparse_points_info;
 use menu::Menu;
@@ -30,6 +36,10 @@ struct Cli {
     #[arg(long, hide = true)]
     start_daemon_self_detached: bool,
 
+    /// Config file to use, ahead of WINDMENU_CONFIG and the usual locations
+    #[arg(long, global = true, value_name = "PATH")]
+    config: Option<PathBuf>,
+
     #[command(subcommand)]
     command: Option<Commands>,
 }
@@ -52,11 +62,32 @@ enum Commands {
     /// enabled (with ready-to-paste PowerShell commands to enable or disable
     /// auto-start).
     Doctor,
+    /// Read entries from stdin and print the selected one (dmenu-compatible)
+    ///
+    /// Shows the menu with one entry per stdin line and writes the chosen line
+    /// (or the Shift+Enter custom text) to stdout; exits with status 1 when
+    /// cancelled. Accepts dmenu's flags: -b, -i, -l <lines>, -p <prompt>,
+    /// -fn <font>, -nb/-nf/-sb/-sf <color> (-f, -m and -w are ignored).
+    Dmenu {
+        /// dmenu flags, passed through verbatim
+        #[arg(allow_hyphen_values = true, trailing_var_arg = true)]
+        args: Vec<String>,
+    },
     /// Manage the windmenu.toml configuration
     Config {
         #[command(subcommand)]
         action: ConfigAction,
     },
+    /// Work with color themes
+    Theme {
+        #[command(subcommand)]
+        action: ThemeAction,
+    },
+    /// Inspect the packs the config imports
+    Pack {
+        #[command(subcommand)]
+        action: PackAction,
+    },
     /// Test utilities
     Test {
         #[command(subcommand)]
@@ -66,7 +97,7 @@ enum Commands {
 
 #[derive(Subcommand)]
 enum ConfigAction {
-    /// Write a default windmenu.toml next to the executable
+    /// Write a default windmenu.toml (per-user, unless --config names one)
     Init {
         /// Overwrite an existing config file
         #[arg(long)]
@@ -74,6 +105,59 @@ enum ConfigAction {
     },
     /// Open the config in an editor (creating it if needed)
     Edit,
+    /// Print the config in effect
+    ///
+    /// With --resolved, prints the effective configuration instead: imports
+    /// merged, theme applied and defaults filled in, each value annotated with
+    /// where it came from (built-in default, windmenu.toml, a named theme, or
+    /// an import file).
+    Show {
+        /// Print the merged, effective configuration with origins
+        #[arg(long)]
+        resolved: bool,
+        /// Print the resolved configuration as JSON
+        #[arg(long, requires = "resolved")]
+        json: bool,
+    },
+    /// Strictly validate the config and its imports
+    ///
+    /// Reports unknown keys (with suggestions), invalid colors, bad key names,
+    /// unknown themes and broken imports with file, line and column. Exits
+    /// nonzero if anything is found; normal startup stays lenient.
+    Check,
+}
+
+#[derive(Subcommand)]
+enum ThemeAction {
+    /// Convert a Windows Terminal, base16 or rofi color scheme into a theme pack
+    ///
+    /// Reads a Windows Terminal settings.json (every entry of "schemes", or a
+    /// single scheme object), a base16 .yaml scheme or a rofi .rasi theme,
+    /// maps its colors onto windmenu's palette keys and writes a pack with one
+    /// [themes.<name>] per scheme, ready for `import`.
+    Import {
+        /// Scheme file (.json for Windows Terminal, .yaml/.yml for base16, .rasi for rofi)
+        file: PathBuf,
+        /// Theme name to use (only for a file with a single scheme)
+        #[arg(long)]
+        name: Option<String>,
+        /// Where to write the pack (default: themes/<name>.toml beside the config)
+        #[arg(long)]
+        output: Option<PathBuf>,
+        /// Overwrite an existing pack file
+        #[arg(long)]
+        force: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum PackAction {
+    /// List imported packs with their metadata and contributions
+    ///
+    /// Shows each pack in merge order with the name, version, author and
+    /// minimum windmenu version from its [pack] header, and the themes and
+    /// commands it contributes.
+    List,
 }
 
 #[derive(Subcommand)]
@@ -224,11 +308,18 @@ fn main() {
         cli_exit(e.exit_code());
     });
 
-    // Resolve stable windmenu path: prefer PATH (Scoop shim) over resolved current_exe
-    let windmenu_path = find_on_path("windmenu.exe")
-        .unwrap_or_else(|| current_exe.clone());
+    if let Some(path) = &cli.config {
+        menu::set_config_flag(path);
+    }
 
-    let windmenu_daemon = WindmenuDaemon::new(&windmenu_path);
+    // Resolve stable windmenu path: prefer PATH (Scoop shim) over resolved
+    // current_exe. Lazy, so commands that never touch the daemon (dmenu mode in
+    // a script) don't pay for a where.exe spawn.
+    let windmenu_daemon = || {
+        let windmenu_path = find_on_path("windmenu.exe")
+            .unwrap_or_else(|| current_exe.clone());
+        WindmenuDaemon::new(&windmenu_path)
+    };
 
     if cli.start_daemon_self_detached {
         // This is the background daemon process
@@ -237,13 +328,31 @@ fn main() {
     }
 
     match cli.command {
-        Some(Commands::Start) => start_daemon(&windmenu_daemon),
-        Some(Commands::Stop) => stop_daemon(&windmenu_daemon),
-        Some(Commands::Restart) => restart_daemon(&windmenu_daemon),
-        Some(Commands::Doctor) => doctor::run(&windmenu_daemon),
+        Some(Commands::Start) => start_daemon(&windmenu_daemon()),
+        Some(Commands::Stop) => stop_daemon(&windmenu_daemon()),
+        Some(Commands::Restart) => restart_daemon(&windmenu_daemon()),
+        Some(Commands::Doctor) => doctor::run(&windmenu_daemon()),
+        Some(Commands::Dmenu { args }) => {
+            let code = dmenu::run(&args);
+            if code != 0 {
+                cli_exit(code);
+            }
+        }
         Some(Commands::Config { action }) => {
             handle_config_command(action);
         }
+        Some(Commands::Theme { action: ThemeAction::Import { file, name, output, force } }) => {
+            let code = scheme::import(&file, name.as_deref(), output.as_deref(), force);
+            if code != 0 {
+                cli_exit(code);
+            }
+        }
+        Some(Commands::Pack { action: PackAction::List }) => {
+            let code = menu::pack_list();
+            if code != 0 {
+                cli_exit(code);
+            }
+        }
         Some(Commands::Test { test_type }) => {
             handle_test_command(test_type);
         }
@@ -306,6 +415,8 @@ fn handle_config_command(action: ConfigAction) {
     let code = match action {
         ConfigAction::Init { force } => menu::config_init(force),
         ConfigAction::Edit => menu::config_edit(),
+        ConfigAction::Show { resolved, json } => menu::config_show(resolved, json),
+        ConfigAction::Check => check::run(),
     };
     if code != 0 {
         cli_exit(code);
@@ -344,6 +455,9 @@ fn start_daemon_self_detached() {
         entries_bg.write().unwrap().rescan_dynamic();
     });
 
+    let menu_watch = menu.clone();
+    thread::spawn(move || menu_watch.watch_config());
+
     menu.hotkey.listen(|| {
         if let Err(e) = menu.clone().show() {
             eprintln!("Menu show error: {}", e);
@@ -1,23 +1,23 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::env;
 use std::fs;
 use std::path::{Path, PathBuf};
-use std::sync::{Arc, Mutex, RwLock};
-use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, OnceLock, RwLock};
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
 use std::process::{Command, Stdio};
 use std::thread;
 use std::ffi::OsStr;
 use std::os::windows::ffi::OsStrExt;
 use std::os::windows::process::CommandExt;
-use std::fmt;
-use serde::Deserialize;
+use std::fmt::{self, Write as _};
+use serde::{Deserialize, Serialize};
 use winapi::um::errhandlingapi::GetLastError;
 use winapi::um::shellapi::ShellExecuteW;
 use winapi::um::winuser::{
-    DispatchMessageW, MessageBoxW, MsgWaitForMultipleObjects, PeekMessageW, RegisterHotKey,
-    SendInput, TranslateMessage, INPUT, INPUT_KEYBOARD, MB_ICONERROR, MB_OK, MOD_ALT,
-    MOD_CONTROL, MOD_NOREPEAT, MOD_SHIFT, MOD_WIN, MSG, PM_REMOVE, QS_ALLINPUT,
-    SW_RESTORE, WM_HOTKEY, KEYBDINPUT, KEYEVENTF_KEYUP,
+    DispatchMessageW, MessageBoxW, MsgWaitForMultipleObjects, PeekMessageW, PostThreadMessageW,
+    RegisterHotKey, SendInput, TranslateMessage, UnregisterHotKey, INPUT, INPUT_KEYBOARD,
+    MB_ICONERROR, MB_OK, MOD_ALT, MOD_CONTROL, MOD_NOREPEAT, MOD_SHIFT, MOD_WIN, MSG,
+    PM_REMOVE, QS_ALLINPUT, SW_RESTORE, WM_APP, WM_HOTKEY, KEYBDINPUT, KEYEVENTF_KEYUP,
     VK_MENU, VK_SHIFT, VK_CAPITAL, VK_CONTROL, VK_TAB, VK_ESCAPE, VK_LWIN, VK_SPACE,
     VK_RETURN, VK_LEFT, VK_UP, VK_RIGHT, VK_DOWN, VK_F1, VK_F2, VK_F3, VK_F4, VK_F5,
     VK_F6, VK_F7, VK_F8, VK_F9, VK_F10, VK_F11, VK_F12, VK_OEM_COMMA, VK_OEM_PERIOD,
@@ -26,11 +26,17 @@ use winapi::um::winuser::{
 };
 use winapi::um::winbase::{CREATE_NEW_PROCESS_GROUP, INFINITE, WAIT_OBJECT_0};
 use winapi::um::handleapi::CloseHandle;
+use winapi::um::processthreadsapi::GetCurrentThreadId;
 use winapi::um::synchapi::CreateEventW;
 
 use crate::apps::{find_reparse_points, get_windows_apps_path};
-use crate::history::{History, HISTORY_FILE};
-use crate::theme::{self, Palette};
+use crate::history::{History, Ranking, HISTORY_FILE};
+use crate::color;
+use crate::doctor::Registry;
+use crate::appmode::{self, AppMode, ThemeSetting};
+use crate::theme::{self, LowContrast, Palette};
+use crate::wallpaper;
+use crate::watch;
 use crate::wlines;
 
 #[derive(Debug)]
@@ -76,6 +82,10 @@ pub(crate) struct EntryStore {
     builtins: HashMap<String, MenuCommand>,
     config: HashMap<String, MenuCommand>,
     dynamic: HashMap<String, MenuCommand>,
+    // Hidden search terms by entry name: from `[[commands]]` aliases/keywords
+    // and from the `[aliases]` table (which also covers discovered apps)
+    command_aliases: HashMap<String, Vec<String>>,
+    alias_table: HashMap<String, Vec<String>>,
 }
 
 impl EntryStore {
@@ -84,36 +94,49 @@ impl EntryStore {
         builtins.insert("Toggle Caps Lock".to_string(), MenuCommand::ToggleCapsLock);
         builtins.insert("Refresh Apps".to_string(), MenuCommand::RefreshApps);
         builtins.insert("Reload Config".to_string(), MenuCommand::ReloadConfig);
-        Self { builtins, config: HashMap::new(), dynamic: HashMap::new() }
+        Self {
+            builtins,
+            config: HashMap::new(),
+            dynamic: HashMap::new(),
+            command_aliases: HashMap::new(),
+            alias_table: HashMap::new(),
+        }
     }
 
     fn apply_config_commands(&mut self, cmds: Vec<CommandConfig>) {
         let mut config = HashMap::new();
+        let mut command_aliases = HashMap::new();
         for cmd in cmds {
-            let (key, command) = match cmd.command_type {
-                CommandType::Args { args } => (cmd.name, MenuCommand::Configured(args)),
-                CommandType::Keys { keys } => {
-                    let key_sequence = keys.join(", ");
-                    let formatted_key = format!("{} [{}]", cmd.name, key_sequence);
-                    (formatted_key, MenuCommand::KeyCombo(keys))
-                }
+            let key = cmd.entry_name();
+            let terms: Vec<String> = cmd.aliases.into_iter().chain(cmd.keywords).collect();
+            let command = match cmd.command_type {
+                CommandType::Args { args } => MenuCommand::Configured(args),
+                CommandType::Keys { keys } => MenuCommand::KeyCombo(keys),
             };
+            if terms.is_empty() {
+                command_aliases.remove(&key);
+            } else {
+                command_aliases.insert(key.clone(), terms);
+            }
             config.insert(key, command);
         }
         self.config = config;
+        self.command_aliases = command_aliases;
     }
 
-    pub(crate)     fn reload_config(&mut self) {
-        if let Ok((cfg, _dir, warnings)) = load_with_imports() {
-            for warning in warnings {
-                eprintln!("Warning: {}", warning);
-            }
-            if let Some(cmds) = cfg.commands {
-                self.apply_config_commands(cmds);
-            } else {
-                self.config.clear();
-            }
+    /// Hidden search terms for every entry that has any.
+    fn aliases(&self) -> HashMap<String, Vec<String>> {
+        let mut aliases = self.command_aliases.clone();
+        for (name, terms) in &self.alias_table {
+            aliases.entry(name.clone()).or_default().extend(terms.iter().cloned());
         }
+        aliases
+    }
+
+    /// Replace the configured commands and the `[aliases]` table.
+    fn apply_config(&mut self, commands: Option<Vec<CommandConfig>>, aliases: Option<HashMap<String, Vec<String>>>) {
+        self.apply_config_commands(commands.unwrap_or_default());
+        self.alias_table = aliases.unwrap_or_default();
     }
 
     pub(crate) fn rescan_dynamic(&mut self) {
@@ -160,22 +183,56 @@ impl EntryStore {
     }
 }
 
+/// The activation hotkey. The keys can change while `listen` runs (config
+/// reload); the listening thread re-registers them, since a hotkey belongs to
+/// the thread that registered it.
 #[derive(Debug)]
 pub struct Hotkey {
-    keys: Vec<String>,
+    keys: Mutex<Vec<String>>,
+    // The listening thread, 0 until `listen` starts
+    thread: AtomicU32,
 }
 
 impl Hotkey {
     const HOTKEY_ID: i32 = 1;
+    // Posted to the listening thread when the keys change
+    const WM_REREGISTER: u32 = WM_APP;
+
+    fn new(keys: Vec<String>) -> Hotkey {
+        Hotkey { keys: Mutex::new(keys), thread: AtomicU32::new(0) }
+    }
+
+    /// Ctrl+Alt+Space
+    fn default_keys() -> Vec<String> {
+        vec!["CTRL".to_string(), "ALT".to_string(), "SPACE".to_string()]
+    }
+
+    /// Switch to new keys. Takes effect on the listening thread; if the new
+    /// combo is invalid or taken, the old one stays registered with a warning.
+    fn set_keys(&self, keys: Vec<String>) {
+        {
+            let mut current = self.keys.lock().unwrap();
+            if *current == keys {
+                return;
+            }
+            *current = keys;
+        }
+        let thread = self.thread.load(Ordering::SeqCst);
+        if thread != 0 {
+            unsafe {
+                PostThreadMessageW(thread, Self::WM_REREGISTER, 0, 0);
+            }
+        }
+    }
 
     /// Map the configured keys to a RegisterHotKey (modifiers, vk) pair.
     /// Valid combos are any number of modifiers (WIN/CTRL/ALT/SHIFT) plus
     /// exactly one other key.
-    fn to_registration(&self) -> Result<(u32, u32), MenuError> {
+    pub(crate) fn to_registration(keys: &[String]) -> Result<(u32, u32), MenuError> {
         let mut modifiers = MOD_NOREPEAT;
         let mut vk: Option<u32> = None;
 
-        for key in &self.keys {
+        for key in keys {
             match key.to_uppercase().as_str() {
                 "WIN" | "WINDOWS" => modifiers |= MOD_WIN,
                 "CTRL" | "CONTROL" => modifiers |= MOD_CONTROL,
@@ -187,7 +244,7 @@ impl Hotkey {
                         return Err(MenuError::InvalidArguments(format!(
                             "hotkey {:?} has more than one non-modifier key; \
                              use any number of WIN/CTRL/ALT/SHIFT plus exactly one other key",
-                            self.keys
+                            keys
                         )));
                     }
                     vk = Some(code as u32);
@@ -200,7 +257,7 @@ impl Hotkey {
             None => Err(MenuError::InvalidArguments(format!(
                 "hotkey {:?} has no non-modifier key; \
                  use any number of WIN/CTRL/ALT/SHIFT plus exactly one other key",
-                self.keys
+                keys
             ))),
         }
     }
@@ -214,20 +271,15 @@ impl Hotkey {
     where
         F: FnMut(),
     {
-        let (modifiers, vk) = self.to_registration().unwrap_or_else(|e| {
-            Self::fatal(&format!("Invalid hotkey configuration: {}", e));
-        });
+        // Set before registering, so a reload racing with startup still
+        // gets its WM_REREGISTER delivered to this thread's queue
+        self.thread.store(unsafe { GetCurrentThreadId() }, Ordering::SeqCst);
+        let mut registered = self.keys.lock().unwrap().clone();
+        if let Err(e) = Self::register(&registered) {
+            Self::fatal(&e);
+        }
 
         unsafe {
-            if RegisterHotKey(std::ptr::null_mut(), Self::HOTKEY_ID, modifiers, vk) == 0 {
-                Self::fatal(&format!(
-                    "Failed to register hotkey {} (error {}). \
-                     Another application may already use this combo; \
-                     change 'hotkey' in windmenu.toml.",
-                    self.keys.join("+"),
-                    GetLastError()
-                ));
-            }
 
             let event_name: Vec<u16> = "windmenu-shutdown-event\0".encode_utf16().collect();
             let shutdown_event = CreateEventW(
@@ -240,7 +292,7 @@ impl Hotkey {
                 Self::fatal("Failed to create shutdown event");
             }
 
-            println!("Hotkey registered ({})", self.keys.join("+"));
+            println!("Hotkey registered ({})", registered.join("+"));
             let mut msg: MSG = std::mem::zeroed();
             loop {
                 let result = MsgWaitForMultipleObjects(
@@ -259,6 +311,8 @@ impl Hotkey {
                     while PeekMessageW(&mut msg, std::ptr::null_mut(), 0, 0, PM_REMOVE) != 0 {
                         if msg.message == WM_HOTKEY && msg.wParam == Self::HOTKEY_ID as usize {
                             callback();
+                        } else if msg.message == Self::WM_REREGISTER {
+                            self.reregister(&mut registered);
                         }
                         TranslateMessage(&msg);
                         DispatchMessageW(&msg);
@@ -272,6 +326,53 @@ impl Hotkey {
         }
     }
 
+    /// Register `keys` as the hotkey on the calling thread.
+    fn register(keys: &[String]) -> Result<(), String> {
+        let (modifiers, vk) = Self::to_registration(keys)
+            .map_err(|e| format!("Invalid hotkey configuration: {}", e))?;
+        unsafe {
+            if RegisterHotKey(std::ptr::null_mut(), Self::HOTKEY_ID, modifiers, vk) == 0 {
+                return Err(format!(
+                    "Failed to register hotkey {} (error {}). \
+                     Another application may already use this combo; \
+                     change 'hotkey' in windmenu.toml.",
+                    keys.join("+"),
+                    GetLastError()
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Swap the registration for the current keys, keeping the `registered`
+    /// ones (and warning) when the new combo can't be registered.
+    fn reregister(&self, registered: &mut Vec<String>) {
+        let keys = self.keys.lock().unwrap().clone();
+        if keys == *registered {
+            return;
+        }
+        unsafe {
+            UnregisterHotKey(std::ptr::null_mut(), Self::HOTKEY_ID);
+        }
+        match Self::register(&keys) {
+            Ok(()) => {
+                println!("Hotkey registered ({})", keys.join("+"));
+                *registered = keys;
+            }
+            Err(e) => {
+                let message = format!("{} Keeping {}.", e, registered.join("+"));
+                eprintln!("Warning: {}", message);
+                error_box(&message);
+                if Self::register(registered).is_err() {
+                    Self::fatal(&format!("Failed to restore hotkey {}", registered.join("+")));
+                }
+                // Back in step with what is registered, so fixing the config
+                // and reloading again retries
+                *self.keys.lock().unwrap() = registered.clone();
+            }
+        }
+    }
+
     /// Report a fatal daemon error and exit. Uses a message box since the
     /// detached daemon process has stdout/stderr redirected to null.
     fn fatal(message: &str) -> ! {
@@ -295,8 +396,8 @@ pub fn error_box(message: &str) {
 /// overrides all sit at the top level; named themes live under `[themes.<name>]`
 /// and are selected by `theme = "<name>"`. Unknown/legacy keys are ignored
 /// (no `deny_unknown_fields`), so old sectioned configs degrade to defaults.
-#[derive(Debug, Deserialize)]
-struct MenuConfig {
+#[derive(Debug, Default, Deserialize)]
+pub(crate) struct MenuConfig {
     hotkey: Option<Vec<String>>, // Custom hotkey keys (e.g., ["WIN", "SPACE"])
 
     // In-menu navigation combos (CTRL/SHIFT + one key). Default Ctrl+J / Ctrl+K.
@@ -306,7 +407,8 @@ struct MenuConfig {
     // Search behavior (rofi's -matching / -case-sensitive).
     matching: Option<String>,     // "complete" / "keywords" / "fuzzy"
     case_sensitive: Option<bool>, // Match case exactly (default: false)
-    history: Option<bool>,        // Order entries by selection frequency (default: true)
+    normalize: Option<bool>,      // Ignore accents, full-width forms, ligatures (default: false)
+    history: Option<HistoryConfig>, // true / false / "frecency" (default: true, most-used-first)
 
     // Window geometry and font.
     horizontal: Option<bool>, // Single-row bar; entries flow left-to-right, `lines` is ignored
@@ -314,30 +416,89 @@ struct MenuConfig {
     width: Option<usize>,   // Fixed window width in px (0 = full monitor width)
     center: Option<bool>,   // Center the window on the monitor (default: true; false = top-left)
     padding: Option<usize>, // Window padding
-    font: Option<String>,   // Font as "Family Size", e.g. "Consolas 18"
+    border_width: Option<usize>, // Window border in px, in the `border` color (default: 0, none)
+    font: Option<String>,   // Font as "Family [Weight] [Italic], Fallback Size", e.g. "Consolas 18"
+    font_quality: Option<String>, // "cleartype" / "antialiased" / "none" (default: "antialiased")
     prompt: Option<String>, // Text shown in the input box
 
     // Color scheme: pick a named theme, then override individual keys.
-    theme: Option<String>,               // Selects [themes.<name>]
+    theme: Option<ThemeSetting>,         // Selects [themes.<name>], or one per app mode / "auto"
     #[serde(flatten)]
     colors: Palette,                     // Top-level color overrides
     themes: Option<HashMap<String, Palette>>,
+    min_contrast: Option<f64>,           // Warn about text below this WCAG contrast ratio (default: 3)
 
     commands: Option<Vec<CommandConfig>>,
 
+    // Search terms for entries by display name, e.g. discovered Start Menu
+    // apps: "Windows Terminal" = ["wt", "console"]
+    aliases: Option<HashMap<String, Vec<String>>>,
+
     // Extra theme/command packs pulled in from other TOML files (paths relative
-    // to this config's directory). Non-recursive: packs cannot import further.
+    // to this config's directory, globs allowed).
     import: Option<Vec<String>>,
+    nested_imports: Option<bool>, // Follow the packs' own `import` too (default: false)
 }
 
 /// An imported pack: a TOML file that contributes only `[themes.*]` and/or
-/// `[[commands]]`. It has no `import` field, so nested imports are ignored
-/// (non-recursive); with no `deny_unknown_fields`, any stray settings a pack
-/// carries are ignored rather than silently overriding the root config.
+/// `[[commands]]`, optionally describes itself in `[pack]`, and may `import`
+/// further packs of its own when the root config sets `nested_imports`. With
+/// no `deny_unknown_fields`, any stray settings a pack carries are ignored
+/// rather than silently overriding the root config.
 #[derive(Debug, Deserialize, Default)]
-struct Pack {
+pub(crate) struct Pack {
     themes: Option<HashMap<String, Palette>>,
     commands: Option<Vec<CommandConfig>>,
+    // Nested imports, relative to this pack's own directory
+    import: Option<Vec<String>>,
+    pack: Option<PackInfo>,
+    // The `import` entry it was read from
+    #[serde(skip)]
+    origin: String,
+}
+
+/// A pack's optional `[pack]` header, so a machine can tell which revision of
+/// a pack it is running.
+#[derive(Debug, Deserialize, Default)]
+pub(crate) struct PackInfo {
+    name: Option<String>,
+    version: Option<String>,
+    author: Option<String>,
+    // Oldest windmenu release the pack works with
+    min_version: Option<String>,
+}
+
+/// A dotted version as numbers ("0.9" and "v0.9.0" both work). None when it
+/// isn't one.
+pub(crate) fn parse_version(s: &str) -> Option<Vec<u64>> {
+    let s = s.trim();
+    let s = s.strip_prefix('v').unwrap_or(s);
+    let mut parts: Vec<u64> = s.split('.').map(|p| p.parse().ok()).collect::<Option<_>>()?;
+    while parts.len() > 1 && parts.last() == Some(&0) {
+        parts.pop();
+    }
+    Some(parts)
+}
+
+/// Why a pack requiring `min_version` can't be trusted on this binary: too
+/// new a requirement, or one that isn't a version at all.
+pub(crate) fn min_version_problem(min_version: &str) -> Option<String> {
+    let running = env!("CARGO_PKG_VERSION");
+    match parse_version(min_version) {
+        None => Some(format!("min_version '{}' is not a version like \"0.9.0\"", min_version)),
+        Some(required) if parse_version(running).is_some_and(|r| required > r) => Some(format!(
+            "needs windmenu {} or newer, this is {}",
+            min_version, running
+        )),
+        Some(_) => None,
+    }
+}
+
+impl Pack {
+    /// The name a pack goes by: its `[pack] name`, else its file.
+    fn display_name(&self) -> &str {
+        self.pack.as_ref().and_then(|p| p.name.as_deref()).unwrap_or(&self.origin)
+    }
 }
 
 /// Fold imported packs into the root config. Root config wins over imports, and
@@ -370,22 +531,184 @@ fn merge_packs(cfg: &mut MenuConfig, packs: Vec<Pack>) {
     }
 }
 
-/// Read and parse each imported pack (path relative to `base_dir`). A missing or
-/// unparseable file warns and is skipped — imports never abort startup.
-fn read_packs(imports: &[String], base_dir: &Path) -> (Vec<Pack>, Vec<String>) {
-    let mut packs = Vec::new();
-    let mut warnings = Vec::new();
-    for rel in imports {
-        let path = base_dir.join(rel);
-        match fs::read_to_string(&path) {
-            Ok(text) => match toml::from_str::<Pack>(&text) {
-                Ok(pack) => packs.push(pack),
-                Err(e) => warnings.push(format!("import '{}' failed to parse: {}", rel, e)),
+/// The contrast ratio text must reach against its background before `doctor`
+/// and `config check` warn: WCAG's minimum for large text, which the built-in
+/// scheme meets.
+const DEFAULT_MIN_CONTRAST: f64 = 3.0;
+
+/// How deep packs may import packs that import packs.
+pub(crate) const MAX_IMPORT_DEPTH: usize = 8;
+
+/// What identifies an imported file for cycle and repeat checks: its
+/// canonical path, so `packs/../team.toml` is `team.toml`.
+pub(crate) fn import_key(path: &Path) -> PathBuf {
+    fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf())
+}
+
+fn is_glob(pattern: &str) -> bool {
+    pattern.contains(['*', '?'])
+}
+
+/// Match a file name against a pattern of `*` (any run) and `?` (any one
+/// char), ignoring case like Windows paths do.
+fn glob_match(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.to_lowercase().chars().collect();
+    let name: Vec<char> = name.to_lowercase().chars().collect();
+    let (mut p, mut n) = (0, 0);
+    // Where the last `*` was, and how much of the name it has swallowed
+    let mut star: Option<(usize, usize)> = None;
+    while n < name.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                star = Some((p, n));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == name[n] => {
+                p += 1;
+                n += 1;
+            }
+            _ => match star {
+                Some((sp, sn)) => {
+                    p = sp + 1;
+                    n = sn + 1;
+                    star = Some((sp, sn + 1));
+                }
+                None => return false,
             },
-            Err(_) => warnings.push(format!("import '{}' not found", rel)),
         }
     }
-    (packs, warnings)
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+/// The files an `import` entry names, relative to `base_dir`: a plain path as
+/// is (existing or not), a glob pattern as its matches in sorted order, so
+/// "later import wins" doesn't depend on directory listing order. Wildcards
+/// may appear in any path component; the components before the first one
+/// are joined as a plain path is, so absolute, UNC and `..` prefixes work.
+pub(crate) fn expand_import(base_dir: &Path, entry: &str) -> Vec<PathBuf> {
+    let Some(wildcard) = entry.find(['*', '?']) else {
+        return vec![base_dir.join(entry)];
+    };
+    let (literal, pattern) = entry.split_at(entry[..wildcard].rfind(['/', '\\']).map_or(0, |i| i + 1));
+    let components: Vec<&str> = pattern.split(['/', '\\']).filter(|c| !c.is_empty()).collect();
+    let mut matches = vec![base_dir.join(literal)];
+    for (i, component) in components.iter().enumerate() {
+        let last = i + 1 == components.len();
+        if !is_glob(component) {
+            matches = matches.into_iter().map(|m| m.join(component)).collect();
+            continue;
+        }
+        let mut next = Vec::new();
+        for dir in &matches {
+            for entry in fs::read_dir(dir).into_iter().flatten().flatten() {
+                let path = entry.path();
+                let wanted = if last { path.is_file() } else { path.is_dir() };
+                if wanted && glob_match(component, &entry.file_name().to_string_lossy()) {
+                    next.push(path);
+                }
+            }
+        }
+        matches = next;
+    }
+    matches.retain(|m| m.is_file());
+    matches.sort();
+    matches
+}
+
+/// Every pack the imports pull in, in merge order, with every file involved
+/// (for the watcher) and the warnings raised along the way.
+#[derive(Default)]
+struct Imports {
+    packs: Vec<Pack>,
+    files: Vec<PathBuf>,
+    warnings: Vec<String>,
+    // Canonical paths already read, so a pack imported twice loads once
+    seen: Vec<PathBuf>,
+    // Whether packs' own imports are followed (`nested_imports`)
+    nested: bool,
+}
+
+impl Imports {
+    /// Resolve `imports` against `base_dir`, depth-first: a pack's own imports
+    /// come before it, so it wins over them as the root config wins over its
+    /// packs. They are only followed when `nested` is set, and are otherwise
+    /// ignored as a pack's stray keys are. `root` names pack paths in
+    /// warnings and origins.
+    fn resolve(imports: &[String], base_dir: &Path, nested: bool) -> Imports {
+        let mut resolved = Imports { nested, ..Imports::default() };
+        resolved.read(imports, base_dir, base_dir, &mut Vec::new());
+        resolved
+    }
+
+    fn read(&mut self, imports: &[String], base_dir: &Path, root: &Path, stack: &mut Vec<(PathBuf, String)>) {
+        for entry in imports {
+            let paths = expand_import(base_dir, entry);
+            if is_glob(entry) {
+                // Watch the pattern itself too, so files appearing in its
+                // directory are noticed
+                self.files.push(base_dir.join(entry));
+                if paths.is_empty() {
+                    self.warnings.push(format!("import '{}' matched no files", entry));
+                }
+            }
+            for path in paths {
+                self.files.push(path.clone());
+                let name = path.strip_prefix(root).unwrap_or(&path).display().to_string();
+                let key = import_key(&path);
+                if let Some(start) = stack.iter().position(|(k, _)| *k == key) {
+                    let cycle: Vec<&str> = stack[start..].iter().map(|(_, n)| n.as_str()).collect();
+                    self.warnings.push(format!("import cycle {} -> {} skipped", cycle.join(" -> "), name));
+                    continue;
+                }
+                if self.seen.contains(&key) {
+                    continue;
+                }
+                if stack.len() >= MAX_IMPORT_DEPTH {
+                    self.warnings.push(format!(
+                        "import '{}' skipped: imports nest deeper than {} levels",
+                        name, MAX_IMPORT_DEPTH
+                    ));
+                    continue;
+                }
+                self.seen.push(key.clone());
+                let text = match fs::read_to_string(&path) {
+                    Ok(text) => text,
+                    Err(_) => {
+                        self.warnings.push(format!("import '{}' not found", name));
+                        continue;
+                    }
+                };
+                let mut pack = match toml::from_str::<Pack>(&text) {
+                    Ok(pack) => pack,
+                    Err(e) => {
+                        self.warnings.push(format!("import '{}' failed to parse: {}", name, e));
+                        continue;
+                    }
+                };
+                if let Some(min) = pack.pack.as_ref().and_then(|p| p.min_version.as_deref()) {
+                    if let Some(problem) = min_version_problem(min) {
+                        self.warnings.push(format!("pack '{}' {}", name, problem));
+                    }
+                }
+                if let Some(nested) = pack.import.take().filter(|_| self.nested) {
+                    let dir = path.parent().unwrap_or(base_dir).to_path_buf();
+                    stack.push((key, name.clone()));
+                    self.read(&nested, &dir, root, stack);
+                    stack.pop();
+                }
+                pack.origin = name;
+                self.packs.push(pack);
+            }
+        }
+    }
+}
+
+/// Read and parse each imported pack (path relative to `base_dir`) and, when
+/// `nested`, the packs they import. A missing or unparseable file, a cycle or
+/// too deep a nesting warns and is skipped — imports never abort startup.
+fn read_packs(imports: &[String], base_dir: &Path, nested: bool) -> (Vec<Pack>, Vec<String>) {
+    let resolved = Imports::resolve(imports, base_dir, nested);
+    (resolved.packs, resolved.warnings)
 }
 
 /// Load the root config and merge any `import`ed packs into it. Returns the
@@ -394,13 +717,27 @@ fn load_with_imports() -> Result<(MenuConfig, PathBuf, Vec<String>), MenuError>
     let (mut cfg, config_dir) = MenuConfig::load()?;
     let mut warnings = Vec::new();
     if let Some(imports) = cfg.import.take() {
-        let (packs, w) = read_packs(&imports, &config_dir);
+        let (packs, w) = read_packs(&imports, &config_dir, cfg.nested_imports.unwrap_or(false));
         warnings = w;
         merge_packs(&mut cfg, packs);
     }
     Ok((cfg, config_dir, warnings))
 }
 
+/// Every file whose changes should trigger a reload: every place the config
+/// may live (one appearing higher in the search order takes over) and the
+/// imports of the one in effect, missing ones included.
+pub fn config_files() -> Vec<PathBuf> {
+    let mut files: Vec<PathBuf> = config_candidates().into_iter().map(|(_, path)| path).collect();
+    if let Some(path) = MenuConfig::resolve_path() {
+        if let (Ok(cfg), Some(dir)) = (MenuConfig::load_from_file(&path), path.parent()) {
+            let nested = cfg.nested_imports.unwrap_or(false);
+            files.extend(Imports::resolve(&cfg.import.unwrap_or_default(), dir, nested).files);
+        }
+    }
+    files
+}
+
 /// Resolve a loaded config into renderer settings, starting from the built-in
 /// defaults. Returns any non-fatal warnings (e.g. an unknown theme name) so
 /// callers can surface them. A missing theme is not fatal: the launcher keeps
@@ -409,7 +746,7 @@ fn load_with_imports() -> Result<(MenuConfig, PathBuf, Vec<String>), MenuError>
 /// Accepts any mix of CTRL/SHIFT modifiers plus exactly one other key. ALT/WIN
 /// are rejected: those keydowns arrive as WM_SYSKEYDOWN, which the menu renderer
 /// doesn't handle, so they'd never fire.
-fn parse_key_combo(keys: &[String]) -> Result<wlines::KeyCombo, MenuError> {
+pub(crate) fn parse_key_combo(keys: &[String]) -> Result<wlines::KeyCombo, MenuError> {
     let mut ctrl = false;
     let mut shift = false;
     let mut vk: Option<u16> = None;
@@ -449,22 +786,35 @@ fn parse_key_combo(keys: &[String]) -> Result<wlines::KeyCombo, MenuError> {
     }
 }
 
-fn resolve_settings(cfg: &MenuConfig) -> (wlines::Settings, Vec<String>) {
+fn resolve_settings(cfg: &MenuConfig, mode: AppMode) -> (wlines::Settings, Vec<String>) {
     let mut settings = theme::default_settings();
     let mut warnings = Vec::new();
 
-    // 1. Named theme (if any), then 2. per-key overrides win over it.
-    // "default" is a reserved name for the built-in palette (already applied by
-    // default_settings above), so it always resolves silently even without a
-    // [themes.default] table; a user-defined [themes.default] still wins.
-    if let Some(ref name) = cfg.theme {
-        match cfg.themes.as_ref().and_then(|t| t.get(name)) {
-            Some(palette) => palette.apply(&mut settings),
-            None if name == "default" => {}
-            None => warnings.push(format!(
-                "theme '{}' not found in [themes.*] — using defaults",
-                name
-            )),
+    // 1. Named theme (if any, the one for `mode` when it follows the app
+    // mode), then 2. per-key overrides win over it.
+    // "default" and "default-light" are reserved names for the built-in
+    // palettes, so they always resolve silently even without a
+    // [themes.<name>] table, as does "wallpaper" for the desktop's colors; a
+    // user-defined table of that name still wins.
+    // A theme's `extends` chain is resolved first, across packs alike since
+    // they are merged by now.
+    if let Some(name) = cfg.theme.as_ref().map(|t| t.select(mode)) {
+        match cfg.themes.as_ref().and_then(|t| theme::resolve_theme(t, name)) {
+            Some((palette, theme_warnings)) => {
+                palette.apply(&mut settings);
+                warnings.extend(theme_warnings);
+            }
+            None => match theme::builtin_palette(name) {
+                Some(palette) => palette.apply(&mut settings),
+                None if name == theme::WALLPAPER => match wallpaper::current() {
+                    Ok((_, palette)) => palette.apply(&mut settings),
+                    Err(e) => warnings.push(format!("theme 'wallpaper': {} — using defaults", e)),
+                },
+                None => warnings.push(format!(
+                    "theme '{}' not found in [themes.*] — using defaults",
+                    name
+                )),
+            },
         }
     }
     cfg.colors.apply(&mut settings);
@@ -485,8 +835,21 @@ fn resolve_settings(cfg: &MenuConfig) -> (wlines::Settings, Vec<String>) {
     if let Some(padding) = cfg.padding {
         settings.padding = padding as i32;
     }
+    if let Some(border_width) = cfg.border_width {
+        settings.border_width = border_width as i32;
+    }
     if let Some(ref font) = cfg.font {
-        theme::apply_font(&mut settings, font);
+        theme::apply_font(&mut settings, font, &wlines::font_installed);
+    }
+    if let Some(ref quality) = cfg.font_quality {
+        match wlines::FontQuality::parse(quality) {
+            Some(quality) => settings.font_quality = quality,
+            None => warnings.push(format!(
+                "font_quality '{}' is not one of {} — using \"antialiased\"",
+                quality,
+                wlines::FontQuality::NAMES.join(", ")
+            )),
+        }
     }
     if let Some(ref prompt) = cfg.prompt {
         settings.prompt = Some(prompt.clone());
@@ -499,6 +862,9 @@ fn resolve_settings(cfg: &MenuConfig) -> (wlines::Settings, Vec<String>) {
     if let Some(case_sensitive) = cfg.case_sensitive {
         settings.case_sensitive = case_sensitive;
     }
+    if let Some(normalize) = cfg.normalize {
+        settings.normalize = normalize;
+    }
 
     // 5. Navigation combos: warn and keep the default (Ctrl+J/K) on bad config.
     for (label, keys, slot) in [
@@ -516,10 +882,172 @@ fn resolve_settings(cfg: &MenuConfig) -> (wlines::Settings, Vec<String>) {
     (settings, warnings)
 }
 
+/// Settings for the dark app mode, and for the light one when the theme
+/// follows the mode.
+struct ModeSettings {
+    dark: wlines::Settings,
+    light: Option<wlines::Settings>,
+    wallpaper: Option<Wallpapered>,
+}
+
+/// What a theme taken from the wallpaper needs to follow it as it changes:
+/// the modes using it, the palette resolved with, and the root color
+/// overrides to lay over a new one.
+struct Wallpapered {
+    modes: Vec<AppMode>,
+    palette: Option<Palette>,
+    overrides: Palette,
+}
+
+impl ModeSettings {
+    /// Resolve `cfg` for each mode its theme distinguishes. A warning both
+    /// give is listed once.
+    fn resolve(cfg: &MenuConfig) -> (ModeSettings, Vec<String>) {
+        let (dark, mut warnings) = resolve_settings(cfg, AppMode::Dark);
+        let light = cfg.theme.as_ref().filter(|t| t.follows_mode()).map(|_| {
+            let (light, light_warnings) = resolve_settings(cfg, AppMode::Light);
+            for warning in light_warnings {
+                if !warnings.contains(&warning) {
+                    warnings.push(warning);
+                }
+            }
+            light
+        });
+        let own_table = cfg.themes.as_ref().is_some_and(|t| t.contains_key(theme::WALLPAPER));
+        let modes: Vec<AppMode> = [AppMode::Dark, AppMode::Light]
+            .into_iter()
+            .filter(|&mode| cfg.theme.as_ref().is_some_and(|t| t.select(mode) == theme::WALLPAPER) && !own_table)
+            .collect();
+        let wallpaper = (!modes.is_empty()).then(|| Wallpapered {
+            modes,
+            palette: wallpaper::current().ok().map(|(_, palette)| palette),
+            overrides: cfg.colors.clone(),
+        });
+        (ModeSettings { dark, light, wallpaper }, warnings)
+    }
+
+    /// The settings for the app mode Windows is in now, in the wallpaper's
+    /// colors as they are now when the theme follows it.
+    fn current(&self) -> wlines::Settings {
+        let mode = match &self.light {
+            Some(_) => appmode::app_mode(&Registry),
+            None => AppMode::Dark,
+        };
+        let mut settings = match (&self.light, mode) {
+            (Some(light), AppMode::Light) => light.clone(),
+            _ => self.dark.clone(),
+        };
+        if let Some(wallpapered) = self.wallpaper.as_ref().filter(|w| w.modes.contains(&mode)) {
+            if let Ok((_, palette)) = wallpaper::current() {
+                if wallpapered.palette.as_ref() != Some(&palette) {
+                    palette.apply(&mut settings);
+                    wallpapered.overrides.apply(&mut settings);
+                }
+            }
+        }
+        settings
+    }
+}
+
+/// Resolve renderer settings from the config on disk (imports included) for a
+/// one-shot menu outside the daemon, such as `windmenu dmenu`. Warnings go to
+/// stderr; with no usable config the built-in defaults are returned.
+pub fn load_settings() -> wlines::Settings {
+    match load_with_imports() {
+        Ok((cfg, _dir, import_warnings)) => {
+            let (settings, warnings) = resolve_settings(&cfg, appmode::app_mode(&Registry));
+            for warning in import_warnings.iter().chain(warnings.iter()) {
+                eprintln!("Warning: {}", warning);
+            }
+            settings
+        }
+        Err(_) => theme::default_settings(),
+    }
+}
+
 /// The commented default config, embedded at compile time. This is the same
 /// file that ships in the repo, so `config init` produces a byte-identical,
 /// fully documented windmenu.toml — the binary carries its own config template.
-const DEFAULT_CONFIG: &str = include_str!("../windmenu.toml");
+pub(crate) const DEFAULT_CONFIG: &str = include_str!("../windmenu.toml");
+
+/// Environment variable naming the config file to use.
+pub const CONFIG_ENV: &str = "WINDMENU_CONFIG";
+
+/// The `--config` path, set once at startup.
+static CONFIG_FLAG: OnceLock<PathBuf> = OnceLock::new();
+
+/// Use `path` as the config file, ahead of everything else. Relative paths
+/// are taken against the current directory (the daemon runs elsewhere).
+pub fn set_config_flag(path: &Path) {
+    let path = env::current_dir().map(|dir| dir.join(path)).unwrap_or_else(|_| path.to_path_buf());
+    let _ = CONFIG_FLAG.set(path);
+}
+
+/// The `--config` path, if given, to hand on to the daemon process.
+pub fn config_flag() -> Option<&'static Path> {
+    CONFIG_FLAG.get().map(PathBuf::as_path)
+}
+
+/// Where a config file can come from, in precedence order.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum ConfigSource {
+    Flag,
+    Env,
+    Cwd,
+    User,
+    Exe,
+}
+
+impl ConfigSource {
+    /// `--config` and WINDMENU_CONFIG name the file outright: it is used
+    /// (or created) even when it doesn't exist yet, never skipped.
+    fn explicit(self) -> bool {
+        matches!(self, ConfigSource::Flag | ConfigSource::Env)
+    }
+
+    fn label(self) -> &'static str {
+        match self {
+            ConfigSource::Flag => "--config",
+            ConfigSource::Env => CONFIG_ENV,
+            ConfigSource::Cwd => "CWD",
+            ConfigSource::User => "User (%APPDATA%\\windmenu)",
+            ConfigSource::Exe => "Exe dir",
+        }
+    }
+}
+
+/// Every place a config may come from, in precedence order: `--config`,
+/// WINDMENU_CONFIG, the current directory (portable installs), the per-user
+/// `%APPDATA%\windmenu` directory, then the executable's directory (Scoop).
+fn config_candidates() -> Vec<(ConfigSource, PathBuf)> {
+    let file = MenuConfig::DEFAULT_CONFIG_PATH;
+    let mut candidates = Vec::new();
+    if let Some(path) = config_flag() {
+        candidates.push((ConfigSource::Flag, path.to_path_buf()));
+    }
+    if let Some(path) = env::var_os(CONFIG_ENV).filter(|v| !v.is_empty()) {
+        candidates.push((ConfigSource::Env, PathBuf::from(path)));
+    }
+    if let Ok(dir) = env::current_dir() {
+        candidates.push((ConfigSource::Cwd, dir.join(file)));
+    }
+    if let Some(dir) = env::var_os("APPDATA") {
+        candidates.push((ConfigSource::User, PathBuf::from(dir).join("windmenu").join(file)));
+    }
+    if let Some(dir) = env::current_exe().ok().and_then(|p| p.parent().map(Path::to_path_buf)) {
+        candidates.push((ConfigSource::Exe, dir.join(file)));
+    }
+    candidates
+}
+
+/// The candidate in effect: the first explicit one, else the first that
+/// exists.
+fn choose_config(
+    candidates: Vec<(ConfigSource, PathBuf)>,
+    exists: impl Fn(&Path) -> bool,
+) -> Option<(ConfigSource, PathBuf)> {
+    candidates.into_iter().find(|(source, path)| source.explicit() || exists(path))
+}
 
 impl MenuConfig {
     const DEFAULT_CONFIG_PATH: &'static str = "windmenu.toml";
@@ -532,23 +1060,10 @@ impl MenuConfig {
         Ok(config)
     }
 
-    /// Resolve the config file to use: CWD first (portable installs), then the
-    /// executable's directory (Scoop installs). None if neither exists.
-    fn resolve_path() -> Option<PathBuf> {
-        let cwd_path = Path::new(Self::DEFAULT_CONFIG_PATH);
-        if cwd_path.exists() {
-            let dir = env::current_dir().unwrap_or_else(|_| PathBuf::from("."));
-            return Some(dir.join(Self::DEFAULT_CONFIG_PATH));
-        }
-        if let Ok(exe_path) = env::current_exe() {
-            if let Some(exe_dir) = exe_path.parent() {
-                let exe_config = exe_dir.join(Self::DEFAULT_CONFIG_PATH);
-                if exe_config.exists() {
-                    return Some(exe_config);
-                }
-            }
-        }
-        None
+    /// Resolve the config file to use, following `config_candidates`. None
+    /// if nothing is named explicitly and no candidate exists.
+    pub(crate) fn resolve_path() -> Option<PathBuf> {
+        choose_config(config_candidates(), Path::exists).map(|(_, path)| path)
     }
 
     fn load() -> Result<(MenuConfig, PathBuf), MenuError> {
@@ -577,31 +1092,34 @@ pub fn config_diagnostics() {
     println!("Exe path: {}", exe_path.as_ref().map(|p| p.display().to_string()).unwrap_or_else(|| "unknown".into()));
     println!("CWD: {}", env::current_dir().map(|p| p.display().to_string()).unwrap_or_else(|_| "unknown".into()));
 
-    let cwd_config = Path::new(MenuConfig::DEFAULT_CONFIG_PATH);
-    println!("CWD config ({}): {}", cwd_config.display(), if cwd_config.exists() { "found" } else { "not found" });
-
-    if let Some(exe_dir) = exe_path.as_ref().and_then(|p| p.parent()) {
-        let exe_config = exe_dir.join(MenuConfig::DEFAULT_CONFIG_PATH);
-        println!("Exe config ({}): {}", exe_config.display(), if exe_config.exists() { "found" } else { "not found" });
+    println!("Search order (first match wins):");
+    let candidates = config_candidates();
+    for (source, path) in &candidates {
+        println!("  {} ({}): {}", source.label(), path.display(), if path.exists() { "found" } else { "not found" });
+    }
+    for source in [ConfigSource::Flag, ConfigSource::Env] {
+        if !candidates.iter().any(|(s, _)| *s == source) {
+            println!("  {}: not set", source.label());
+        }
     }
 
-    match MenuConfig::resolve_path() {
-        Some(path) => match MenuConfig::load_from_file(&path) {
+    match choose_config(candidates, Path::exists) {
+        Some((source, path)) => match MenuConfig::load_from_file(&path) {
             Ok(mut cfg) => {
-                println!("Result: using {}", path.display());
+                println!("Result: using {} (from {})", path.display(), source.label());
                 let base_dir = path.parent().unwrap_or_else(|| Path::new("."));
+                let mut packs = Vec::new();
                 if let Some(imports) = cfg.import.take() {
-                    for rel in &imports {
-                        let ip = base_dir.join(rel);
+                    let resolved = Imports::resolve(&imports, base_dir, cfg.nested_imports.unwrap_or(false));
+                    for ip in resolved.files.iter().filter(|f| !is_glob(&f.to_string_lossy())) {
                         println!("Import ({}): {}", ip.display(), if ip.exists() { "found" } else { "not found" });
                     }
-                    let (packs, import_warnings) = read_packs(&imports, base_dir);
-                    for warning in import_warnings {
+                    for warning in resolved.warnings {
                         println!("Warning: {}", warning);
                     }
-                    merge_packs(&mut cfg, packs);
+                    packs = resolved.packs;
                 }
-                let (_, warnings) = resolve_settings(&cfg);
+                let (_, warnings) = Resolved::new(cfg, packs, &path, appmode::app_mode(&Registry));
                 for warning in warnings {
                     println!("Warning: {}", warning);
                 }
@@ -612,21 +1130,461 @@ pub fn config_diagnostics() {
     }
 }
 
-/// The default location `config init` writes to: next to the executable. This
-/// matches the portable-binary story and Scoop installs, and is independent of
-/// the shell's current directory.
+/// Where a value in the effective configuration came from.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+enum Origin {
+    Default,
+    Root { file: String },
+    Theme { name: String, file: String },
+    Import { file: String },
+}
+
+impl fmt::Display for Origin {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Origin::Default => write!(f, "default"),
+            Origin::Root { file } => write!(f, "{}", file),
+            Origin::Theme { name, file } => write!(f, "theme '{}' ({})", name, file),
+            Origin::Import { file } => write!(f, "{}", file),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct ResolvedValue {
+    key: &'static str,
+    value: toml::Value,
+    origin: Origin,
+}
+
+#[derive(Debug, Serialize)]
+struct ResolvedCommand {
+    name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    args: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    keys: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    aliases: Vec<String>,
+    origin: Origin,
+}
+
+/// The configuration that actually takes effect once imports are merged and
+/// settings resolved, each value tagged with its origin.
+#[derive(Debug, Serialize)]
+struct Resolved {
+    settings: Vec<ResolvedValue>,
+    colors: Vec<ResolvedValue>,
+    commands: Vec<ResolvedCommand>,
+    aliases: BTreeMap<String, Vec<String>>,
+    #[serde(skip)]
+    min_contrast: f64,
+    // Where the root config was read from, for values it sets
+    #[serde(skip)]
+    root: Origin,
+}
+
+fn string_array(items: &[String]) -> toml::Value {
+    toml::Value::Array(items.iter().cloned().map(toml::Value::String).collect())
+}
+
+/// A table key, quoted unless it's a bare key.
+fn toml_key(key: &str) -> String {
+    if !key.is_empty() && key.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-') {
+        key.to_string()
+    } else {
+        toml::Value::String(key.to_string()).to_string()
+    }
+}
+
+impl Resolved {
+    /// Resolve the root config and its packs like a menu shown in `mode`
+    /// does, tracking where each value comes from. Also returns the
+    /// resolution warnings.
+    fn new(mut cfg: MenuConfig, packs: Vec<Pack>, root: &Path, mode: AppMode) -> (Resolved, Vec<String>) {
+        let root = Origin::Root { file: root.display().to_string() };
+        // Theme and command origins, read before merge_packs folds the packs
+        // in, with the same precedence: later imports, then the root, win
+        let mut theme_origins: HashMap<String, Origin> = HashMap::new();
+        let mut command_origins: HashMap<String, Origin> = HashMap::new();
+        let layers = packs
+            .iter()
+            .map(|p| (Origin::Import { file: p.origin.clone() }, &p.themes, &p.commands))
+            .chain(std::iter::once((root.clone(), &cfg.themes, &cfg.commands)));
+        for (origin, themes, commands) in layers {
+            for name in themes.iter().flat_map(|t| t.keys()) {
+                theme_origins.insert(name.clone(), origin.clone());
+            }
+            for command in commands.iter().flatten() {
+                command_origins.insert(command.entry_name(), origin.clone());
+            }
+        }
+        merge_packs(&mut cfg, packs);
+        let (settings, mut warnings) = resolve_settings(&cfg, mode);
+        let (ranking, history_warning) = resolve_history(&cfg);
+        warnings.extend(history_warning);
+
+        let from = |set: bool| if set { root.clone() } else { Origin::Default };
+        let value = |key, value, origin| ResolvedValue { key, value, origin };
+        let combo = |keys: &Option<Vec<String>>, default: [&str; 2]| match keys {
+            Some(keys) if parse_key_combo(keys).is_ok() => (string_array(keys), root.clone()),
+            _ => (string_array(&default.map(String::from)), Origin::Default),
+        };
+        let (next, next_origin) = combo(&cfg.next, ["CTRL", "J"]);
+        let (prev, prev_origin) = combo(&cfg.prev, ["CTRL", "K"]);
+        let history = match ranking {
+            None => toml::Value::Boolean(false),
+            Some(Ranking::Count) => toml::Value::String("count".to_string()),
+            Some(Ranking::Frecency) => toml::Value::String("frecency".to_string()),
+        };
+        let hotkey = cfg.hotkey.clone().unwrap_or_else(Hotkey::default_keys);
+        let mut resolved_settings = vec![
+            value("hotkey", string_array(&hotkey), from(cfg.hotkey.is_some())),
+            value("next", next, next_origin),
+            value("prev", prev, prev_origin),
+            value("matching", settings.filter_mode.name().into(), from(cfg.matching.is_some())),
+            value("case_sensitive", settings.case_sensitive.into(), from(cfg.case_sensitive.is_some())),
+            value("normalize", settings.normalize.into(), from(cfg.normalize.is_some())),
+            value("history", history, from(cfg.history.is_some())),
+            value("horizontal", settings.horizontal.into(), from(cfg.horizontal.is_some())),
+            value("lines", (settings.line_count as i64).into(), from(cfg.lines.is_some())),
+            value("width", i64::from(settings.width).into(), from(cfg.width.is_some())),
+            value("center", settings.center_window.into(), from(cfg.center.is_some())),
+            value("padding", i64::from(settings.padding).into(), from(cfg.padding.is_some())),
+            value("border_width", i64::from(settings.border_width).into(), from(cfg.border_width.is_some())),
+            value("font", theme::format_font(&settings).into(), from(cfg.font.is_some())),
+            value("font_quality", settings.font_quality.name().into(), from(cfg.font_quality.is_some())),
+        ];
+        if let Some(prompt) = &settings.prompt {
+            resolved_settings.push(value("prompt", prompt.as_str().into(), root.clone()));
+        }
+        resolved_settings.push(value(
+            "theme",
+            cfg.theme.as_ref().map_or("default", |t| t.select(mode)).into(),
+            from(cfg.theme.is_some()),
+        ));
+        let min_contrast = cfg.min_contrast.unwrap_or(DEFAULT_MIN_CONTRAST);
+        resolved_settings.push(value("min_contrast", min_contrast.into(), from(cfg.min_contrast.is_some())));
+
+        // A color is the root override's, else the selected theme's or the
+        // nearest ancestor's it extends, else the default — counting only
+        // values that evaluate, as `Palette::apply` does. Optional colors nobody
+        // set are left out: they follow other colors
+        let no_themes = HashMap::new();
+        let themes = cfg.themes.as_ref().unwrap_or(&no_themes);
+        let wallpaper = match cfg.theme.as_ref().map(|t| t.select(mode)) {
+            Some(theme::WALLPAPER) if !themes.contains_key(theme::WALLPAPER) => wallpaper::current().ok(),
+            _ => None,
+        };
+        let chain: Vec<(Origin, &Palette)> = match cfg.theme.as_ref().map(|t| t.select(mode)) {
+            Some(name) if themes.contains_key(name) => theme::theme_chain(themes, name)
+                .0
+                .into_iter()
+                .map(|name| {
+                    let file = theme_origins.get(name).map(ToString::to_string).unwrap_or_default();
+                    (Origin::Theme { name: name.to_string(), file }, &themes[name])
+                })
+                .collect(),
+            _ => wallpaper
+                .iter()
+                .map(|(path, palette)| {
+                    let file = path.display().to_string();
+                    (Origin::Theme { name: theme::WALLPAPER.to_string(), file }, palette)
+                })
+                .collect(),
+        };
+        let sets = |palette: &Palette, key: &str| palette.evaluate(key, &settings).is_some_and(|color| color.is_ok());
+        let colors = Palette::from_settings(&settings)
+            .entries()
+            .into_iter()
+            .filter_map(|(key, color)| {
+                let color = color?;
+                let origin = if sets(&cfg.colors, key) {
+                    root.clone()
+                } else {
+                    chain
+                        .iter()
+                        .find(|(_, palette)| sets(palette, key))
+                        .map_or(Origin::Default, |(origin, _)| origin.clone())
+                };
+                Some(value(key, color.into(), origin))
+            })
+            .collect();
+
+        // Same-named commands replace earlier ones, as in `apply_config_commands`
+        let mut commands: Vec<ResolvedCommand> = Vec::new();
+        let mut positions: HashMap<String, usize> = HashMap::new();
+        for command in cfg.commands.take().unwrap_or_default() {
+            let entry = command.entry_name();
+            let (args, keys) = match command.command_type {
+                CommandType::Args { args } => (Some(args), None),
+                CommandType::Keys { keys } => (None, Some(keys)),
+            };
+            let resolved = ResolvedCommand {
+                name: command.name,
+                args,
+                keys,
+                aliases: command.aliases.into_iter().chain(command.keywords).collect(),
+                origin: command_origins.get(&entry).cloned().unwrap_or_else(|| root.clone()),
+            };
+            match positions.get(&entry) {
+                Some(&i) => commands[i] = resolved,
+                None => {
+                    positions.insert(entry, commands.len());
+                    commands.push(resolved);
+                }
+            }
+        }
+
+        let resolved = Resolved {
+            settings: resolved_settings,
+            colors,
+            commands,
+            aliases: cfg.aliases.take().unwrap_or_default().into_iter().collect(),
+            min_contrast,
+            root,
+        };
+        warnings.extend(resolved.low_contrast().into_iter().map(|(_, message)| message));
+        (resolved, warnings)
+    }
+
+    /// The text/background pairs below `min_contrast`, each with a warning
+    /// naming both colors and where they came from.
+    fn low_contrast(&self) -> Vec<(LowContrast, String)> {
+        let find = |key: &str| self.colors.iter().find(|c| c.key == key);
+        let rgb = |key: &str| find(key)?.value.as_str().and_then(color::parse).map(|(rgb, _)| rgb);
+        theme::low_contrast(rgb, self.min_contrast)
+            .into_iter()
+            .filter_map(|low| {
+                let (text, background) = (find(low.text)?, find(low.background)?);
+                let origins = if text.origin == background.origin {
+                    format!("both from {}", text.origin)
+                } else {
+                    format!("{} from {}, {} from {}", text.key, text.origin, background.key, background.origin)
+                };
+                let message = format!(
+                    "{} {} on {} {} has contrast {:.1}:1, below min_contrast {} ({})",
+                    text.key,
+                    text.value.as_str().unwrap_or_default(),
+                    background.key,
+                    background.value.as_str().unwrap_or_default(),
+                    low.ratio,
+                    self.min_contrast,
+                    origins
+                );
+                Some((low, message))
+            })
+            .collect()
+    }
+
+    /// Render as TOML a config could contain, each value's origin in a
+    /// trailing comment.
+    fn to_toml(&self) -> String {
+        let mut out = String::new();
+        for group in [&self.settings, &self.colors] {
+            for item in group {
+                let _ = writeln!(out, "{} = {} # {}", item.key, item.value, item.origin);
+            }
+            out.push('\n');
+        }
+        for command in &self.commands {
+            let _ = writeln!(out, "[[commands]] # {}", command.origin);
+            let _ = writeln!(out, "name = {}", toml::Value::String(command.name.clone()));
+            if let Some(args) = &command.args {
+                let _ = writeln!(out, "args = {}", string_array(args));
+            }
+            if let Some(keys) = &command.keys {
+                let _ = writeln!(out, "keys = {}", string_array(keys));
+            }
+            if !command.aliases.is_empty() {
+                let _ = writeln!(out, "aliases = {}", string_array(&command.aliases));
+            }
+            out.push('\n');
+        }
+        if !self.aliases.is_empty() {
+            let _ = writeln!(out, "[aliases] # {}", self.root);
+            for (name, terms) in &self.aliases {
+                let _ = writeln!(out, "{} = {}", toml_key(name), string_array(terms));
+            }
+        }
+        out.trim_end().to_string()
+    }
+}
+
+/// `config show`: print the config file in effect, or with `resolved` the
+/// effective configuration after imports and defaults, annotated with where
+/// each value comes from (as TOML, or JSON with `json`). Returns an exit code.
+pub fn config_show(resolved: bool, json: bool) -> i32 {
+    let Some(path) = MenuConfig::resolve_path() else {
+        if !resolved {
+            eprintln!("config show: no windmenu.toml found — windmenu uses built-in defaults");
+            return 1;
+        }
+        let root = Path::new(MenuConfig::DEFAULT_CONFIG_PATH);
+        let (resolved, _) = Resolved::new(MenuConfig::default(), Vec::new(), root, AppMode::Dark);
+        return print_resolved(&resolved, json);
+    };
+    if !resolved {
+        return match fs::read_to_string(&path) {
+            Ok(text) => {
+                println!("# {}", path.display());
+                print!("{}", text);
+                0
+            }
+            Err(e) => {
+                eprintln!("config show: failed to read {}: {}", path.display(), e);
+                1
+            }
+        };
+    }
+
+    let mut cfg = match MenuConfig::load_from_file(&path) {
+        Ok(cfg) => cfg,
+        Err(e) => {
+            eprintln!("config show: {} ({})", e, path.display());
+            return 1;
+        }
+    };
+    let base_dir = path.parent().unwrap_or_else(|| Path::new("."));
+    let nested = cfg.nested_imports.unwrap_or(false);
+    let (packs, mut warnings) = read_packs(&cfg.import.take().unwrap_or_default(), base_dir, nested);
+    let (resolved, resolve_warnings) = Resolved::new(cfg, packs, &path, appmode::app_mode(&Registry));
+    warnings.extend(resolve_warnings);
+    for warning in warnings {
+        eprintln!("Warning: {}", warning);
+    }
+    print_resolved(&resolved, json)
+}
+
+/// The effective colors' pairs below `min_contrast`, for `config check`:
+/// the config at `path` resolved with its imports. Empty when it won't load.
+pub(crate) fn low_contrast(path: &Path) -> Vec<(LowContrast, String)> {
+    let Ok(mut cfg) = MenuConfig::load_from_file(path) else {
+        return Vec::new();
+    };
+    let base_dir = path.parent().unwrap_or_else(|| Path::new("."));
+    let nested = cfg.nested_imports.unwrap_or(false);
+    let (packs, _) = read_packs(&cfg.import.take().unwrap_or_default(), base_dir, nested);
+    Resolved::new(cfg, packs, path, appmode::app_mode(&Registry)).0.low_contrast()
+}
+
+fn print_resolved(resolved: &Resolved, json: bool) -> i32 {
+    if json {
+        match serde_json::to_string_pretty(resolved) {
+            Ok(text) => println!("{}", text),
+            Err(e) => {
+                eprintln!("config show: {}", e);
+                return 1;
+            }
+        }
+    } else {
+        println!("{}", resolved.to_toml());
+    }
+    0
+}
+
+impl Pack {
+    /// How `pack list` shows it: the header line, then what it contributes.
+    fn describe(&self) -> String {
+        let info = self.pack.as_ref();
+        let mut out = self.display_name().to_string();
+        if let Some(version) = info.and_then(|p| p.version.as_deref()) {
+            out.push_str(&format!(" {}", version));
+        }
+        if self.display_name() != self.origin {
+            out.push_str(&format!(" ({})", self.origin));
+        }
+        if let Some(author) = info.and_then(|p| p.author.as_deref()) {
+            out.push_str(&format!("\n  author:   {}", author));
+        }
+        if let Some(min) = info.and_then(|p| p.min_version.as_deref()) {
+            out.push_str(&format!("\n  requires: windmenu {}", min));
+        }
+        let mut themes: Vec<&str> = self.themes.iter().flatten().map(|(name, _)| name.as_str()).collect();
+        themes.sort_unstable();
+        let commands: Vec<String> = self.commands.iter().flatten().map(CommandConfig::entry_name).collect();
+        if !themes.is_empty() {
+            out.push_str(&format!("\n  themes:   {}", themes.join(", ")));
+        }
+        if !commands.is_empty() {
+            out.push_str(&format!("\n  commands: {}", commands.join(", ")));
+        }
+        if themes.is_empty() && commands.is_empty() {
+            out.push_str("\n  (contributes nothing)");
+        }
+        out
+    }
+}
+
+/// `pack list`: every pack the config imports, in merge order, with its
+/// `[pack]` metadata and the themes and commands it contributes. Returns an
+/// exit code.
+pub fn pack_list() -> i32 {
+    let Some(path) = MenuConfig::resolve_path() else {
+        eprintln!("pack list: no windmenu.toml found — nothing is imported");
+        return 1;
+    };
+    let cfg = match MenuConfig::load_from_file(&path) {
+        Ok(cfg) => cfg,
+        Err(e) => {
+            eprintln!("pack list: {} ({})", e, path.display());
+            return 1;
+        }
+    };
+    let base_dir = path.parent().unwrap_or_else(|| Path::new("."));
+    let nested = cfg.nested_imports.unwrap_or(false);
+    let (packs, warnings) = read_packs(&cfg.import.unwrap_or_default(), base_dir, nested);
+    for warning in warnings {
+        eprintln!("Warning: {}", warning);
+    }
+    if packs.is_empty() {
+        println!("No packs imported by {}", path.display());
+    }
+    for (i, pack) in packs.iter().enumerate() {
+        if i > 0 {
+            println!();
+        }
+        println!("{}", pack.describe());
+    }
+    0
+}
+
+/// The file `config init` writes: the config in effect (an explicit path, or
+/// the first found) so an existing one is never shadowed by a new file, else
+/// the per-user one, else the one next to the executable when %APPDATA% is
+/// unset. Nothing ranks above a fresh file, so it is the one loaded next.
+fn choose_init_target(
+    candidates: Vec<(ConfigSource, PathBuf)>,
+    exists: impl Fn(&Path) -> bool,
+) -> Option<(ConfigSource, PathBuf)> {
+    let fresh = candidates
+        .iter()
+        .find(|(source, _)| matches!(source, ConfigSource::User | ConfigSource::Exe))
+        .cloned();
+    choose_config(candidates, exists).or(fresh)
+}
+
 fn init_target() -> Result<PathBuf, String> {
-    let exe = env::current_exe().map_err(|e| format!("cannot locate executable: {}", e))?;
-    let dir = exe.parent().ok_or_else(|| "executable has no parent directory".to_string())?;
-    Ok(dir.join(MenuConfig::DEFAULT_CONFIG_PATH))
+    choose_init_target(config_candidates(), Path::exists)
+        .map(|(_, path)| path)
+        .ok_or_else(|| "cannot locate %APPDATA% or the executable's directory".to_string())
+}
+
+/// The directory relative imports resolve against: the config in effect's,
+/// else where `config init` would write one.
+pub(crate) fn config_dir() -> Result<PathBuf, String> {
+    let config = init_target()?;
+    Ok(config.parent().map_or_else(|| PathBuf::from("."), Path::to_path_buf))
 }
 
 const RESTART_REMINDER: &str =
-    "Restart the daemon ('windmenu restart') or run 'Reload Config' from the menu to apply changes.";
+    "A running daemon applies saved changes automatically ('Reload Config' in the menu forces it).";
 
-/// `config init`: write the embedded default config next to the executable.
-/// Refuses to overwrite an existing file unless `force` is set. Returns a
-/// process exit code.
+/// `config init`: write the embedded default config to `init_target`: the
+/// `--config`/WINDMENU_CONFIG path, else `%APPDATA%\windmenu\windmenu.toml`.
+/// Refuses to overwrite an existing file, including one already found on the
+/// search path, unless `force` is set. Returns a process exit code.
 pub fn config_init(force: bool) -> i32 {
     let target = match init_target() {
         Ok(t) => t,
@@ -641,6 +1599,10 @@ pub fn config_init(force: bool) -> i32 {
         return 1;
     }
 
+    // An explicit path may point into a directory that doesn't exist yet
+    if let Some(dir) = target.parent() {
+        let _ = fs::create_dir_all(dir);
+    }
     if let Err(e) = fs::write(&target, DEFAULT_CONFIG) {
         eprintln!("config init: failed to write {}: {}", target.display(), e);
         return 1;
@@ -655,16 +1617,16 @@ pub fn config_init(force: bool) -> i32 {
 /// none exists. Uses %EDITOR% when set, otherwise notepad. Returns an exit code.
 pub fn config_edit() -> i32 {
     let path = match MenuConfig::resolve_path() {
-        Some(p) => p,
-        None => {
-            // No config anywhere yet: create one next to the exe, then edit it.
+        Some(p) if p.exists() => p,
+        _ => {
+            // No config yet: create one where init writes, then edit it.
             let code = config_init(false);
             if code != 0 {
                 return code;
             }
             match MenuConfig::resolve_path() {
-                Some(p) => p,
-                None => {
+                Some(p) if p.exists() => p,
+                _ => {
                     eprintln!("config edit: config file not found after init");
                     return 1;
                 }
@@ -741,10 +1703,55 @@ fn split_command(command: &str) -> Vec<String> {
 #[derive(Debug, Deserialize)]
 struct CommandConfig {
     name: String,
+    // Extra search terms: matched like the name, never displayed
+    #[serde(default)]
+    aliases: Vec<String>,
+    #[serde(default)]
+    keywords: Vec<String>,
     #[serde(flatten)]
     command_type: CommandType,
 }
 
+impl CommandConfig {
+    /// The name shown in the menu: key combos carry their keys, e.g.
+    /// `Show Desktop [WIN, D]`.
+    fn entry_name(&self) -> String {
+        match &self.command_type {
+            CommandType::Args { .. } => self.name.clone(),
+            CommandType::Keys { keys } => format!("{} [{}]", self.name, keys.join(", ")),
+        }
+    }
+}
+
+/// `history = true | false | "count" | "frecency"`. The bool form predates
+/// frecency and keeps meaning on/off with the plain most-used ranking.
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum HistoryConfig {
+    Enabled(bool),
+    Ranking(String),
+}
+
+/// The history ranking a config asks for, `None` when history is off.
+/// An unknown ranking name falls back to the plain count with a warning.
+fn resolve_history(cfg: &MenuConfig) -> (Option<Ranking>, Option<String>) {
+    match &cfg.history {
+        None | Some(HistoryConfig::Enabled(true)) => (Some(Ranking::Count), None),
+        Some(HistoryConfig::Enabled(false)) => (None, None),
+        Some(HistoryConfig::Ranking(name)) => match name.to_lowercase().as_str() {
+            "count" => (Some(Ranking::Count), None),
+            "frecency" => (Some(Ranking::Frecency), None),
+            _ => (
+                Some(Ranking::Count),
+                Some(format!(
+                    "history '{}' is not one of true, false, \"count\", \"frecency\" — using \"count\"",
+                    name
+                )),
+            ),
+        },
+    }
+}
+
 #[derive(Debug, Deserialize)]
 #[serde(untagged)]
 enum CommandType {
@@ -755,7 +1762,8 @@ enum CommandType {
 pub struct Menu {
     pub process_running: AtomicBool,
     pub entries: Arc<RwLock<EntryStore>>,
-    pub settings: wlines::Settings,
+    // Swapped wholesale on config reload
+    settings: RwLock<ModeSettings>,
     pub hotkey: Hotkey,
     // None when disabled via `history = false` in the config
     history: Option<Mutex<History>>,
@@ -799,61 +1807,96 @@ impl Menu {
     pub fn new() -> Menu {
         let process_running = AtomicBool::new(false);
 
-        // Ctrl+Alt+Space
-        let mut hotkey = Hotkey {
-            keys: vec!["CTRL".to_string(), "ALT".to_string(), "SPACE".to_string()],
-        };
-        let mut settings = theme::default_settings();
+        let mut hotkey_keys = Hotkey::default_keys();
+        let mut settings = ModeSettings { dark: theme::default_settings(), light: None, wallpaper: None };
         let entries = Arc::new(RwLock::new(EntryStore::empty()));
-        let mut history_enabled = true;
+        let mut history_ranking = Some(Ranking::Count);
         let mut history_dir: Option<PathBuf> = None;
 
         if let Ok((cfg, config_dir, import_warnings)) = load_with_imports() {
-            let (resolved, warnings) = resolve_settings(&cfg);
+            let (resolved, mut warnings) = ModeSettings::resolve(&cfg);
             settings = resolved;
+            let (ranking, history_warning) = resolve_history(&cfg);
+            warnings.extend(history_warning);
             for warning in import_warnings.iter().chain(warnings.iter()) {
                 eprintln!("Warning: {}", warning);
             }
-            if let Some(ref keys) = &cfg.hotkey {
-                hotkey.keys = keys.clone();
-            }
-            if let Some(cmds) = cfg.commands {
-                entries.write().unwrap().apply_config_commands(cmds);
+            if let Some(keys) = cfg.hotkey {
+                hotkey_keys = keys;
             }
-            history_enabled = cfg.history.unwrap_or(true);
+            entries.write().unwrap().apply_config(cfg.commands, cfg.aliases);
+            history_ranking = ranking;
             history_dir = Some(config_dir);
         }
 
         // The history file lives next to the config; with no config it sits
-        // next to the executable, mirroring where `config init` would write.
-        let history = history_enabled.then(|| {
+        // next to the executable.
+        let history = history_ranking.map(|ranking| {
             let dir = history_dir.or_else(|| {
                 env::current_exe().ok().and_then(|p| p.parent().map(Path::to_path_buf))
             });
             let path = dir.unwrap_or_else(|| PathBuf::from(".")).join(HISTORY_FILE);
-            Mutex::new(History::load(path))
+            Mutex::new(History::load(path, ranking))
         });
 
         Menu {
             process_running,
             entries,
-            settings,
-            hotkey,
+            settings: RwLock::new(settings),
+            hotkey: Hotkey::new(hotkey_keys),
             history,
         }
     }
 
+    /// Re-read the config and imports and apply everything that can change
+    /// at runtime: settings and theme, hotkey, commands and aliases. Returns
+    /// the warnings; a config that fails to load leaves everything as is.
+    /// The history setting still needs a restart.
+    pub fn reload(&self) -> Vec<String> {
+        let (cfg, _dir, mut warnings) = match load_with_imports() {
+            Ok(loaded) => loaded,
+            Err(e) => return vec![format!("{} — keeping the current configuration", e)],
+        };
+        let (settings, settings_warnings) = ModeSettings::resolve(&cfg);
+        warnings.extend(settings_warnings);
+        *self.settings.write().unwrap() = settings;
+        self.hotkey.set_keys(cfg.hotkey.unwrap_or_else(Hotkey::default_keys));
+        self.entries.write().unwrap().apply_config(cfg.commands, cfg.aliases);
+        warnings
+    }
+
+    /// Reload whenever `windmenu.toml` or one of its imports changes on
+    /// disk. Blocks; run it on its own thread.
+    pub fn watch_config(&self) {
+        watch::watch(config_files, || {
+            println!("Config changed, reloading");
+            for warning in self.reload() {
+                eprintln!("Warning: {}", warning);
+            }
+        });
+    }
+
     pub fn show(self: Arc<Self>) -> Result<(), MenuError> {
         if self.process_running.swap(true, Ordering::SeqCst) {
             return Err(MenuError::MenuAlreadyRunning);
         }
 
         let entries = self.prepare_entries();
+        let aliases = self.entries.read().unwrap().aliases();
+        // Picked now, so the menu follows the app mode as it changes
+        let settings = self.settings.read().unwrap().current();
 
         // Run the menu window and its message loop on a dedicated thread
         thread::spawn(move || {
-            let result = match wlines::show(&self.settings, &entries) {
-                Some(selected) => self.execute_command(&selected),
+            let adaptive = self.history.is_some().then(|| {
+                let menu = Arc::clone(&self);
+                Box::new(move |query: &str| match &menu.history {
+                    Some(history) => history.lock().unwrap().query_weights(query),
+                    None => HashMap::new(),
+                }) as wlines::Adaptive
+            });
+            let result = match wlines::show(&settings, &entries, &aliases, adaptive) {
+                Some(selection) => self.execute_command(&selection.text, &selection.query),
                 None => Ok(()), // User cancelled
             };
 
@@ -876,14 +1919,14 @@ impl Menu {
         entries
     }
 
-    fn execute_command(&self, selected: &str) -> Result<(), MenuError> {
+    fn execute_command(&self, selected: &str, query: &str) -> Result<(), MenuError> {
         let cmd = {
             self.entries.read().unwrap().get(selected).cloned()
         };
 
         if cmd.is_some() {
             if let Some(history) = &self.history {
-                history.lock().unwrap().record(selected);
+                history.lock().unwrap().record(selected, query);
             }
         }
 
@@ -912,10 +1955,15 @@ impl Menu {
                 Ok(())
             },
             Some(MenuCommand::ReloadConfig) => {
-                let entries = self.entries.clone();
-                thread::spawn(move || {
-                    entries.write().unwrap().reload_config();
-                });
+                // Asked for explicitly, so problems get a message box rather
+                // than only the (usually detached) stderr
+                let warnings = self.reload();
+                for warning in &warnings {
+                    eprintln!("Warning: {}", warning);
+                }
+                if !warnings.is_empty() {
+                    error_box(&format!("Config reloaded with warnings:\n\n{}", warnings.join("\n")));
+                }
                 Ok(())
             },
             None => {
@@ -1060,7 +2108,7 @@ impl Menu {
         }
     }
 
-    fn parse_key_name_to_vk_code(key: &str) -> Result<u16, MenuError> {
+    pub(crate) fn parse_key_name_to_vk_code(key: &str) -> Result<u16, MenuError> {
         match key.to_uppercase().as_str() {
             "ALT" => Ok(VK_MENU as u16),
             "CTRL" | "CONTROL" => Ok(VK_CONTROL as u16),
@@ -1206,9 +2254,12 @@ mod tests {
         );
     }
 
-    use super::{merge_packs, read_packs, resolve_settings, MenuConfig, Pack, DEFAULT_CONFIG};
+    use super::{choose_config, choose_init_target, expand_import, glob_match, merge_packs, min_version_problem, parse_version, Imports, ConfigSource, ModeSettings, Origin, Resolved, read_packs, resolve_history, resolve_settings, EntryStore, MenuConfig, Pack, DEFAULT_CONFIG};
+    use crate::history::Ranking;
+    use crate::appmode::AppMode;
     use crate::theme::default_settings;
-    use crate::wlines::parse_color;
+    use crate::wlines::{parse_color, FontQuality};
+    use std::path::{Path, PathBuf};
 
     fn parse_config(s: &str) -> MenuConfig {
         toml::from_str(s).expect("config should parse")
@@ -1227,7 +2278,7 @@ mod tests {
             bg_select = "#5e81ac"
         "##,
         );
-        let (settings, warnings) = resolve_settings(&cfg);
+        let (settings, warnings) = resolve_settings(&cfg, AppMode::Dark);
         assert!(warnings.is_empty());
         assert_eq!(settings.bg_select, parse_color("#5e81ac").unwrap());
     }
@@ -1242,23 +2293,233 @@ mod tests {
             bg_select = "#5e81ac"
         "##,
         );
-        let (settings, _) = resolve_settings(&cfg);
+        let (settings, _) = resolve_settings(&cfg, AppMode::Dark);
         assert_eq!(settings.bg_select, parse_color("#ffffff").unwrap());
     }
 
+    #[test]
+    fn aliases_come_from_commands_and_table() {
+        let cfg = parse_config(
+            r##"
+            [[commands]]
+            name = "Terminal"
+            args = ["wt"]
+            aliases = ["wt"]
+            keywords = ["console", "shell"]
+
+            [[commands]]
+            name = "Show Desktop"
+            keys = ["WIN", "D"]
+            aliases = ["desk"]
+
+            [aliases]
+            "Terminal" = ["cmd"]
+            "Visual Studio Code" = ["vsc"]
+        "##,
+        );
+        let mut store = EntryStore::empty();
+        store.apply_config(cfg.commands, cfg.aliases);
+        let aliases = store.aliases();
+        assert_eq!(aliases["Terminal"], vec!["wt", "console", "shell", "cmd"]);
+        assert_eq!(aliases["Show Desktop [WIN, D]"], vec!["desk"]);
+        assert_eq!(aliases["Visual Studio Code"], vec!["vsc"]);
+        assert_eq!(aliases.len(), 3);
+
+        // A reload without commands or aliases clears both
+        store.apply_config(None, None);
+        assert!(store.aliases().is_empty());
+        assert!(store.get("Terminal").is_none());
+        assert!(store.get("Reload Config").is_some());
+    }
+
+    #[test]
+    fn config_search_follows_precedence() {
+        let candidates = |sources: &[ConfigSource]| -> Vec<(ConfigSource, PathBuf)> {
+            sources.iter().map(|&s| (s, PathBuf::from(format!("{:?}.toml", s)))).collect()
+        };
+        let all = [ConfigSource::Flag, ConfigSource::Env, ConfigSource::Cwd, ConfigSource::User, ConfigSource::Exe];
+        let exists = |found: &'static [&'static str]| move |p: &Path| found.iter().any(|f| p == Path::new(f));
+
+        // Explicit paths win even when the file doesn't exist yet
+        let chosen = choose_config(candidates(&all), exists(&["Exe.toml"]));
+        assert_eq!(chosen.map(|(s, _)| s), Some(ConfigSource::Flag));
+        let chosen = choose_config(candidates(&all[1..]), exists(&[]));
+        assert_eq!(chosen.map(|(s, _)| s), Some(ConfigSource::Env));
+
+        // Otherwise the first location that has a config
+        let implicit = candidates(&all[2..]);
+        assert_eq!(choose_config(implicit.clone(), exists(&["User.toml", "Exe.toml"])).map(|(s, _)| s), Some(ConfigSource::User));
+        assert_eq!(choose_config(implicit.clone(), exists(&["Cwd.toml", "Exe.toml"])).map(|(s, _)| s), Some(ConfigSource::Cwd));
+        assert_eq!(choose_config(implicit.clone(), exists(&[])), None);
+
+        // init writes the config in effect, else a fresh per-user one
+        let init = |candidates, found| choose_init_target(candidates, exists(found)).map(|(s, _)| s);
+        assert_eq!(init(candidates(&all), &["Cwd.toml"]), Some(ConfigSource::Flag));
+        assert_eq!(init(implicit.clone(), &["Cwd.toml", "Exe.toml"]), Some(ConfigSource::Cwd));
+        assert_eq!(init(implicit.clone(), &["Exe.toml"]), Some(ConfigSource::Exe));
+        assert_eq!(init(implicit, &[]), Some(ConfigSource::User));
+        assert_eq!(init(candidates(&[ConfigSource::Cwd, ConfigSource::Exe]), &[]), Some(ConfigSource::Exe));
+    }
+
+    /// A scratch directory with the given files, removed by the caller.
+    fn pack_dir(name: &str, files: &[(&str, &str)]) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("windmenu-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        for (file, text) in files {
+            let path = dir.join(file);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, text).unwrap();
+        }
+        dir
+    }
+
+    #[test]
+    fn glob_patterns_match_file_names() {
+        assert!(glob_match("*.toml", "Nord.TOML"));
+        assert!(glob_match("theme-?.toml", "theme-a.toml"));
+        assert!(glob_match("*-dark*", "nord-dark.toml"));
+        assert!(!glob_match("*.toml", "nord.toml.bak"));
+        assert!(!glob_match("theme-?.toml", "theme-ab.toml"));
+    }
+
+    #[test]
+    fn nested_and_glob_imports_resolve_in_order() {
+        let dir = pack_dir("imports", &[
+            ("team.toml", "import = [\"sub/base.toml\"]\n[themes.team]\nbg = \"#000001\"\n"),
+            ("sub/base.toml", "[themes.team]\nbg = \"#000002\"\n"),
+            ("packs/b.toml", "[[commands]]\nname = \"B\"\nargs = [\"b\"]\n"),
+            ("packs/a.toml", "[[commands]]\nname = \"A\"\nargs = [\"a\"]\n"),
+            ("packs/notes.txt", ""),
+        ]);
+        let imports = vec!["team.toml".to_string(), "packs/*.toml".to_string(), "sub/base.toml".to_string()];
+        let resolved = Imports::resolve(&imports, &dir, true);
+        // Without nested_imports a pack's own import is ignored
+        let flat = Imports::resolve(&imports[..1], &dir, false);
+        let _ = std::fs::remove_dir_all(&dir);
+
+        let flat: Vec<&str> = flat.packs.iter().map(|p| p.origin.as_str()).collect();
+        assert_eq!(flat, ["team.toml"]);
+
+        assert!(resolved.warnings.is_empty(), "{:?}", resolved.warnings);
+        // A pack's own imports come first so the pack wins over them; globs
+        // expand sorted; a pack already loaded isn't loaded again
+        let origins: Vec<PathBuf> = resolved.packs.iter().map(|p| PathBuf::from(&p.origin)).collect();
+        assert_eq!(origins, vec![
+            Path::new("sub").join("base.toml"),
+            PathBuf::from("team.toml"),
+            Path::new("packs").join("a.toml"),
+            Path::new("packs").join("b.toml"),
+        ]);
+        let mut cfg = parse_config(r#"theme = "team""#);
+        merge_packs(&mut cfg, resolved.packs);
+        assert_eq!(resolve_settings(&cfg, AppMode::Dark).0.bg, parse_color("#000001").unwrap());
+        assert!(resolved.files.contains(&dir.join("packs/*.toml")));
+    }
+
+    #[test]
+    fn glob_imports_keep_absolute_and_parent_prefixes() {
+        let dir = pack_dir("prefixes", &[("packs/a.toml", ""), ("packs/b.toml", ""), ("sub/c.toml", "")]);
+        let absolute = format!("{}/*.toml", dir.join("packs").display());
+        let from_elsewhere = expand_import(Path::new("elsewhere"), &absolute);
+        let from_sub = expand_import(&dir.join("sub"), "../packs/?.toml");
+        let _ = std::fs::remove_dir_all(&dir);
+
+        assert_eq!(from_elsewhere, vec![dir.join("packs").join("a.toml"), dir.join("packs").join("b.toml")]);
+        let names: Vec<_> = from_sub.iter().map(|p| p.strip_prefix(&dir).unwrap().to_path_buf()).collect();
+        assert_eq!(names, vec![Path::new("sub/../packs").join("a.toml"), Path::new("sub/../packs").join("b.toml")]);
+    }
+
+    #[test]
+    fn import_cycles_depth_and_empty_globs_warn() {
+        let mut files: Vec<(String, String)> = vec![
+            ("a.toml".into(), "import = [\"b.toml\"]".into()),
+            ("b.toml".into(), "import = [\"a.toml\"]".into()),
+        ];
+        // deep0.toml -> deep1.toml -> ... -> deep11.toml
+        files.extend((0..12).map(|i| (format!("deep{}.toml", i), format!("import = [\"deep{}.toml\"]", i + 1))));
+        let files: Vec<(&str, &str)> = files.iter().map(|(name, text)| (name.as_str(), text.as_str())).collect();
+        let dir = pack_dir("cycles", &files);
+        let imports = vec!["a.toml".to_string(), "deep0.toml".to_string(), "none/*.toml".to_string()];
+        let resolved = Imports::resolve(&imports, &dir, true);
+        let _ = std::fs::remove_dir_all(&dir);
+
+        assert_eq!(resolved.warnings.len(), 3, "{:?}", resolved.warnings);
+        assert_eq!(resolved.warnings[0], "import cycle a.toml -> b.toml -> a.toml skipped");
+        assert!(resolved.warnings[1].contains("deeper than 8 levels"));
+        assert_eq!(resolved.warnings[2], "import 'none/*.toml' matched no files");
+        // Both halves of the cycle and the first eight levels still load
+        assert_eq!(resolved.packs.len(), 2 + 8);
+    }
+
+    #[test]
+    fn history_accepts_bool_or_ranking() {
+        let ranking = |toml: &str| resolve_history(&parse_config(toml));
+        assert_eq!(ranking(""), (Some(Ranking::Count), None));
+        assert_eq!(ranking("history = true"), (Some(Ranking::Count), None));
+        assert_eq!(ranking("history = false"), (None, None));
+        assert_eq!(ranking("history = \"frecency\""), (Some(Ranking::Frecency), None));
+        let (fallback, warning) = ranking("history = \"recent\"");
+        assert_eq!(fallback, Some(Ranking::Count));
+        assert!(warning.unwrap().contains("recent"));
+    }
+
     #[test]
     fn reserved_default_theme_never_warns() {
         // "default" resolves to the built-in palette even with no [themes.default].
         let cfg = parse_config(r#"theme = "default""#);
-        let (settings, warnings) = resolve_settings(&cfg);
+        let (settings, warnings) = resolve_settings(&cfg, AppMode::Dark);
         assert!(warnings.is_empty());
         assert_eq!(settings.bg, default_settings().bg);
     }
 
+    #[test]
+    fn themes_resolve_for_each_app_mode() {
+        let cfg = parse_config(
+            r##"
+            theme = { light = "paper", dark = "nord" }
+            [themes.nord]
+            bg = "#2e3440"
+            [themes.paper]
+            bg = "#fafafa"
+        "##,
+        );
+        let (settings, warnings) = ModeSettings::resolve(&cfg);
+        assert!(warnings.is_empty());
+        assert_eq!(settings.dark.bg, parse_color("#2e3440").unwrap());
+        assert_eq!(settings.light.map(|light| light.bg), parse_color("#fafafa"));
+
+        let (settings, _) = ModeSettings::resolve(&parse_config("theme = \"auto\""));
+        assert_eq!(settings.dark.bg, default_settings().bg);
+        assert_eq!(settings.light.map(|light| light.bg), parse_color("#f3f3f3"));
+
+        // A fixed theme needs no second resolution, and one missing theme
+        // warns once however many modes use it
+        let (settings, warnings) = ModeSettings::resolve(&parse_config("theme = \"nope\""));
+        assert!(settings.light.is_none());
+        assert_eq!(warnings.len(), 1);
+        let cfg = parse_config("theme = { light = \"nope\", dark = \"nope\" }");
+        assert_eq!(ModeSettings::resolve(&cfg).1.len(), 1);
+    }
+
+    #[test]
+    fn wallpaper_is_followed_only_where_it_is_the_theme() {
+        let cfg = parse_config("theme = { light = \"wallpaper\", dark = \"default\" }\nbg_select = \"#ff0000\"");
+        let wallpapered = ModeSettings::resolve(&cfg).0.wallpaper.unwrap();
+        assert_eq!(wallpapered.modes, [AppMode::Light]);
+        assert_eq!(wallpapered.overrides.bg_select.as_deref(), Some("#ff0000"));
+
+        // A [themes.wallpaper] table wins over the desktop, as for the built-ins
+        let cfg = parse_config("theme = \"wallpaper\"\n[themes.wallpaper]\nbg = \"#102030\"");
+        let (settings, warnings) = ModeSettings::resolve(&cfg);
+        assert!(warnings.is_empty());
+        assert!(settings.wallpaper.is_none());
+        assert_eq!(settings.dark.bg, parse_color("#102030").unwrap());
+    }
+
     #[test]
     fn missing_theme_warns_and_keeps_default() {
         let cfg = parse_config(r#"theme = "nope""#);
-        let (settings, warnings) = resolve_settings(&cfg);
+        let (settings, warnings) = resolve_settings(&cfg, AppMode::Dark);
         assert_eq!(warnings.len(), 1);
         assert!(warnings[0].contains("nope"));
         // The default palette is left intact; the launcher still resolves.
@@ -1274,33 +2535,64 @@ mod tests {
             hotkey = ["ALT", "F2"]
             matching = "keywords"
             case_sensitive = true
+            normalize = true
             lines = 20
             width = 640
             padding = 4
-            font = "Cascadia Code 14"
+            font = "Cascadia Code Bold, Consolas 14"
+            font_quality = "cleartype"
             prompt = "Run:"
         "#,
         );
-        let (settings, _) = resolve_settings(&cfg);
+        let (settings, _) = resolve_settings(&cfg, AppMode::Dark);
         assert_eq!(settings.line_count, 20);
         assert_eq!(settings.width, 640);
         assert_eq!(settings.padding, 4);
         assert_eq!(settings.font_name, "Cascadia Code");
         assert_eq!(settings.font_size, 14);
+        assert_eq!(settings.font_fallbacks, ["Consolas"]);
+        assert_eq!(settings.font_weight, 700);
+        assert_eq!(settings.font_quality, FontQuality::ClearType);
         assert_eq!(settings.prompt.as_deref(), Some("Run:"));
         assert!(settings.case_sensitive);
+        assert!(settings.normalize);
         assert_eq!(cfg.hotkey, Some(vec!["ALT".to_string(), "F2".to_string()]));
     }
 
+    #[test]
+    fn prompt_border_and_separator_colors_are_optional() {
+        let (plain, _) = resolve_settings(&parse_config("theme = \"default\"\n"), AppMode::Dark);
+        assert_eq!((plain.bg_prompt, plain.fg_prompt, plain.border, plain.separator), (None, None, None, None));
+        assert_eq!(plain.border_width, 0);
+
+        let cfg = parse_config(
+            r##"
+            theme = "boxed"
+            border_width = 2
+            [themes.boxed]
+            bg_prompt = "#010203"
+            border = "#0a0b0c"
+            separator = "#102030"
+        "##,
+        );
+        let (settings, warnings) = resolve_settings(&cfg, AppMode::Dark);
+        assert!(warnings.is_empty());
+        assert_eq!(settings.border_width, 2);
+        assert_eq!(settings.bg_prompt, parse_color("#010203"));
+        assert_eq!(settings.fg_prompt, None);
+        assert_eq!(settings.border, parse_color("#0a0b0c"));
+        assert_eq!(settings.separator, parse_color("#102030"));
+    }
+
     #[test]
     fn center_is_independent_of_width() {
         // Centering defaults on and is its own key; setting `width` (even 0,
         // full monitor width) no longer implies anything about position.
-        let (settings, _) = resolve_settings(&parse_config("width = 0"));
+        let (settings, _) = resolve_settings(&parse_config("width = 0"), AppMode::Dark);
         assert_eq!(settings.width, 0);
         assert!(settings.center_window);
 
-        let (settings, _) = resolve_settings(&parse_config("width = 640\ncenter = false"));
+        let (settings, _) = resolve_settings(&parse_config("width = 640\ncenter = false"), AppMode::Dark);
         assert_eq!(settings.width, 640);
         assert!(!settings.center_window);
     }
@@ -1308,11 +2600,11 @@ mod tests {
     #[test]
     fn horizontal_flag_applies() {
         let cfg = parse_config("horizontal = true");
-        let (settings, warnings) = resolve_settings(&cfg);
+        let (settings, warnings) = resolve_settings(&cfg, AppMode::Dark);
         assert!(warnings.is_empty());
         assert!(settings.horizontal);
 
-        let (settings, _) = resolve_settings(&parse_config(""));
+        let (settings, _) = resolve_settings(&parse_config(""), AppMode::Dark);
         assert!(!settings.horizontal);
     }
 
@@ -1321,7 +2613,7 @@ mod tests {
         // `config init` writes DEFAULT_CONFIG; resolving it must reproduce the
         // no-config appearance exactly (colors + geometry + font).
         let cfg: MenuConfig = toml::from_str(DEFAULT_CONFIG).expect("shipped config parses");
-        let (settings, warnings) = resolve_settings(&cfg);
+        let (settings, warnings) = resolve_settings(&cfg, AppMode::Dark);
         assert!(warnings.is_empty(), "shipped config warned: {:?}", warnings);
         let def = default_settings();
         assert_eq!(settings.bg, def.bg);
@@ -1330,6 +2622,8 @@ mod tests {
         assert_eq!(settings.fg_select, def.fg_select);
         assert_eq!(settings.bg_edit, def.bg_edit);
         assert_eq!(settings.fg_edit, def.fg_edit);
+        assert_eq!(settings.fg_match, def.fg_match);
+        assert_eq!(settings.fg_match_select, def.fg_match_select);
         assert_eq!(settings.line_count, def.line_count);
         assert_eq!(settings.width, def.width);
         assert_eq!(settings.padding, def.padding);
@@ -1342,7 +2636,7 @@ mod tests {
         let mut cfg = parse_config(r#"theme = "nord""#);
         let pack = parse_pack("[themes.nord]\nbg = \"#2e3440\"\n");
         merge_packs(&mut cfg, vec![pack]);
-        let (settings, warnings) = resolve_settings(&cfg);
+        let (settings, warnings) = resolve_settings(&cfg, AppMode::Dark);
         assert!(warnings.is_empty());
         assert_eq!(settings.bg, parse_color("#2e3440").unwrap());
     }
@@ -1377,18 +2671,19 @@ mod tests {
     }
 
     #[test]
-    fn pack_ignores_nested_import_and_stray_keys() {
-        // A pack carries only themes/commands; hotkey/bg/import are silently ignored.
+    fn pack_reads_nested_import_and_ignores_stray_keys() {
+        // A pack carries themes/commands and its own imports; hotkey/bg are silently ignored.
         let pack = parse_pack(
             "hotkey = [\"WIN\", \"SPACE\"]\nbg = \"#123456\"\nimport = [\"other.toml\"]\n[themes.z]\nfg = \"#ffffff\"\n",
         );
         assert!(pack.themes.as_ref().unwrap().contains_key("z"));
         assert!(pack.commands.is_none());
+        assert_eq!(pack.import, Some(vec!["other.toml".to_string()]));
     }
 
     #[test]
     fn read_packs_missing_file_warns_and_skips() {
-        let (packs, warnings) = read_packs(&["does-not-exist.toml".to_string()], std::path::Path::new("."));
+        let (packs, warnings) = read_packs(&["does-not-exist.toml".to_string()], std::path::Path::new("."), false);
         assert!(packs.is_empty());
         assert_eq!(warnings.len(), 1);
         assert!(warnings[0].contains("not found"));
@@ -1400,11 +2695,167 @@ mod tests {
         let name = format!("windmenu-pack-test-{}.toml", std::process::id());
         let path = dir.join(&name);
         std::fs::write(&path, "[themes.temp]\nbg = \"#010203\"\n").unwrap();
-        let (packs, warnings) = read_packs(&[name], &dir);
+        let (packs, warnings) = read_packs(&[name], &dir, false);
         let _ = std::fs::remove_file(&path);
         assert!(warnings.is_empty());
         assert_eq!(packs.len(), 1);
         assert!(packs[0].themes.as_ref().unwrap().contains_key("temp"));
     }
 
+    #[test]
+    fn versions_compare_numerically() {
+        assert_eq!(parse_version("v0.10.0"), Some(vec![0, 10]));
+        assert!(parse_version("0.10") > parse_version("0.9.3"));
+        assert_eq!(parse_version("1.0"), parse_version("1"));
+        assert_eq!(parse_version("1.x"), None);
+        assert_eq!(min_version_problem("0.0.1"), None);
+        assert!(min_version_problem("999.0").unwrap().starts_with("needs windmenu 999.0 or newer"));
+        assert!(min_version_problem("soon").is_some());
+    }
+
+    #[test]
+    fn pack_metadata_is_described_with_contributions() {
+        let pack = Pack {
+            origin: "packs/power.toml".to_string(),
+            ..parse_pack(
+                r##"
+                [pack]
+                name = "power"
+                version = "1.2.0"
+                author = "Jane"
+                min_version = "0.5"
+
+                [[commands]]
+                name = "Lock"
+                keys = ["WIN", "L"]
+
+                [themes.dusk]
+                bg = "#101010"
+            "##,
+            )
+        };
+        assert_eq!(
+            pack.describe(),
+            "power 1.2.0 (packs/power.toml)\n  author:   Jane\n  requires: windmenu 0.5\n  \
+             themes:   dusk\n  commands: Lock [WIN, L]"
+        );
+        let bare = Pack { origin: "empty.toml".to_string(), ..Pack::default() };
+        assert_eq!(bare.describe(), "empty.toml\n  (contributes nothing)");
+    }
+
+    #[test]
+    fn resolved_values_carry_their_origin() {
+        let cfg = parse_config(
+            r##"
+            theme = "nord"
+            lines = 8
+            fg = "#eceff4"
+            bg_select = "oops"
+            min_contrast = 0
+
+            [[commands]]
+            name = "Terminal"
+            args = ["wt"]
+        "##,
+        );
+        let nord = Pack {
+            origin: "packs/nord.toml".to_string(),
+            ..parse_pack("[themes.nord]\nbg = \"#2e3440\"\nbg_select = \"#88c0d0\"\n")
+        };
+        let power = Pack {
+            origin: "packs/power.toml".to_string(),
+            ..parse_pack("[[commands]]\nname = \"Lock\"\nkeys = [\"WIN\", \"L\"]\n\n[[commands]]\nname = \"Terminal\"\nargs = [\"cmd\"]\n")
+        };
+        let (resolved, warnings) = Resolved::new(cfg, vec![nord, power], Path::new("windmenu.toml"), AppMode::Dark);
+        assert!(warnings.is_empty());
+        let root = Origin::Root { file: "windmenu.toml".to_string() };
+
+        let setting = |key: &str| resolved.settings.iter().find(|v| v.key == key).unwrap();
+        assert_eq!((setting("lines").value.as_integer(), &setting("lines").origin), (Some(8), &root));
+        assert_eq!(setting("width").origin, Origin::Default);
+        assert_eq!(setting("font").value.as_str(), Some("Consolas 20"));
+        assert_eq!(setting("matching").value.as_str(), Some("fuzzy"));
+
+        let color = |key: &str| resolved.colors.iter().find(|v| v.key == key).unwrap();
+        let nord_theme = Origin::Theme { name: "nord".to_string(), file: "packs/nord.toml".to_string() };
+        assert_eq!((color("bg").value.as_str(), &color("bg").origin), (Some("#2e3440"), &nord_theme));
+        assert_eq!((color("fg").value.as_str(), &color("fg").origin), (Some("#eceff4"), &root));
+        // An unparseable override leaves the theme's color in effect
+        assert_eq!(color("bg_select").origin, nord_theme);
+        assert_eq!(color("bg_input").origin, Origin::Default);
+
+        let commands: Vec<_> = resolved.commands.iter().map(|c| (c.name.as_str(), c.origin.to_string())).collect();
+        assert_eq!(commands, vec![("Lock", "packs/power.toml".to_string()), ("Terminal", "windmenu.toml".to_string())]);
+        assert_eq!(resolved.commands[1].args, Some(vec!["wt".to_string()]));
+
+        let text = resolved.to_toml();
+        assert!(text.contains("lines = 8 # windmenu.toml\n"));
+        assert!(text.contains("bg = \"#2e3440\" # theme 'nord' (packs/nord.toml)\n"));
+        assert!(text.contains("[[commands]] # packs/power.toml\nname = \"Lock\"\nkeys = [\"WIN\", \"L\"]\n"));
+        // The rendered view is itself a loadable config
+        let reparsed = parse_config(&text);
+        assert_eq!(reparsed.lines, Some(8));
+        assert_eq!(reparsed.commands.map(|c| c.len()), Some(2));
+    }
+
+    #[test]
+    fn root_origin_names_the_config_file_in_use() {
+        let cfg = parse_config("lines = 8\n[aliases]\nTerminal = [\"wt\"]\n");
+        let (resolved, _) = Resolved::new(cfg, Vec::new(), Path::new("D:/dotfiles/windmenu.toml"), AppMode::Dark);
+        let text = resolved.to_toml();
+        assert!(text.contains("lines = 8 # D:/dotfiles/windmenu.toml\n"));
+        assert!(text.contains("[aliases] # D:/dotfiles/windmenu.toml\n"));
+    }
+
+    #[test]
+    fn extended_theme_colors_come_from_the_defining_theme() {
+        let cfg = parse_config("theme = \"nord-light\"\nmin_contrast = 0\n[themes.nord-light]\nextends = \"nord\"\nbg = \"#eceff4\"\n");
+        let nord = Pack {
+            origin: "packs/nord.toml".to_string(),
+            ..parse_pack("[themes.nord]\nbg = \"#2e3440\"\nfg = \"#d8dee9\"\n")
+        };
+        let (resolved, warnings) = Resolved::new(cfg, vec![nord], Path::new("windmenu.toml"), AppMode::Dark);
+        assert!(warnings.is_empty());
+        let color = |key: &str| resolved.colors.iter().find(|v| v.key == key).unwrap();
+        assert_eq!(color("bg").value.as_str(), Some("#eceff4"));
+        assert_eq!(color("bg").origin.to_string(), "theme 'nord-light' (windmenu.toml)");
+        assert_eq!(color("fg").value.as_str(), Some("#d8dee9"));
+        assert_eq!(color("fg").origin.to_string(), "theme 'nord' (packs/nord.toml)");
+        assert_eq!(color("bg_select").origin, Origin::Default);
+    }
+
+    #[test]
+    fn low_contrast_is_warned_with_origins() {
+        let nord = || Pack {
+            origin: "packs/nord.toml".to_string(),
+            ..parse_pack("[themes.nord]\nbg = \"#2e3440\"\nfg = \"#3b4252\"\nbg_select = \"#88c0d0\"\n")
+        };
+        let cfg = parse_config("theme = \"nord\"\nfg_select = \"#eceff4\"\n");
+        let (resolved, warnings) = Resolved::new(cfg, vec![nord()], Path::new("windmenu.toml"), AppMode::Dark);
+        let nord_theme = "theme 'nord' (packs/nord.toml)";
+        assert_eq!(
+            warnings,
+            vec![
+                format!("fg #3b4252 on bg #2e3440 has contrast 1.2:1, below min_contrast 3 (both from {})", nord_theme),
+                format!(
+                    "fg_select #eceff4 on bg_select #88c0d0 has contrast 1.7:1, below min_contrast 3 \
+                     (fg_select from windmenu.toml, bg_select from {})",
+                    nord_theme
+                ),
+                format!(
+                    "fg_match_select #ffe680 on bg_select #88c0d0 has contrast 1.6:1, below min_contrast 3 \
+                     (fg_match_select from default, bg_select from {})",
+                    nord_theme
+                ),
+            ]
+        );
+        let setting = resolved.settings.iter().find(|v| v.key == "min_contrast").unwrap();
+        assert_eq!((setting.value.as_float(), &setting.origin), (Some(3.0), &Origin::Default));
+
+        let cfg = parse_config("theme = \"nord\"\nfg_select = \"#eceff4\"\nmin_contrast = 1.5\n");
+        let (_, warnings) = Resolved::new(cfg, vec![nord()], Path::new("windmenu.toml"), AppMode::Dark);
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0].starts_with("fg #3b4252 on bg #2e3440"));
+    }
+
 }
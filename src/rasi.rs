@@ -0,0 +1,358 @@
+//! rofi `.rasi` themes for `windmenu theme import`: the subset that maps onto
+//! windmenu. Colors come from the `*` variables and the widgets drawn the
+//! same way (`window`, `element selected`, `inputbar`/`entry`, `prompt`,
+//! `listview`); `font`, `lines`, `width`, `padding`, `border` and the prompt
+//! text become window settings. Everything else is listed as skipped.
+
+use std::collections::HashSet;
+
+use crate::color;
+use crate::scheme::{self, Converted, Scheme};
+use crate::theme::{self, Palette};
+
+/// How deep `@name`/`var(name)` references may chain.
+const MAX_REFERENCE_DEPTH: usize = 8;
+
+/// One `selector { ... }` block. A list of selectors becomes one section each.
+struct Section {
+    selector: String,
+    properties: Vec<(String, String)>,
+}
+
+/// `element.selected.normal`, `#element selected.normal` and
+/// `element selected normal` all name the same thing.
+fn normalize_selector(selector: &str) -> String {
+    let selector = selector.trim().trim_start_matches('#').replace('.', " ").to_lowercase();
+    selector.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Split on `separator` outside strings, brackets and parentheses.
+fn split_top_level(text: &str, separator: char) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let (mut depth, mut in_string, mut start) = (0i32, false, 0);
+    for (i, c) in text.char_indices() {
+        match c {
+            '"' => in_string = !in_string,
+            '(' | '[' | '{' if !in_string => depth += 1,
+            ')' | ']' | '}' if !in_string => depth -= 1,
+            c if c == separator && !in_string && depth == 0 => {
+                parts.push(&text[start..i]);
+                start = i + c.len_utf8();
+            }
+            _ => {}
+        }
+    }
+    parts.push(&text[start..]);
+    parts
+}
+
+/// Byte index of the `}` closing the `{` at `open`.
+fn matching_brace(text: &str, open: usize) -> Option<usize> {
+    let (mut depth, mut in_string) = (0, false);
+    for (i, c) in text[open..].char_indices() {
+        match c {
+            '"' => in_string = !in_string,
+            '{' if !in_string => depth += 1,
+            '}' if !in_string => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(open + i);
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+/// The sections of a theme, and the `@`-statements (`@import`, `@media`, ...)
+/// that were not followed.
+fn parse_sections(text: &str) -> Result<(Vec<Section>, Vec<String>), String> {
+    let text = scheme::strip_comments(text);
+    let (mut sections, mut statements) = (Vec::new(), Vec::new());
+    let mut rest = text.trim_start();
+    while !rest.is_empty() {
+        let open = rest.find('{');
+        if rest.starts_with('@') {
+            let end = rest.find([';', '\n']).unwrap_or(rest.len());
+            if open.is_none_or(|open| end < open) {
+                statements.push(rest[..end].trim().to_string());
+                rest = rest[end..].trim_start_matches(';').trim_start();
+                continue;
+            }
+        }
+        let open = open.ok_or_else(|| format!("expected '{{' after '{}'", rest.lines().next().unwrap_or("").trim()))?;
+        let close = matching_brace(rest, open).ok_or("unclosed '{'")?;
+        let (head, body) = (rest[..open].trim(), &rest[open + 1..close]);
+        if head.starts_with('@') {
+            statements.push(head.to_string());
+        } else {
+            let properties: Vec<(String, String)> = split_top_level(body, ';')
+                .into_iter()
+                .filter_map(|declaration| {
+                    let (name, value) = declaration.split_once(':')?;
+                    Some((name.trim().to_lowercase(), value.trim().to_string()))
+                })
+                .collect();
+            for selector in split_top_level(head, ',') {
+                let selector = normalize_selector(selector);
+                sections.push(Section { selector, properties: properties.clone() });
+            }
+        }
+        rest = rest[close + 1..].trim_start();
+    }
+    Ok((sections, statements))
+}
+
+/// Property lookups over the parsed sections, remembering which properties
+/// were used so the rest can be reported.
+struct Theme {
+    sections: Vec<Section>,
+    used: HashSet<(String, String)>,
+}
+
+impl Theme {
+    /// The raw value of `property` in the last section named by any of
+    /// `selectors` (later blocks override earlier ones, as in rofi). The
+    /// overridden ones count as used too.
+    fn raw(&mut self, selectors: &[&str], property: &str) -> Option<String> {
+        let mut value = None;
+        for section in &self.sections {
+            if !selectors.contains(&section.selector.as_str()) {
+                continue;
+            }
+            if let Some((_, v)) = section.properties.iter().rev().find(|(name, _)| name == property) {
+                value = Some(v.clone());
+                self.used.insert((section.selector.clone(), property.to_string()));
+            }
+        }
+        value
+    }
+
+    /// Follow `@name` and `var(name, fallback)` references to the `*`
+    /// variables.
+    fn resolve(&mut self, value: &str, depth: usize) -> Option<String> {
+        let value = value.trim();
+        if depth > MAX_REFERENCE_DEPTH || value == "inherit" {
+            return None;
+        }
+        if let Some(name) = value.strip_prefix('@') {
+            let referenced = self.raw(&["*"], &name.to_lowercase())?;
+            return self.resolve(&referenced, depth + 1);
+        }
+        if let Some(args) = value.strip_prefix("var(").and_then(|v| v.strip_suffix(')')) {
+            let (name, fallback) = args.split_once(',').unwrap_or((args, ""));
+            if let Some(referenced) = self.raw(&["*"], &name.trim().to_lowercase()) {
+                if let Some(resolved) = self.resolve(&referenced, depth + 1) {
+                    return Some(resolved);
+                }
+            }
+            let fallback = fallback.trim();
+            return if fallback.is_empty() { None } else { self.resolve(fallback, depth + 1) };
+        }
+        Some(value.to_string())
+    }
+
+    fn value(&mut self, selectors: &[&str], property: &str) -> Option<String> {
+        let raw = self.raw(selectors, property)?;
+        self.resolve(&raw, 0)
+    }
+
+    /// The first candidate `(selectors, property)` holding a visible color,
+    /// as `#rrggbb`. Values like `bold #ff0000` (highlight) or `2px solid
+    /// @accent` (border) give their color part; transparent ones are passed
+    /// over.
+    fn color(&mut self, candidates: &[(&[&str], &str)]) -> Option<String> {
+        candidates.iter().find_map(|&(selectors, property)| {
+            let raw = self.raw(selectors, property)?;
+            std::iter::once(raw.as_str())
+                .chain(raw.split_whitespace())
+                .filter_map(|part| self.resolve(part, 0))
+                .find_map(|part| match color::parse(&part) {
+                    Some((rgb, alpha)) if alpha > 0 => Some(color::hex(rgb)),
+                    _ => None,
+                })
+        })
+    }
+}
+
+/// A length in pixels: `12px` or a bare `12`. Relative units (`%`, `em`,
+/// `ch`, `mm`) have no windmenu equivalent.
+fn pixels(value: &str) -> Option<usize> {
+    let value = value.trim();
+    value.strip_suffix("px").unwrap_or(value).trim().parse().ok()
+}
+
+/// Convert a rofi theme into one windmenu theme called `name`, the window
+/// settings it implies and the properties windmenu can't represent.
+pub fn convert(text: &str, name: &str) -> Result<Converted, String> {
+    let (sections, statements) = parse_sections(text)?;
+    let mut rasi = Theme { sections, used: HashSet::new() };
+    const ALL: &[&str] = &["*"];
+    const WINDOW: &[&str] = &["window"];
+    const ELEMENT: &[&str] = &["element", "element normal normal", "element normal", "element-text"];
+    const SELECTED: &[&str] = &["element selected normal", "element selected", "element-text selected"];
+    const INPUT: &[&str] = &["entry", "inputbar"];
+    const PROMPT: &[&str] = &["prompt"];
+
+    let bg = rasi.color(&[
+        (WINDOW, "background-color"),
+        (ALL, "background-color"),
+        (ALL, "background"),
+        (ALL, "normal-background"),
+    ]);
+    let fg = rasi.color(&[(ELEMENT, "text-color"), (ALL, "text-color"), (ALL, "foreground"), (ALL, "normal-foreground")]);
+    if bg.is_none() && fg.is_none() {
+        return Err("no background or text color found".to_string());
+    }
+    let palette = Palette {
+        bg_select: rasi.color(&[(SELECTED, "background-color"), (ALL, "selected-normal-background")]),
+        fg_select: rasi.color(&[(SELECTED, "text-color"), (ALL, "selected-normal-foreground")]),
+        bg_input: rasi.color(&[(INPUT, "background-color")]).or_else(|| bg.clone()),
+        fg_input: rasi.color(&[(INPUT, "text-color")]).or_else(|| fg.clone()),
+        fg_match: rasi.color(&[(ELEMENT, "highlight")]),
+        fg_match_select: rasi.color(&[(SELECTED, "highlight")]),
+        bg_prompt: rasi.color(&[(PROMPT, "background-color")]),
+        fg_prompt: rasi.color(&[(PROMPT, "text-color")]),
+        border: rasi.color(&[(WINDOW, "border-color")]),
+        separator: rasi.color(&[(&["listview"], "border-color"), (ALL, "separatorcolor")]),
+        bg,
+        fg,
+        ..Palette::default()
+    };
+
+    let mut settings: Vec<(&'static str, String)> = Vec::new();
+    let mut skipped: Vec<String> = Vec::new();
+    let quote = |s: &str| toml::Value::String(s.to_string()).to_string();
+    let config: &[&str] = &["configuration"];
+    let font_sources: [&[&str]; 4] = [config, ALL, WINDOW, &["element", "element-text"]];
+    if let Some(font) = font_sources.iter().find_map(|selectors| rasi.value(selectors, "font")) {
+        let font = font.trim_matches('"');
+        match theme::parse_font(font) {
+            (Some(family), Some(size)) => settings.push(("font", quote(&format!("{} {}", family, size)))),
+            (Some(family), None) => settings.push(("font", quote(&family))),
+            _ => {}
+        }
+    }
+    let lines = rasi.value(&["listview"], "lines").or_else(|| rasi.value(config, "lines"));
+    if let Some(lines) = lines {
+        match lines.parse::<usize>() {
+            Ok(lines) => settings.push(("lines", lines.to_string())),
+            Err(_) => skipped.push(format!("listview lines: '{}' is not a number", lines)),
+        }
+    }
+    let mut length = |key: &'static str, property: &str, value: Option<String>| {
+        let Some(value) = value else { return };
+        let mut parts = value.split_whitespace();
+        match parts.next().and_then(pixels) {
+            Some(px) if parts.next().is_none() => settings.push((key, px.to_string())),
+            // `padding: 8px 16px` and friends: windmenu has one padding
+            Some(px) if key == "padding" => {
+                settings.push((key, px.to_string()));
+                skipped.push(format!("window {}: '{}' has per-side values; used {}px", property, value, px));
+            }
+            _ => skipped.push(format!("window {}: '{}' is not a pixel length", property, value)),
+        }
+    };
+    length("width", "width", rasi.value(WINDOW, "width"));
+    length("padding", "padding", rasi.value(WINDOW, "padding"));
+    length("border_width", "border", rasi.value(WINDOW, "border"));
+    let prompt = ["display-drun", "display-run", "display-combi"]
+        .iter()
+        .find_map(|key| rasi.value(config, key))
+        .or_else(|| rasi.value(&["prompt", "textbox-prompt-colon"], "str"));
+    if let Some(prompt) = prompt {
+        settings.push(("prompt", quote(prompt.trim_matches('"'))));
+    }
+
+    // What's left: properties of real widgets nothing above read. The `*`
+    // variables and rofi's non-theme `configuration` options aren't listed
+    for section in &rasi.sections {
+        if matches!(section.selector.as_str(), "*" | "configuration") {
+            continue;
+        }
+        let unused: Vec<&str> = section
+            .properties
+            .iter()
+            .map(|(name, _)| name.as_str())
+            .filter(|name| !rasi.used.contains(&(section.selector.clone(), name.to_string())))
+            .collect();
+        if !unused.is_empty() {
+            skipped.push(format!("{}: {}", section.selector, unused.join(", ")));
+        }
+    }
+    skipped.extend(statements.into_iter().map(|s| format!("{} (not followed)", s)));
+
+    let scheme = Scheme { name: name.to_string(), author: None, palette };
+    Ok(Converted { schemes: vec![scheme], settings, skipped })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{convert, normalize_selector};
+
+    const NORD: &str = include_str!("../tests/fixtures/nord.rasi");
+
+    #[test]
+    fn selectors_normalize() {
+        assert_eq!(normalize_selector("#element.selected.normal"), "element selected normal");
+        assert_eq!(normalize_selector("  element   selected "), "element selected");
+    }
+
+    #[test]
+    fn colors_follow_variables_into_the_palette() {
+        let converted = convert(NORD, "nord").unwrap();
+        let palette = &converted.schemes[0].palette;
+        let colors: Vec<(&str, &str)> =
+            palette.entries().into_iter().filter_map(|(key, value)| Some((key, value?))).collect();
+        assert_eq!(colors, vec![
+            ("bg", "#2e3440"),
+            ("fg", "#d8dee9"),
+            ("bg_select", "#88c0d0"),
+            ("fg_select", "#2e3440"),
+            ("bg_input", "#3b4252"),
+            ("fg_input", "#eceff4"),
+            ("fg_match", "#ebcb8b"),
+            ("fg_match_select", "#bf616a"),
+            ("bg_prompt", "#5e81ac"),
+            ("fg_prompt", "#eceff4"),
+            ("border", "#88c0d0"),
+            ("separator", "#4c566a"),
+        ]);
+    }
+
+    #[test]
+    fn window_settings_and_skipped_properties_are_reported() {
+        let converted = convert(NORD, "nord").unwrap();
+        assert_eq!(converted.settings, vec![
+            ("font", "\"Iosevka Nerd Font 14\"".to_string()),
+            ("lines", "8".to_string()),
+            ("width", "640".to_string()),
+            ("padding", "12".to_string()),
+            ("border_width", "2".to_string()),
+            ("prompt", "\"Run\"".to_string()),
+        ]);
+        assert_eq!(converted.skipped, vec![
+            "window padding: '12px 24px' has per-side values; used 12px".to_string(),
+            "window: border-radius, location".to_string(),
+            "element-icon: size".to_string(),
+            "@import \"shared/colors.rasi\" (not followed)".to_string(),
+        ]);
+    }
+
+    #[test]
+    fn minimal_and_broken_themes() {
+        let converted = convert("* { background: #000; foreground: rgba(255, 255, 255, 100%); }", "m").unwrap();
+        let palette = &converted.schemes[0].palette;
+        assert_eq!((palette.bg.as_deref(), palette.fg.as_deref()), (Some("#000000"), Some("#ffffff")));
+        assert_eq!(palette.bg_input, palette.bg);
+        assert!(converted.settings.is_empty() && converted.skipped.is_empty());
+
+        // A transparent window shows nothing useful: the next candidate wins
+        let converted = convert("window { background-color: transparent; } * { background: #111; }", "t");
+        assert_eq!(converted.unwrap().schemes[0].palette.bg.as_deref(), Some("#111111"));
+
+        assert!(convert("listview { lines: 5; }", "x").is_err());
+        assert!(convert("window { background-color: #000;", "x").is_err());
+    }
+}
@@ -0,0 +1,440 @@
+//! `windmenu theme import`: convert color schemes kept for other programs
+//! into a pack of `[themes.<name>]` ready for `import`. Each format maps onto
+//! the palette keys like this (the first source key present wins; keys with
+//! no source keep windmenu's defaults):
+//!
+//! | windmenu          | Windows Terminal scheme        | base16   |
+//! |-------------------|--------------------------------|----------|
+//! | `bg`              | `background`                   | `base00` |
+//! | `fg`              | `foreground`                   | `base05` |
+//! | `bg_select`       | `selectionBackground`, `blue`  | `base02` |
+//! | `fg_select`       | `foreground`                   | `base06` |
+//! | `bg_input`        | `black`, `background`          | `base01` |
+//! | `fg_input`        | `foreground`                   | `base05` |
+//! | `fg_match`        | `yellow`                       | `base0A` |
+//! | `fg_match_select` | `brightYellow`, `yellow`       | `base0A` |
+//!
+//! rofi `.rasi` themes are converted by `rasi`.
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::color;
+use crate::menu;
+use crate::rasi;
+use crate::theme::Palette;
+
+type Mapping = [(&'static str, &'static [&'static str]); 8];
+
+const WINDOWS_TERMINAL: Mapping = [
+    ("bg", &["background"]),
+    ("fg", &["foreground"]),
+    ("bg_select", &["selectionBackground", "blue"]),
+    ("fg_select", &["foreground"]),
+    ("bg_input", &["black", "background"]),
+    ("fg_input", &["foreground"]),
+    ("fg_match", &["yellow"]),
+    ("fg_match_select", &["brightYellow", "yellow"]),
+];
+
+// Keys are matched lowercased: files differ on `base0A` vs `base0a`
+const BASE16: Mapping = [
+    ("bg", &["base00"]),
+    ("fg", &["base05"]),
+    ("bg_select", &["base02"]),
+    ("fg_select", &["base06"]),
+    ("bg_input", &["base01"]),
+    ("fg_input", &["base05"]),
+    ("fg_match", &["base0a"]),
+    ("fg_match_select", &["base0a"]),
+];
+
+/// One imported color scheme.
+#[derive(Debug)]
+pub struct Scheme {
+    pub name: String,
+    pub author: Option<String>,
+    pub palette: Palette,
+}
+
+/// What an import produced: the schemes, and for formats that describe more
+/// than colors, the window settings they imply (a pack can't carry those, so
+/// they are suggested for windmenu.toml) and what couldn't be represented.
+#[derive(Debug, Default)]
+pub struct Converted {
+    pub schemes: Vec<Scheme>,
+    pub settings: Vec<(&'static str, String)>, // key, TOML value
+    pub skipped: Vec<String>,
+}
+
+/// A theme name usable as a bare TOML key: lowercase letters, digits and
+/// dashes ("One Half Dark" becomes "one-half-dark").
+pub fn slug(name: &str) -> String {
+    let mut slug = String::new();
+    for c in name.chars().flat_map(char::to_lowercase) {
+        if c.is_ascii_alphanumeric() {
+            slug.push(c);
+        } else if !slug.is_empty() && !slug.ends_with('-') {
+            slug.push('-');
+        }
+    }
+    let slug = slug.trim_end_matches('-');
+    if slug.is_empty() { "imported".to_string() } else { slug.to_string() }
+}
+
+/// Build a palette from `mapping`, looking source keys up with `get`. Colors
+/// are normalized to `#rrggbb`; the two base keys must be present.
+fn palette(mapping: &Mapping, get: impl Fn(&str) -> Option<String>) -> Result<Palette, String> {
+    let mut colors: HashMap<&str, String> = HashMap::new();
+    for (key, sources) in mapping {
+        let Some((source, value)) = sources.iter().find_map(|s| get(s).map(|v| (s, v))) else {
+            if matches!(*key, "bg" | "fg") {
+                return Err(format!("no '{}' color", sources[0]));
+            }
+            continue;
+        };
+        let (rgb, _) = color::parse(&value).ok_or_else(|| format!("invalid color '{}' for '{}'", value, source))?;
+        colors.insert(key, color::hex(rgb));
+    }
+    let mut take = |key| colors.remove(key);
+    Ok(Palette {
+        bg: take("bg"),
+        fg: take("fg"),
+        bg_select: take("bg_select"),
+        fg_select: take("fg_select"),
+        bg_input: take("bg_input"),
+        fg_input: take("fg_input"),
+        fg_match: take("fg_match"),
+        fg_match_select: take("fg_match_select"),
+        ..Palette::default()
+    })
+}
+
+/// Drop `//` and `/* */` comments and trailing commas, which Windows Terminal
+/// accepts in settings.json but JSON parsers don't (and which rasi allows).
+pub(crate) fn strip_comments(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+    let mut in_string = false;
+    while let Some(c) = chars.next() {
+        if in_string {
+            out.push(c);
+            match c {
+                '\\' => out.extend(chars.next()),
+                '"' => in_string = false,
+                _ => {}
+            }
+            continue;
+        }
+        match (c, chars.peek()) {
+            ('"', _) => {
+                in_string = true;
+                out.push(c);
+            }
+            ('/', Some('/')) => {
+                while chars.next_if(|&c| c != '\n').is_some() {}
+            }
+            ('/', Some('*')) => {
+                chars.next();
+                let mut previous = ' ';
+                for c in chars.by_ref() {
+                    if previous == '*' && c == '/' {
+                        break;
+                    }
+                    previous = c;
+                }
+            }
+            (']' | '}', _) => {
+                let trimmed = out.trim_end().len();
+                if out[..trimmed].ends_with(',') {
+                    out.truncate(trimmed - 1);
+                }
+                out.push(c);
+            }
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+/// The schemes in a Windows Terminal settings.json (its `schemes` array), or
+/// a single scheme object as copied out of one.
+pub fn from_windows_terminal(text: &str) -> Result<Vec<Scheme>, String> {
+    let json: serde_json::Value =
+        serde_json::from_str(&strip_comments(text)).map_err(|e| format!("invalid JSON: {}", e))?;
+    let objects: Vec<&serde_json::Value> = match json.get("schemes") {
+        Some(schemes) => schemes.as_array().ok_or("'schemes' is not an array")?.iter().collect(),
+        None if json.get("background").is_some() => vec![&json],
+        None => return Err("no color schemes found (expected a 'schemes' array or a scheme object)".to_string()),
+    };
+    objects
+        .into_iter()
+        .enumerate()
+        .map(|(i, scheme)| {
+            let name = scheme.get("name").and_then(|v| v.as_str()).map(str::to_string);
+            let label = name.clone().unwrap_or_else(|| format!("#{}", i + 1));
+            let palette = palette(&WINDOWS_TERMINAL, |key| {
+                scheme.get(key).and_then(|v| v.as_str()).map(str::to_string)
+            })
+            .map_err(|e| format!("scheme {}: {}", label, e))?;
+            Ok(Scheme { name: name.unwrap_or(label), author: None, palette })
+        })
+        .collect()
+}
+
+/// The `key: value` pairs of a flat (or one-level nested) YAML file, keys
+/// lowercased and values unquoted; enough for base16 scheme files.
+fn yaml_fields(text: &str) -> HashMap<String, String> {
+    let mut fields = HashMap::new();
+    for line in text.lines() {
+        let line = line.trim();
+        if line.starts_with('#') || line.starts_with("---") {
+            continue;
+        }
+        let Some((key, value)) = line.split_once(':') else { continue };
+        let value = value.trim();
+        let value = match value.chars().next() {
+            Some(quote @ ('"' | '\'')) => value[1..].split(quote).next().unwrap_or(""),
+            _ => value.split(" #").next().unwrap_or("").trim(),
+        };
+        if !value.is_empty() {
+            fields.insert(key.trim().to_lowercase(), value.to_string());
+        }
+    }
+    fields
+}
+
+/// A base16 scheme, in the classic layout (`scheme:` and top-level
+/// `base00`...) or the tinted-theming one (`name:` and a `palette:` map).
+pub fn from_base16(text: &str) -> Result<Scheme, String> {
+    let fields = yaml_fields(text);
+    let palette = palette(&BASE16, |key| fields.get(key).cloned()).map_err(|e| format!("not a base16 scheme: {}", e))?;
+    let name = fields.get("scheme").or_else(|| fields.get("name")).cloned().unwrap_or_else(|| "base16".to_string());
+    Ok(Scheme { name, author: fields.get("author").cloned(), palette })
+}
+
+/// Parse `text` by the file's extension: `.json` is Windows Terminal,
+/// `.yaml`/`.yml` base16, `.rasi` rofi (named after the file); anything else
+/// by whether it looks like JSON.
+pub fn parse(path: &Path, text: &str) -> Result<Converted, String> {
+    let extension = path.extension().and_then(|e| e.to_str()).map(str::to_lowercase);
+    let schemes = match extension.as_deref() {
+        Some("rasi") => {
+            let name = path.file_stem().unwrap_or_default().to_string_lossy();
+            return rasi::convert(text, &name);
+        }
+        Some("json") => from_windows_terminal(text)?,
+        Some("yaml" | "yml") => vec![from_base16(text)?],
+        _ if text.trim_start().starts_with(['{', '/']) => from_windows_terminal(text)?,
+        _ => vec![from_base16(text)?],
+    };
+    Ok(Converted { schemes, ..Converted::default() })
+}
+
+/// The pack file for `converted`: a `[pack]` header, then one theme per
+/// scheme under its slugged name. Suggested settings and skipped properties
+/// go in comments at the top.
+pub fn to_pack(converted: &Converted, pack_name: &str, source: &str) -> String {
+    let quote = |s: &str| toml::Value::String(s.to_string()).to_string();
+    let mut out = format!("# Imported from {} by `windmenu theme import`\n", source);
+    if !converted.settings.is_empty() {
+        out.push_str("#\n# Window settings from the same file; a pack can't set them, so copy\n# any you want into windmenu.toml:\n");
+        for (key, value) in &converted.settings {
+            out.push_str(&format!("#   {} = {}\n", key, value));
+        }
+    }
+    if !converted.skipped.is_empty() {
+        out.push_str("#\n# Not representable in windmenu, skipped:\n");
+        for skipped in &converted.skipped {
+            out.push_str(&format!("#   {}\n", skipped));
+        }
+    }
+    out.push_str(&format!("\n[pack]\nname = {}\n", quote(pack_name)));
+    let schemes = &converted.schemes;
+    if let Some(author) = schemes.iter().find_map(|s| s.author.as_deref()) {
+        out.push_str(&format!("author = {}\n", quote(author)));
+    }
+    for scheme in schemes {
+        out.push_str(&format!("\n# {}\n[themes.{}]\n", scheme.name, slug(&scheme.name)));
+        for (key, value) in scheme.palette.entries() {
+            if let Some(value) = value {
+                out.push_str(&format!("{} = \"{}\"\n", key, value));
+            }
+        }
+    }
+    out
+}
+
+/// `theme import`: convert `file` into a pack. A single scheme is named
+/// `name` when given; the pack goes to `output`, or `themes/<name>.toml`
+/// beside the config. Refuses to overwrite without `force`. Returns an exit
+/// code.
+pub fn import(file: &Path, name: Option<&str>, output: Option<&Path>, force: bool) -> i32 {
+    let text = match fs::read_to_string(file) {
+        Ok(text) => text,
+        Err(e) => {
+            eprintln!("theme import: failed to read {}: {}", file.display(), e);
+            return 1;
+        }
+    };
+    let mut converted = match parse(file, &text) {
+        Ok(converted) => converted,
+        Err(e) => {
+            eprintln!("theme import: {}: {}", file.display(), e);
+            return 1;
+        }
+    };
+    let schemes = &mut converted.schemes;
+    if schemes.is_empty() {
+        eprintln!("theme import: {} has no color schemes", file.display());
+        return 1;
+    }
+    let pack_name = match (name, schemes.as_mut_slice()) {
+        (Some(name), [scheme]) => {
+            scheme.name = name.to_string();
+            slug(name)
+        }
+        (Some(_), _) => {
+            eprintln!("theme import: --name needs a file with one scheme; {} has {}", file.display(), schemes.len());
+            return 1;
+        }
+        (None, [scheme]) => slug(&scheme.name),
+        (None, _) => slug(&file.file_stem().unwrap_or_default().to_string_lossy()),
+    };
+
+    let target = match output {
+        Some(path) => path.to_path_buf(),
+        None => match menu::config_dir() {
+            Ok(dir) => dir.join("themes").join(format!("{}.toml", pack_name)),
+            Err(e) => {
+                eprintln!("theme import: {}", e);
+                return 1;
+            }
+        },
+    };
+    if target.exists() && !force {
+        eprintln!("theme import: {} already exists; use --force to overwrite", target.display());
+        return 1;
+    }
+    if let Some(dir) = target.parent() {
+        let _ = fs::create_dir_all(dir);
+    }
+    let source = file.file_name().map_or_else(|| file.display().to_string(), |f| f.to_string_lossy().into_owned());
+    if let Err(e) = fs::write(&target, to_pack(&converted, &pack_name, &source)) {
+        eprintln!("theme import: failed to write {}: {}", target.display(), e);
+        return 1;
+    }
+
+    let schemes = &converted.schemes;
+    println!("Wrote {} with {} theme(s): {}", target.display(), schemes.len(), theme_names(schemes).join(", "));
+    let import = menu::config_dir()
+        .ok()
+        .and_then(|dir| target.strip_prefix(dir).ok().map(PathBuf::from))
+        .unwrap_or_else(|| target.clone());
+    println!("To use it, add to windmenu.toml:");
+    println!("  import = [\"{}\"]", import.display().to_string().replace('\\', "/"));
+    println!("  theme = \"{}\"", theme_names(schemes)[0]);
+    for (key, value) in &converted.settings {
+        println!("  {} = {}", key, value);
+    }
+    if !converted.skipped.is_empty() {
+        println!("Not representable in windmenu, skipped:");
+        for skipped in &converted.skipped {
+            println!("  {}", skipped);
+        }
+    }
+    0
+}
+
+fn theme_names(schemes: &[Scheme]) -> Vec<String> {
+    schemes.iter().map(|s| slug(&s.name)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{from_base16, from_windows_terminal, parse, slug, to_pack, Converted};
+    use crate::theme::Palette;
+    use std::collections::HashMap;
+    use std::path::Path;
+
+    const WINDOWS_TERMINAL: &str = include_str!("../tests/fixtures/windows-terminal-settings.json");
+    const BASE16_NORD: &str = include_str!("../tests/fixtures/base16-nord.yaml");
+    const BASE16_TINTED: &str = include_str!("../tests/fixtures/base16-tinted.yaml");
+
+    fn colors(palette: &Palette) -> Vec<(&'static str, &str)> {
+        palette.entries().into_iter().filter_map(|(key, value)| Some((key, value?))).collect()
+    }
+
+    #[test]
+    fn windows_terminal_schemes_map_onto_the_palette() {
+        let schemes = from_windows_terminal(WINDOWS_TERMINAL).unwrap();
+        assert_eq!(schemes.len(), 2);
+        assert_eq!(schemes[0].name, "Campbell");
+        assert_eq!(colors(&schemes[0].palette), vec![
+            ("bg", "#0c0c0c"),
+            ("fg", "#cccccc"),
+            ("bg_select", "#ffffff"),
+            ("fg_select", "#cccccc"),
+            ("bg_input", "#0c0c0c"),
+            ("fg_input", "#cccccc"),
+            ("fg_match", "#c19c00"),
+            ("fg_match_select", "#f9f1a5"),
+        ]);
+        // No selectionBackground: the scheme's blue stands in
+        assert_eq!(schemes[1].palette.bg_select.as_deref(), Some("#61afef"));
+
+        let single = r##"{ "name": "Mono", "background": "#000000", "foreground": "#ffffff" }"##;
+        let mono = from_windows_terminal(single).unwrap();
+        assert_eq!(colors(&mono[0].palette).len(), 5);
+        assert!(from_windows_terminal(r#"{ "profiles": {} }"#).is_err());
+        let bad = from_windows_terminal(r##"{ "name": "X", "background": "nope", "foreground": "#fff" }"##);
+        assert_eq!(bad.unwrap_err(), "scheme X: invalid color 'nope' for 'background'");
+    }
+
+    #[test]
+    fn base16_layouts_map_onto_the_palette() {
+        let nord = from_base16(BASE16_NORD).unwrap();
+        assert_eq!((nord.name.as_str(), nord.author.as_deref()), ("Nord", Some("arcticicestudio")));
+        assert_eq!(colors(&nord.palette), vec![
+            ("bg", "#2e3440"),
+            ("fg", "#e5e9f0"),
+            ("bg_select", "#434c5e"),
+            ("fg_select", "#eceff4"),
+            ("bg_input", "#3b4252"),
+            ("fg_input", "#e5e9f0"),
+            ("fg_match", "#ebcb8b"),
+            ("fg_match_select", "#ebcb8b"),
+        ]);
+
+        let gruvbox = from_base16(BASE16_TINTED).unwrap();
+        assert_eq!(gruvbox.name, "Gruvbox dark, medium");
+        assert_eq!(gruvbox.author.as_deref(), Some("Dawid Kurek (dawikur@gmail.com)"));
+        assert_eq!(gruvbox.palette.bg.as_deref(), Some("#282828"));
+        assert!(from_base16("scheme: \"Empty\"\n").is_err());
+    }
+
+    #[test]
+    fn format_follows_the_extension() {
+        assert_eq!(parse(Path::new("nord.yml"), BASE16_NORD).unwrap().schemes[0].name, "Nord");
+        assert_eq!(parse(Path::new("settings.json"), WINDOWS_TERMINAL).unwrap().schemes.len(), 2);
+        assert_eq!(parse(Path::new("settings"), WINDOWS_TERMINAL).unwrap().schemes.len(), 2);
+        assert!(parse(Path::new("nord.json"), BASE16_NORD).is_err());
+    }
+
+    #[test]
+    fn pack_round_trips_through_toml() {
+        assert_eq!(slug("One Half Dark"), "one-half-dark");
+        assert_eq!(slug("Gruvbox dark, medium"), "gruvbox-dark-medium");
+        assert_eq!(slug("!!!"), "imported");
+
+        let schemes = from_windows_terminal(WINDOWS_TERMINAL).unwrap();
+        let converted = Converted { schemes, ..Converted::default() };
+        let pack = to_pack(&converted, "terminal", "settings.json");
+        let parsed: toml::Table = toml::from_str(&pack).unwrap();
+        assert_eq!(parsed["pack"]["name"].as_str(), Some("terminal"));
+        let themes: HashMap<String, Palette> = parsed["themes"].clone().try_into().unwrap();
+        assert_eq!(themes["campbell"].bg.as_deref(), Some("#0c0c0c"));
+        assert_eq!(themes["one-half-dark"].fg.as_deref(), Some("#dcdfe4"));
+    }
+}
@@ -1,42 +1,331 @@
 use serde::Deserialize;
+use std::collections::HashMap;
 
+use crate::appmode::DEFAULT_LIGHT;
+use crate::color;
 use crate::wlines::{self, Settings};
 
-/// A menu color scheme: the six colors a `[themes.<name>]` theme defines, and
+/// A menu color scheme: the colors a `[themes.<name>]` theme defines, and
 /// the same keys usable as top-level overrides. Config keys are short
 /// (`bg`/`fg`/…); `bg_input`/`fg_input` map to the renderer's input-box fields
-/// (`bg_edit`/`fg_edit`).
-#[derive(Debug, Default, Deserialize)]
+/// (`bg_edit`/`fg_edit`). Themes written before `fg_match`/`fg_match_select`
+/// existed keep the built-in highlight colors, and the optional prompt,
+/// border and separator colors fall back to what was drawn before they
+/// existed. A theme may `extends` another and set only the colors that differ,
+/// and a color may be derived from the others (`lighten(bg, 8%)`, see
+/// `color::evaluate`).
+#[derive(Debug, Default, Clone, PartialEq, Deserialize)]
 pub struct Palette {
+    pub extends: Option<String>,   // Parent theme, by name
     pub bg: Option<String>,        // Window background
     pub fg: Option<String>,        // Window text
     pub bg_select: Option<String>, // Selected item background
     pub fg_select: Option<String>, // Selected item text
     pub bg_input: Option<String>,  // Input box background
     pub fg_input: Option<String>,  // Input box text
+    pub fg_match: Option<String>,        // Matched characters
+    pub fg_match_select: Option<String>, // Matched characters in the selected item
+    pub bg_prompt: Option<String>,       // Prompt box (default: bg_select)
+    pub fg_prompt: Option<String>,       // Prompt text (default: fg_select)
+    pub border: Option<String>,          // Window border, drawn when border_width > 0 (default: bg_select)
+    pub separator: Option<String>,       // Line between input and entries (default: none)
 }
 
 impl Palette {
     /// Overlay the set colors onto `settings`, leaving unset fields alone.
+    /// A color referring to a key this palette leaves unset reads it from
+    /// `settings` as it was.
     pub fn apply(&self, settings: &mut Settings) {
-        apply_color(&mut settings.bg, &self.bg, "bg");
-        apply_color(&mut settings.fg, &self.fg, "fg");
-        apply_color(&mut settings.bg_select, &self.bg_select, "bg_select");
-        apply_color(&mut settings.fg_select, &self.fg_select, "fg_select");
-        apply_color(&mut settings.bg_edit, &self.bg_input, "bg_input");
-        apply_color(&mut settings.fg_edit, &self.fg_input, "fg_input");
+        let base = settings.clone();
+        let color = |key| self.setting(key, &base);
+        apply_color(&mut settings.bg, color("bg"));
+        apply_color(&mut settings.fg, color("fg"));
+        apply_color(&mut settings.bg_select, color("bg_select"));
+        apply_color(&mut settings.fg_select, color("fg_select"));
+        apply_color(&mut settings.bg_edit, color("bg_input"));
+        apply_color(&mut settings.fg_edit, color("fg_input"));
+        apply_color(&mut settings.fg_match, color("fg_match"));
+        apply_color(&mut settings.fg_match_select, color("fg_match_select"));
+        apply_optional_color(&mut settings.bg_prompt, color("bg_prompt"));
+        apply_optional_color(&mut settings.fg_prompt, color("fg_prompt"));
+        apply_optional_color(&mut settings.border, color("border"));
+        apply_optional_color(&mut settings.separator, color("separator"));
     }
+
+    /// A key's own value, if this palette sets it.
+    fn get(&self, key: &str) -> Option<&str> {
+        self.entries().into_iter().find(|&(k, _)| k == key).and_then(|(_, value)| value)
+    }
+
+    /// Evaluate a key this palette sets, reading unset keys from `base` (see
+    /// `apply`). None when it isn't set.
+    pub fn evaluate(&self, key: &str, base: &Settings) -> Option<Result<[u8; 3], String>> {
+        self.get(key)?;
+        let value = |key: &str| self.get(key).map(String::from);
+        Some(evaluate(key, &value, &|key| drawn(base, key)))
+    }
+
+    /// A set key as the renderer's color, warning when it can't be evaluated.
+    fn setting(&self, key: &str, base: &Settings) -> Option<u32> {
+        match self.evaluate(key, base)? {
+            Ok(rgb) => Some(colorref(rgb)),
+            Err(e) => {
+                eprintln!("Warning: invalid {}: {} — using default", key, e);
+                None
+            }
+        }
+    }
+
+    /// Fill the colors this palette leaves unset from `parent`.
+    fn inherit(&mut self, parent: &Palette) {
+        let fields = [
+            (&mut self.bg, &parent.bg),
+            (&mut self.fg, &parent.fg),
+            (&mut self.bg_select, &parent.bg_select),
+            (&mut self.fg_select, &parent.fg_select),
+            (&mut self.bg_input, &parent.bg_input),
+            (&mut self.fg_input, &parent.fg_input),
+            (&mut self.fg_match, &parent.fg_match),
+            (&mut self.fg_match_select, &parent.fg_match_select),
+            (&mut self.bg_prompt, &parent.bg_prompt),
+            (&mut self.fg_prompt, &parent.fg_prompt),
+            (&mut self.border, &parent.border),
+            (&mut self.separator, &parent.separator),
+        ];
+        for (own, inherited) in fields {
+            if own.is_none() {
+                own.clone_from(inherited);
+            }
+        }
+    }
+
+    /// The keys with their values, in config order.
+    pub fn entries(&self) -> [(&'static str, Option<&str>); 12] {
+        [
+            ("bg", self.bg.as_deref()),
+            ("fg", self.fg.as_deref()),
+            ("bg_select", self.bg_select.as_deref()),
+            ("fg_select", self.fg_select.as_deref()),
+            ("bg_input", self.bg_input.as_deref()),
+            ("fg_input", self.fg_input.as_deref()),
+            ("fg_match", self.fg_match.as_deref()),
+            ("fg_match_select", self.fg_match_select.as_deref()),
+            ("bg_prompt", self.bg_prompt.as_deref()),
+            ("fg_prompt", self.fg_prompt.as_deref()),
+            ("border", self.border.as_deref()),
+            ("separator", self.separator.as_deref()),
+        ]
+    }
+
+    /// The colors `settings` ends up with; the optional ones only when set.
+    pub fn from_settings(settings: &Settings) -> Palette {
+        let color = |c| Some(wlines::format_color(c));
+        Palette {
+            bg: color(settings.bg),
+            fg: color(settings.fg),
+            bg_select: color(settings.bg_select),
+            fg_select: color(settings.fg_select),
+            bg_input: color(settings.bg_edit),
+            fg_input: color(settings.fg_edit),
+            fg_match: color(settings.fg_match),
+            fg_match_select: color(settings.fg_match_select),
+            bg_prompt: settings.bg_prompt.map(wlines::format_color),
+            fg_prompt: settings.fg_prompt.map(wlines::format_color),
+            border: settings.border.map(wlines::format_color),
+            separator: settings.separator.map(wlines::format_color),
+            extends: None,
+        }
+    }
+}
+
+/// Text colors and the background each is drawn on, checked for contrast.
+const CONTRAST_PAIRS: [(&str, &str); 6] = [
+    ("fg", "bg"),
+    ("fg_select", "bg_select"),
+    ("fg_input", "bg_input"),
+    ("fg_match", "bg"),
+    ("fg_match_select", "bg_select"),
+    ("fg_prompt", "bg_prompt"),
+];
+
+/// A text color too close to its background, by key.
+#[derive(Debug, PartialEq)]
+pub struct LowContrast {
+    pub text: &'static str,
+    pub background: &'static str,
+    pub ratio: f64,
 }
 
-fn apply_color(target: &mut u32, color: &Option<String>, name: &str) {
-    if let Some(value) = color {
-        match wlines::parse_color(value) {
-            Some(parsed) => *target = parsed,
-            None => eprintln!("Warning: invalid {} '{}', using default", name, value),
+/// The pairs of a resolved palette whose WCAG contrast ratio is below `min`,
+/// `color` looking up each key. An unset prompt color is drawn, and checked,
+/// as the selection color it falls back to; with both unset the pair is the
+/// selection pair already checked.
+pub fn low_contrast(color: impl Fn(&str) -> Option<[u8; 3]>, min: f64) -> Vec<LowContrast> {
+    let lookup = |key: &'static str| match color(key) {
+        Some(rgb) => Some((key, rgb)),
+        None => {
+            let fallback = match key {
+                "bg_prompt" => "bg_select",
+                "fg_prompt" => "fg_select",
+                _ => return None,
+            };
+            Some((fallback, color(fallback)?))
         }
+    };
+    CONTRAST_PAIRS
+        .iter()
+        .filter_map(|&(text, background)| {
+            let (text_key, text_rgb) = lookup(text)?;
+            let (background_key, background_rgb) = lookup(background)?;
+            if text_key != text && background_key != background {
+                return None;
+            }
+            let ratio = color::contrast(text_rgb, background_rgb);
+            (ratio < min).then_some(LowContrast { text: text_key, background: background_key, ratio })
+        })
+        .collect()
+}
+
+/// The names `name` resolves through, itself first, following `extends`. The
+/// chain stops at a parent that is missing or already in it, with a warning;
+/// "default" and "default-light" need no table, they name built-in palettes.
+pub fn theme_chain<'a>(themes: &'a HashMap<String, Palette>, name: &'a str) -> (Vec<&'a str>, Vec<String>) {
+    let mut chain = vec![name];
+    let mut warnings = Vec::new();
+    let mut current = name;
+    while let Some(parent) = themes.get(current).and_then(|t| t.extends.as_deref()) {
+        if chain.contains(&parent) {
+            warnings.push(format!(
+                "theme '{}' extends '{}', forming a cycle ({} -> {}); ignoring that parent",
+                current,
+                parent,
+                chain.join(" -> "),
+                parent
+            ));
+            break;
+        }
+        if !themes.contains_key(parent) {
+            if builtin_palette(parent).is_none() {
+                warnings.push(format!("theme '{}' extends unknown theme '{}'; ignoring it", current, parent));
+            }
+            break;
+        }
+        chain.push(parent);
+        current = parent;
+    }
+    (chain, warnings)
+}
+
+/// The named theme with every inherited color filled in, or None when there
+/// is no such theme. Parent problems are warned about and skipped.
+pub fn resolve_theme(themes: &HashMap<String, Palette>, name: &str) -> Option<(Palette, Vec<String>)> {
+    let theme = themes.get(name)?;
+    let (chain, warnings) = theme_chain(themes, name);
+    let mut palette = theme.clone();
+    for parent in &chain[1..] {
+        palette.inherit(&themes[*parent]);
+    }
+    let root = chain.last().map_or(theme, |last| &themes[*last]);
+    let builtin = root.extends.as_deref().filter(|parent| !themes.contains_key(*parent));
+    if let Some(builtin) = builtin.and_then(builtin_palette) {
+        palette.inherit(&builtin);
+    }
+    Some((palette, warnings))
+}
+
+fn apply_color(target: &mut u32, color: Option<u32>) {
+    if let Some(color) = color {
+        *target = color;
     }
 }
 
+fn apply_optional_color(target: &mut Option<u32>, color: Option<u32>) {
+    if color.is_some() {
+        *target = color;
+    }
+}
+
+fn colorref([r, g, b]: [u8; 3]) -> u32 {
+    u32::from_le_bytes([r, g, b, 0])
+}
+
+/// The color `settings` draws `key` in, unset optional colors as what they
+/// fall back to.
+fn drawn(settings: &Settings, key: &str) -> Result<[u8; 3], String> {
+    let color = match key {
+        "bg" => settings.bg,
+        "fg" => settings.fg,
+        "bg_select" => settings.bg_select,
+        "fg_select" => settings.fg_select,
+        "bg_input" => settings.bg_edit,
+        "fg_input" => settings.fg_edit,
+        "fg_match" => settings.fg_match,
+        "fg_match_select" => settings.fg_match_select,
+        "bg_prompt" => settings.bg_prompt.unwrap_or(settings.bg_select),
+        "fg_prompt" => settings.fg_prompt.unwrap_or(settings.fg_select),
+        "border" => settings.border.unwrap_or(settings.bg_select),
+        "separator" => settings.separator.ok_or("separator is not set")?,
+        _ => return Err(format!("'{}' is not a palette key", key)),
+    };
+    let [r, g, b, _] = color.to_le_bytes();
+    Ok([r, g, b])
+}
+
+/// Why a palette key can't be evaluated. A cycle fails every key that leads
+/// into it; any other mistake only the key written wrong.
+enum Invalid {
+    Cycle(String),
+    Value(String),
+}
+
+/// Evaluate palette key `key` from the raw values `value` looks up: a color,
+/// another key, or an expression over them (`color::evaluate`). A key without
+/// a value, or whose value is itself invalid (and warned about on its own),
+/// reads `unset`.
+pub fn evaluate(
+    key: &str,
+    value: &dyn Fn(&str) -> Option<String>,
+    unset: &dyn Fn(&str) -> Result<[u8; 3], String>,
+) -> Result<[u8; 3], String> {
+    evaluate_key(key, value, unset, &mut Vec::new()).map_err(|(Invalid::Cycle(e) | Invalid::Value(e))| e)
+}
+
+fn evaluate_key(
+    key: &str,
+    value: &dyn Fn(&str) -> Option<String>,
+    unset: &dyn Fn(&str) -> Result<[u8; 3], String>,
+    stack: &mut Vec<String>,
+) -> Result<[u8; 3], Invalid> {
+    let Some(raw) = value(key) else {
+        return unset(key).map_err(Invalid::Value);
+    };
+    stack.push(key.to_string());
+    let mut cycle = None;
+    let result = color::evaluate(&raw, &mut |name| {
+        if !Palette::default().entries().iter().any(|&(k, _)| k == name) {
+            return None;
+        }
+        if let Some(start) = stack.iter().position(|k| k == name) {
+            let message = format!("{} -> {} forms a cycle", stack[start..].join(" -> "), name);
+            cycle = Some(message.clone());
+            return Some(Err(message));
+        }
+        Some(match evaluate_key(name, value, unset, stack) {
+            Ok(rgb) => Ok(rgb),
+            Err(Invalid::Cycle(e)) => {
+                cycle = Some(e.clone());
+                Err(e)
+            }
+            Err(Invalid::Value(_)) => unset(name),
+        })
+    });
+    stack.pop();
+    result.map_err(|e| match cycle {
+        Some(cycle) => Invalid::Cycle(cycle),
+        None => Invalid::Value(e),
+    })
+}
+
 /// Parse a rofi-style font spec ("Family Size") into name and size. A trailing
 /// integer is taken as the point size; otherwise the whole string is the family.
 pub fn parse_font(spec: &str) -> (Option<String>, Option<i32>) {
@@ -51,18 +340,106 @@ pub fn parse_font(spec: &str) -> (Option<String>, Option<i32>) {
     ((!spec.is_empty()).then(|| spec.to_string()), None)
 }
 
-/// Apply a font spec ("Family Size") onto `settings`, updating only the parts
-/// present in the spec.
-pub fn apply_font(settings: &mut Settings, spec: &str) {
+/// Weight words a font spec may give after a family, as GDI weights.
+const FONT_WEIGHTS: [(&str, i32); 14] = [
+    ("Thin", 100),
+    ("ExtraLight", 200),
+    ("UltraLight", 200),
+    ("Light", 300),
+    ("Regular", 400),
+    ("Normal", 400),
+    ("Medium", 500),
+    ("SemiBold", 600),
+    ("DemiBold", 600),
+    ("Bold", 700),
+    ("ExtraBold", 800),
+    ("UltraBold", 800),
+    ("Black", 900),
+    ("Heavy", 900),
+];
+
+/// The regular weight, used when a spec names none.
+pub const FONT_REGULAR: i32 = 400;
+
+/// The family part of a font spec: the families in order of preference, and
+/// the weight and style that apply to whichever is used.
+#[derive(Debug, PartialEq)]
+pub struct FontFace {
+    pub families: Vec<String>,
+    pub weight: i32,
+    pub italic: bool,
+}
+
+/// Split the name `parse_font` returns ("Cascadia Code SemiBold, Consolas")
+/// into its comma-separated families, taking weight words (`FONT_WEIGHTS`)
+/// and Italic/Oblique off the end of each, case-insensitively. Words stop
+/// coming off once what is left is `installed`, so "Arial Black" and
+/// "Segoe UI Semibold Italic" keep their family. At most one weight and one
+/// style word come off, and never the first word, so "Black" alone stays a
+/// family. The weight and style apply to every family.
+pub fn parse_font_face(name: &str, installed: &dyn Fn(&str) -> bool) -> FontFace {
+    let mut face = FontFace { families: Vec::new(), weight: FONT_REGULAR, italic: false };
+    for family in name.split(',') {
+        let mut words: Vec<&str> = family.split_whitespace().collect();
+        let (mut weight, mut italic) = (None, false);
+        while words.len() > 1 && !installed(&words.join(" ")) {
+            let last = words[words.len() - 1];
+            if !italic && (last.eq_ignore_ascii_case("italic") || last.eq_ignore_ascii_case("oblique")) {
+                italic = true;
+            } else if let Some(&(_, w)) =
+                FONT_WEIGHTS.iter().find(|(word, _)| weight.is_none() && word.eq_ignore_ascii_case(last))
+            {
+                weight = Some(w);
+            } else {
+                break;
+            }
+            words.pop();
+        }
+        if !words.is_empty() {
+            face.families.push(words.join(" "));
+            face.weight = weight.unwrap_or(face.weight);
+            face.italic |= italic;
+        }
+    }
+    face
+}
+
+/// Apply a font spec ("Family [Weight] [Italic], Fallback Size") onto
+/// `settings`, updating only the parts present in the spec. The weight and
+/// style come with the families: a spec naming any resets them. `installed`
+/// tells the families that exist under their full name.
+pub fn apply_font(settings: &mut Settings, spec: &str, installed: &dyn Fn(&str) -> bool) {
     let (name, size) = parse_font(spec);
     if let Some(name) = name {
-        settings.font_name = name;
+        let face = parse_font_face(&name, installed);
+        if let Some((first, fallbacks)) = face.families.split_first() {
+            settings.font_name = first.clone();
+            settings.font_fallbacks = fallbacks.to_vec();
+        }
+        settings.font_weight = face.weight;
+        settings.font_italic = face.italic;
     }
     if let Some(size) = size {
         settings.font_size = size;
     }
 }
 
+/// The font spec `settings` draws with, as `apply_font` reads it.
+pub fn format_font(settings: &Settings) -> String {
+    let families: Vec<&str> =
+        std::iter::once(&settings.font_name).chain(&settings.font_fallbacks).map(String::as_str).collect();
+    let mut spec = families.join(", ");
+    if settings.font_weight != FONT_REGULAR {
+        if let Some((word, _)) = FONT_WEIGHTS.iter().find(|&&(_, weight)| weight == settings.font_weight) {
+            spec.push_str(&format!(" {}", word));
+        }
+    }
+    if settings.font_italic {
+        spec.push_str(" Italic");
+    }
+    format!("{} {}", spec, settings.font_size)
+}
+
 /// windmenu's built-in color scheme (the "default" theme, a Windows-blue look).
 /// Kept in sync with `[themes.default]` in the shipped windmenu.toml so that a
 /// fresh `config init` reproduces the no-config appearance exactly.
@@ -74,6 +451,38 @@ pub fn default_palette() -> Palette {
         fg_select: Some("#ffffff".to_string()),
         bg_input: Some("#2d2d2d".to_string()),
         fg_input: Some("#ffffff".to_string()),
+        fg_match: Some("#ffb900".to_string()),
+        fg_match_select: Some("#ffe680".to_string()),
+        ..Palette::default()
+    }
+}
+
+/// The theme name that takes the palette from the desktop wallpaper (see
+/// `wallpaper`), like the built-in names usable without a table.
+pub const WALLPAPER: &str = "wallpaper";
+
+/// The light counterpart of `default_palette`.
+pub fn light_palette() -> Palette {
+    Palette {
+        bg: Some("#f3f3f3".to_string()),
+        fg: Some("#1b1b1b".to_string()),
+        bg_select: Some("#005fb8".to_string()),
+        fg_select: Some("#ffffff".to_string()),
+        bg_input: Some("#ffffff".to_string()),
+        fg_input: Some("#1b1b1b".to_string()),
+        fg_match: Some("#9d5d00".to_string()),
+        fg_match_select: Some("#ffe680".to_string()),
+        ..Palette::default()
+    }
+}
+
+/// A built-in scheme by name, as usable in `theme` and `extends` without a
+/// `[themes.*]` table.
+pub fn builtin_palette(name: &str) -> Option<Palette> {
+    match name {
+        "default" => Some(default_palette()),
+        DEFAULT_LIGHT => Some(light_palette()),
+        _ => None,
     }
 }
 
@@ -88,14 +497,160 @@ pub fn default_settings() -> Settings {
         center_window: true,
         ..Settings::default()
     };
-    apply_font(&mut settings, "Consolas 20");
+    apply_font(&mut settings, "Consolas 20", &|_| false);
     default_palette().apply(&mut settings);
     settings
 }
 
 #[cfg(test)]
 mod tests {
-    use super::parse_font;
+    use super::{
+        apply_font, default_settings, format_font, low_contrast, parse_font, parse_font_face, resolve_theme,
+        FontFace, Palette,
+    };
+    use crate::color;
+    use std::collections::HashMap;
+
+    fn themes(toml: &str) -> HashMap<String, Palette> {
+        toml::from_str(toml).expect("themes should parse")
+    }
+
+    #[test]
+    fn theme_inherits_through_extends_chain() {
+        let themes = themes(
+            r##"
+            [base]
+            bg = "#000000"
+            fg = "#111111"
+            [dark]
+            extends = "base"
+            fg = "#222222"
+            [darker]
+            extends = "dark"
+            bg_select = "#333333"
+        "##,
+        );
+        let (palette, warnings) = resolve_theme(&themes, "darker").unwrap();
+        assert!(warnings.is_empty());
+        assert_eq!(palette.bg.as_deref(), Some("#000000"));
+        assert_eq!(palette.fg.as_deref(), Some("#222222"));
+        assert_eq!(palette.bg_select.as_deref(), Some("#333333"));
+        assert!(resolve_theme(&themes, "missing").is_none());
+    }
+
+    #[test]
+    fn theme_extends_default_cycles_and_unknown_parents() {
+        let themes = themes(
+            r##"
+            [light]
+            extends = "default"
+            bg = "#ffffff"
+            [a]
+            extends = "b"
+            bg = "#aaaaaa"
+            [b]
+            extends = "a"
+            fg = "#bbbbbb"
+            [orphan]
+            extends = "nowhere"
+            [paper]
+            extends = "default-light"
+        "##,
+        );
+        let (light, warnings) = resolve_theme(&themes, "light").unwrap();
+        assert!(warnings.is_empty());
+        assert_eq!(light.fg_match.as_deref(), Some("#ffb900"));
+        let (paper, warnings) = resolve_theme(&themes, "paper").unwrap();
+        assert!(warnings.is_empty());
+        assert_eq!(paper.fg_match.as_deref(), Some("#9d5d00"));
+
+        let (a, warnings) = resolve_theme(&themes, "a").unwrap();
+        assert_eq!((a.bg.as_deref(), a.fg.as_deref()), (Some("#aaaaaa"), Some("#bbbbbb")));
+        assert_eq!(warnings, ["theme 'b' extends 'a', forming a cycle (a -> b -> a); ignoring that parent"]);
+
+        let (_, warnings) = resolve_theme(&themes, "orphan").unwrap();
+        assert_eq!(warnings, ["theme 'orphan' extends unknown theme 'nowhere'; ignoring it"]);
+    }
+
+    #[test]
+    fn colors_derive_from_other_keys() {
+        let palette: Palette = toml::from_str(
+            r##"
+            bg = "#2e3440"
+            bg_input = "lighten(bg, 10%)"
+            fg_input = "fg"
+            bg_select = "mix(bg, #88c0d0, 25%)"
+            fg_select = "fg_match_select"
+            fg_match_select = "fg_select"
+            border = "separator"
+            "##,
+        )
+        .unwrap();
+        let mut settings = default_settings();
+        let eval = |key| palette.evaluate(key, &default_settings());
+        assert_eq!(eval("bg_input"), Some(Ok([67, 76, 94])));
+        // Keys the palette leaves unset read what's already there
+        assert_eq!(eval("fg_input"), Some(Ok([0xff, 0xff, 0xff])));
+        assert_eq!(eval("bg_select"), Some(Ok([0x72, 0x9d, 0xac])));
+        assert_eq!(eval("fg"), None);
+        assert_eq!(
+            eval("fg_select"),
+            Some(Err("fg_select -> fg_match_select -> fg_select forms a cycle".to_string()))
+        );
+        assert_eq!(
+            eval("fg_match_select"),
+            Some(Err("fg_match_select -> fg_select -> fg_match_select forms a cycle".to_string()))
+        );
+        assert_eq!(eval("border"), Some(Err("separator is not set".to_string())));
+
+        let fg_select = settings.fg_select;
+        palette.apply(&mut settings);
+        assert_eq!(settings.bg_edit, u32::from_le_bytes([67, 76, 94, 0]));
+        assert_eq!((settings.fg_select, settings.border), (fg_select, None));
+
+        // A reference to a key written wrong reads what that key falls back to
+        let palette: Palette = toml::from_str("bg = \"nope\"\nbg_input = \"darken(bg, 0%)\"\n").unwrap();
+        assert_eq!(palette.evaluate("bg_input", &default_settings()), Some(Ok([0x1e, 0x1e, 0x1e])));
+        assert!(palette.evaluate("bg", &default_settings()).unwrap().unwrap_err().starts_with("'nope' is not a color"));
+    }
+
+    #[test]
+    fn low_contrast_pairs_fall_back_like_drawing() {
+        let palette: Palette = toml::from_str(
+            r##"
+            bg = "#1e1e1e"
+            fg = "#2a2a2a"
+            bg_select = "#0078d4"
+            fg_select = "#ffffff"
+            bg_input = "#2d2d2d"
+            fg_input = "#ffffff"
+            fg_match = "#ffb900"
+            fg_match_select = "#ffe680"
+            "##,
+        )
+        .unwrap();
+        let entries = palette.entries();
+        let color = |key: &str| {
+            let value = entries.iter().find(|(k, _)| *k == key)?.1?;
+            color::parse(value).map(|(rgb, _)| rgb)
+        };
+        let found = low_contrast(color, 3.0);
+        assert_eq!(found.len(), 1);
+        assert_eq!((found[0].text, found[0].background), ("fg", "bg"));
+        assert!(found[0].ratio < 1.2);
+        // The selected match color sits at 3.6:1; unset prompt colors aren't
+        // reported twice as the selection pair
+        let found: Vec<_> = low_contrast(color, 4.5).into_iter().map(|l| (l.text, l.background)).collect();
+        assert_eq!(found, [("fg", "bg"), ("fg_match_select", "bg_select")]);
+
+        let prompt = |key: &str| match key {
+            "bg_prompt" => Some([0xdd, 0xdd, 0xdd]),
+            "fg_prompt" => None,
+            _ => color(key),
+        };
+        let found = low_contrast(prompt, 4.5);
+        assert_eq!(found.last().map(|l| (l.text, l.background)), Some(("fg_select", "bg_prompt")));
+    }
 
     #[test]
     fn font_family_and_size() {
@@ -117,4 +672,49 @@ mod tests {
         // "Book" isn't a size, so the whole spec is the family.
         assert_eq!(parse_font("Sans Book"), (Some("Sans Book".to_string()), None));
     }
+
+    #[test]
+    fn font_weight_style_and_fallbacks() {
+        let face = |families: &[&str], weight, italic| FontFace {
+            families: families.iter().map(|f| f.to_string()).collect(),
+            weight,
+            italic,
+        };
+        let none = |_: &str| false;
+        assert_eq!(parse_font_face("Cascadia Code SemiBold", &none), face(&["Cascadia Code"], 600, false));
+        assert_eq!(parse_font_face("Segoe UI Italic", &none), face(&["Segoe UI"], 400, true));
+        assert_eq!(parse_font_face("Segoe UI bold italic", &none), face(&["Segoe UI"], 700, true));
+        assert_eq!(
+            parse_font_face("Cascadia Code, Consolas Light,", &none),
+            face(&["Cascadia Code", "Consolas"], 300, false)
+        );
+        // Only trailing words, never the first, and one weight at most
+        assert_eq!(parse_font_face("Black Ops One", &none), face(&["Black Ops One"], 400, false));
+        assert_eq!(parse_font_face("Heavy", &none), face(&["Heavy"], 400, false));
+        assert_eq!(parse_font_face("Sans Book", &none), face(&["Sans Book"], 400, false));
+        assert_eq!(parse_font_face("Segoe UI Semibold Bold", &none), face(&["Segoe UI Semibold"], 700, false));
+
+        // Installed families keep their weight word
+        let installed = |family: &str| ["Arial Black", "Segoe UI Semibold", "Segoe UI Light"].contains(&family);
+        assert_eq!(parse_font_face("Arial Black", &installed), face(&["Arial Black"], 400, false));
+        assert_eq!(parse_font_face("Segoe UI Semibold Italic", &installed), face(&["Segoe UI Semibold"], 400, true));
+        assert_eq!(
+            parse_font_face("Segoe UI Light, Consolas Bold", &installed),
+            face(&["Segoe UI Light", "Consolas"], 700, false)
+        );
+
+        let mut settings = default_settings();
+        apply_font(&mut settings, "Cascadia Code SemiBold, Consolas 14", &none);
+        assert_eq!(format_font(&settings), "Cascadia Code, Consolas SemiBold 14");
+        apply_font(&mut settings, "Segoe UI Italic", &none);
+        assert_eq!(format_font(&settings), "Segoe UI Italic 14");
+        // What format_font writes reads back the same
+        apply_font(&mut settings, "Segoe UI Semibold Bold 16", &installed);
+        let spec = format_font(&settings);
+        assert_eq!(spec, "Segoe UI Semibold Bold 16");
+        let mut reread = default_settings();
+        apply_font(&mut reread, &spec, &installed);
+        assert_eq!(format_font(&reread), spec);
+        assert_eq!(format_font(&default_settings()), "Consolas 20");
+    }
 }
@@ -0,0 +1,318 @@
+//! `theme = "wallpaper"`: a palette taken from the desktop wallpaper. The
+//! decoded image is scaled down and split into its dominant colors by median
+//! cut, which map onto the palette keys like this:
+//!
+//! | windmenu          | from the wallpaper                                |
+//! |-------------------|---------------------------------------------------|
+//! | `bg`              | the most common color                             |
+//! | `fg`              | the color contrasting most with `bg`              |
+//! | `bg_select`       | the most colorful color, weighted by how common   |
+//! | `fg_select`       | the color contrasting most with `bg_select`       |
+//! | `bg_input`        | `bg`, 8% of the way to `fg`                       |
+//! | `fg_input`        | `fg`                                              |
+//! | `fg_match`        | `bg_select`                                       |
+//! | `fg_match_select` | the color contrasting next most with `bg_select`  |
+//!
+//! Every text color is then moved toward black or white until it reaches
+//! `TEXT_CONTRAST` on its background, and the selection until it stands out
+//! from `bg`. The palette is cached until the wallpaper file changes; only
+//! finding that file is Windows-specific.
+
+use std::ffi::OsString;
+use std::fs;
+use std::os::windows::ffi::OsStringExt;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::SystemTime;
+use winapi::shared::minwindef::MAX_PATH;
+use winapi::um::winuser::{SystemParametersInfoW, SPI_GETDESKWALLPAPER};
+
+use crate::color;
+use crate::theme::Palette;
+
+/// The contrast every text color reaches on its background: WCAG AA for
+/// body text. Black or white always reach it, so it's always met.
+const TEXT_CONTRAST: f64 = 4.5;
+
+/// The contrast between the selection and the window background.
+const SELECTION_CONTRAST: f64 = 1.5;
+
+/// How many colors the wallpaper is cut into.
+const SWATCHES: usize = 8;
+
+/// The longest side, in pixels, the wallpaper is scaled to before the cut.
+const SAMPLE_SIZE: u32 = 160;
+
+/// One of the dominant colors: the average of its pixels, and how many.
+#[derive(Debug, Clone, Copy)]
+struct Swatch {
+    rgb: [u8; 3],
+    pixels: usize,
+}
+
+/// The palette for an image's pixels, None when there are none.
+pub fn palette(pixels: &[[u8; 3]]) -> Option<Palette> {
+    let mut swatches = median_cut(pixels, SWATCHES);
+    swatches.sort_by_key(|swatch| std::cmp::Reverse(swatch.pixels));
+    let bg = swatches.first()?.rgb;
+    // The swatches by contrast with `background`, most first
+    let by_contrast = |background: [u8; 3]| {
+        let mut colors: Vec<[u8; 3]> = swatches.iter().map(|swatch| swatch.rgb).collect();
+        colors.sort_by(|a, b| color::contrast(*b, background).total_cmp(&color::contrast(*a, background)));
+        colors
+    };
+    let accent = swatches[1..]
+        .iter()
+        .max_by_key(|swatch| chroma(swatch.rgb) as usize * swatch.pixels)
+        .map_or(bg, |swatch| swatch.rgb);
+
+    let fg = readable(by_contrast(bg)[0], bg, TEXT_CONTRAST);
+    let bg_select = readable(accent, bg, SELECTION_CONTRAST);
+    let on_select = by_contrast(bg_select);
+    let bg_input = color::mix(fg, bg, 0.08);
+    let hex = |rgb| Some(color::hex(rgb));
+    Some(Palette {
+        bg: hex(bg),
+        fg: hex(fg),
+        bg_select: hex(bg_select),
+        fg_select: hex(readable(on_select[0], bg_select, TEXT_CONTRAST)),
+        bg_input: hex(bg_input),
+        fg_input: hex(readable(fg, bg_input, TEXT_CONTRAST)),
+        fg_match: hex(readable(bg_select, bg, TEXT_CONTRAST)),
+        fg_match_select: hex(readable(*on_select.get(1).unwrap_or(&on_select[0]), bg_select, TEXT_CONTRAST)),
+        ..Palette::default()
+    })
+}
+
+/// Split `pixels` into up to `count` boxes, each time halving the box with
+/// the widest range in one channel at its median, and average each box.
+fn median_cut(pixels: &[[u8; 3]], count: usize) -> Vec<Swatch> {
+    let mut boxes = vec![pixels.to_vec()];
+    while boxes.len() < count {
+        let widest = boxes
+            .iter()
+            .enumerate()
+            .map(|(i, pixels)| {
+                let (channel, range) = (0..3)
+                    .map(|c| {
+                        let values = pixels.iter().map(|p| p[c]);
+                        (c, values.clone().max().unwrap_or(0) - values.min().unwrap_or(0))
+                    })
+                    .max_by_key(|&(_, range)| range)
+                    .unwrap_or((0, 0));
+                (i, channel, range)
+            })
+            .filter(|&(_, _, range)| range > 0)
+            .max_by_key(|&(_, _, range)| range);
+        let Some((i, channel, _)) = widest else { break };
+        let mut pixels = boxes.swap_remove(i);
+        pixels.sort_unstable_by_key(|p| p[channel]);
+        // Cut next to the median where the value changes, keeping a color whole
+        let median = pixels[pixels.len() / 2][channel];
+        let below = pixels.partition_point(|p| p[channel] < median);
+        let above = pixels.partition_point(|p| p[channel] <= median);
+        let cut = [below, above]
+            .into_iter()
+            .filter(|&cut| cut > 0 && cut < pixels.len())
+            .min_by_key(|&cut| cut.abs_diff(pixels.len() / 2))
+            .unwrap_or(pixels.len() / 2);
+        let upper = pixels.split_off(cut);
+        boxes.push(pixels);
+        boxes.push(upper);
+    }
+    boxes
+        .into_iter()
+        .filter(|pixels| !pixels.is_empty())
+        .map(|pixels| {
+            let sum = pixels.iter().fold([0usize; 3], |sum, p| [0, 1, 2].map(|c| sum[c] + usize::from(p[c])));
+            let rgb = sum.map(|total| ((total + pixels.len() / 2) / pixels.len()) as u8);
+            Swatch { rgb, pixels: pixels.len() }
+        })
+        .collect()
+}
+
+/// How colorful `rgb` is: its widest channel spread.
+fn chroma(rgb: [u8; 3]) -> u8 {
+    rgb.iter().max().unwrap_or(&0) - rgb.iter().min().unwrap_or(&0)
+}
+
+/// `text` moved toward black or white, whichever `background` contrasts
+/// with more, just far enough to reach `min` against it.
+fn readable(text: [u8; 3], background: [u8; 3], min: f64) -> [u8; 3] {
+    let target = if color::contrast([255; 3], background) >= color::contrast([0; 3], background) {
+        [255; 3]
+    } else {
+        [0; 3]
+    };
+    (0..=100)
+        .map(|step| color::mix(target, text, f64::from(step) / 100.0))
+        .find(|&rgb| color::contrast(rgb, background) >= min)
+        .unwrap_or(target)
+}
+
+/// Decode an image file's bytes into pixels, scaled to `SAMPLE_SIZE`.
+pub fn decode(bytes: &[u8]) -> Result<Vec<[u8; 3]>, String> {
+    let image = image::load_from_memory(bytes).map_err(|e| e.to_string())?;
+    let image = image.thumbnail(SAMPLE_SIZE, SAMPLE_SIZE).to_rgb8();
+    Ok(image.pixels().map(|p| p.0).collect())
+}
+
+/// A wallpaper file's palette, with the file's size and modification time
+/// when it was read.
+struct Cached {
+    path: PathBuf,
+    stamp: (Option<SystemTime>, u64),
+    palette: Palette,
+}
+
+/// The last palette extracted, reused while the file is unchanged.
+struct Cache(Mutex<Option<Cached>>);
+
+static CACHE: Cache = Cache(Mutex::new(None));
+
+impl Cache {
+    /// The palette of the image at `path`, extracted again only when the
+    /// file has changed since last time.
+    fn get(&self, path: &Path) -> Result<Palette, String> {
+        let metadata = fs::metadata(path).map_err(|e| format!("can't read {}: {}", path.display(), e))?;
+        let stamp = (metadata.modified().ok(), metadata.len());
+        let mut cached = self.0.lock().unwrap();
+        if let Some(cached) = cached.as_ref().filter(|c| c.path == path && c.stamp == stamp) {
+            return Ok(cached.palette.clone());
+        }
+        let bytes = fs::read(path).map_err(|e| format!("can't read {}: {}", path.display(), e))?;
+        let pixels = decode(&bytes).map_err(|e| format!("can't decode {}: {}", path.display(), e))?;
+        let palette = palette(&pixels).ok_or_else(|| format!("{} has no pixels", path.display()))?;
+        *cached = Some(Cached { path: path.to_path_buf(), stamp, palette: palette.clone() });
+        Ok(palette)
+    }
+}
+
+/// The desktop wallpaper's path and palette.
+pub fn current() -> Result<(PathBuf, Palette), String> {
+    let path = path().ok_or("no desktop wallpaper is set")?;
+    let palette = CACHE.get(&path)?;
+    Ok((path, palette))
+}
+
+/// The desktop wallpaper file, None for a solid color background.
+fn path() -> Option<PathBuf> {
+    let mut buffer = [0u16; MAX_PATH];
+    let ok = unsafe {
+        SystemParametersInfoW(SPI_GETDESKWALLPAPER, MAX_PATH as u32, buffer.as_mut_ptr().cast(), 0)
+    };
+    let len = buffer.iter().position(|&c| c == 0).unwrap_or(buffer.len());
+    (ok != 0 && len > 0).then(|| PathBuf::from(OsString::from_wide(&buffer[..len])))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{decode, median_cut, palette, Cache};
+    use crate::color;
+    use crate::theme;
+    use std::fs;
+    use std::sync::Mutex;
+    use std::time::Duration;
+
+    /// Ten rows of vertical bands, each `(color, columns)` wide.
+    fn bands(bands: &[([u8; 3], usize)]) -> Vec<[u8; 3]> {
+        let row: Vec<[u8; 3]> = bands.iter().flat_map(|&(rgb, columns)| vec![rgb; columns]).collect();
+        row.repeat(10)
+    }
+
+    fn png(pixels: &[[u8; 3]], width: u32) -> Vec<u8> {
+        let height = pixels.len() as u32 / width;
+        let image = image::RgbImage::from_raw(width, height, pixels.concat()).unwrap();
+        let mut bytes = std::io::Cursor::new(Vec::new());
+        image.write_to(&mut bytes, image::ImageFormat::Png).unwrap();
+        bytes.into_inner()
+    }
+
+    fn rgb(value: &Option<String>) -> [u8; 3] {
+        color::parse(value.as_deref().unwrap()).unwrap().0
+    }
+
+    /// Every text color reaches 4.5:1 on its background.
+    fn assert_readable(palette: &theme::Palette) {
+        let entries = palette.entries();
+        let color = |key: &str| {
+            let value = entries.iter().find(|&&(k, _)| k == key)?.1?;
+            color::parse(value).map(|(rgb, _)| rgb)
+        };
+        assert_eq!(theme::low_contrast(color, 4.5), Vec::new(), "{:?}", palette);
+    }
+
+    #[test]
+    fn median_cut_finds_the_dominant_colors() {
+        let pixels = bands(&[([20, 30, 60], 60), ([230, 120, 40], 25), ([240, 240, 230], 15)]);
+        let mut swatches = median_cut(&pixels, 8);
+        swatches.sort_by_key(|s| std::cmp::Reverse(s.pixels));
+        let found: Vec<([u8; 3], usize)> = swatches.iter().map(|s| (s.rgb, s.pixels)).collect();
+        assert_eq!(found, [([20, 30, 60], 600), ([230, 120, 40], 250), ([240, 240, 230], 150)]);
+        assert!(median_cut(&[], 8).is_empty());
+    }
+
+    #[test]
+    fn palette_follows_the_wallpaper() {
+        // A night sky with an orange moon and pale stars
+        let night = palette(&bands(&[([20, 30, 60], 60), ([230, 120, 40], 25), ([240, 240, 230], 15)])).unwrap();
+        assert_eq!(rgb(&night.bg), [20, 30, 60]);
+        assert_eq!(rgb(&night.fg), [240, 240, 230]);
+        assert_eq!(rgb(&night.bg_select), [230, 120, 40]);
+        assert_readable(&night);
+
+        // A pale beach: light background, dark text
+        let beach = palette(&bands(&[([238, 226, 200], 70), ([70, 140, 180], 20), ([120, 110, 90], 10)])).unwrap();
+        assert_eq!(rgb(&beach.bg), [238, 226, 200]);
+        assert_eq!(rgb(&beach.bg_select), [70, 140, 180]);
+        assert!(color::contrast(rgb(&beach.fg), [0; 3]) < 4.0);
+        assert_readable(&beach);
+    }
+
+    #[test]
+    fn contrast_is_guaranteed_for_flat_images() {
+        // One color, a gray gradient, and mid-tones too close to each other
+        let flat = vec![[128, 128, 128]; 100];
+        let gradient: Vec<[u8; 3]> = (0..=255).map(|v| [v; 3]).collect();
+        let murky = bands(&[([110, 100, 95], 50), ([120, 115, 100], 30), ([100, 110, 120], 20)]);
+        for pixels in [flat, gradient, murky] {
+            let palette = palette(&pixels).unwrap();
+            assert_readable(&palette);
+            assert!(color::contrast(rgb(&palette.bg_select), rgb(&palette.bg)) >= 1.5, "{:?}", palette);
+        }
+        assert!(palette(&[]).is_none());
+    }
+
+    #[test]
+    fn images_decode_to_scaled_pixels() {
+        let pixels = bands(&[([20, 30, 60], 300), ([230, 120, 40], 100)]);
+        let decoded = decode(&png(&pixels, 400)).unwrap();
+        // 400 x 10 scaled to fit 160 x 160
+        assert_eq!(decoded.len(), 160 * 4);
+        assert_eq!(decoded[0], [20, 30, 60]);
+        assert_eq!(decoded[159], [230, 120, 40]);
+        assert!(decode(b"not an image").is_err());
+    }
+
+    #[test]
+    fn palette_is_cached_until_the_file_changes() {
+        let path = std::env::temp_dir().join(format!("windmenu-wallpaper-{}.png", std::process::id()));
+        let touch = |modified| fs::File::options().write(true).open(&path).unwrap().set_modified(modified).unwrap();
+        let cache = Cache(Mutex::new(None));
+        let night = png(&[[20, 30, 60]; 100], 10);
+        fs::write(&path, &night).unwrap();
+        assert_eq!(rgb(&cache.get(&path).unwrap().bg), [20, 30, 60]);
+
+        // Same size and time: not read again
+        let modified = fs::metadata(&path).unwrap().modified().unwrap();
+        fs::write(&path, vec![0; night.len()]).unwrap();
+        touch(modified);
+        assert_eq!(rgb(&cache.get(&path).unwrap().bg), [20, 30, 60]);
+
+        fs::write(&path, png(&[[238, 226, 200]; 100], 10)).unwrap();
+        touch(modified + Duration::from_secs(1));
+        assert_eq!(rgb(&cache.get(&path).unwrap().bg), [238, 226, 200]);
+        fs::remove_file(&path).unwrap();
+        assert!(cache.get(&path).is_err());
+    }
+}
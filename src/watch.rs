@@ -0,0 +1,136 @@
+//! Config file watcher for the daemon: waits on directory change notifications
+//! for the folders holding `windmenu.toml` and its imports, and reports when
+//! any of those files is created, modified or deleted.
+
+use std::fs;
+use std::os::windows::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, SystemTime};
+use winapi::shared::minwindef::FALSE;
+use winapi::um::fileapi::{FindCloseChangeNotification, FindFirstChangeNotificationW};
+use winapi::um::handleapi::INVALID_HANDLE_VALUE;
+use winapi::um::synchapi::WaitForMultipleObjects;
+use winapi::um::winbase::{INFINITE, WAIT_OBJECT_0};
+use winapi::um::winnt::{
+    FILE_NOTIFY_CHANGE_FILE_NAME, FILE_NOTIFY_CHANGE_LAST_WRITE, HANDLE, MAXIMUM_WAIT_OBJECTS,
+};
+
+/// Editors save in bursts (truncate, write, rename over a temp file); wait for
+/// them to finish before looking at the files.
+const SETTLE: Duration = Duration::from_millis(200);
+
+/// Each watched file with its modification time, None when it doesn't exist.
+type Snapshot = Vec<(PathBuf, Option<SystemTime>)>;
+
+fn snapshot(files: &[PathBuf]) -> Snapshot {
+    files
+        .iter()
+        .map(|path| {
+            let modified = fs::metadata(path).and_then(|m| m.modified()).ok();
+            (path.clone(), modified)
+        })
+        .collect()
+}
+
+/// Change notification handles for a set of directories, closed on drop.
+struct DirWatch {
+    handles: Vec<HANDLE>,
+}
+
+impl DirWatch {
+    /// Watch the parent directory of every file. Directories that don't exist
+    /// (or can't be watched) are skipped.
+    fn open(files: &[PathBuf]) -> DirWatch {
+        let mut dirs: Vec<&Path> = files.iter().filter_map(|f| f.parent()).collect();
+        dirs.sort();
+        dirs.dedup();
+        dirs.truncate(MAXIMUM_WAIT_OBJECTS as usize);
+
+        let handles = dirs
+            .into_iter()
+            .filter_map(|dir| {
+                let wide: Vec<u16> = dir.as_os_str().encode_wide().chain(Some(0)).collect();
+                let handle = unsafe {
+                    FindFirstChangeNotificationW(
+                        wide.as_ptr(),
+                        FALSE,
+                        FILE_NOTIFY_CHANGE_FILE_NAME | FILE_NOTIFY_CHANGE_LAST_WRITE,
+                    )
+                };
+                (handle != INVALID_HANDLE_VALUE).then_some(handle)
+            })
+            .collect();
+        DirWatch { handles }
+    }
+
+    /// Block until something changes in any watched directory. False when
+    /// there is nothing to wait on or the wait failed.
+    fn wait(&self) -> bool {
+        if self.handles.is_empty() {
+            return false;
+        }
+        let count = self.handles.len() as u32;
+        let result = unsafe { WaitForMultipleObjects(count, self.handles.as_ptr(), FALSE, INFINITE) };
+        result < WAIT_OBJECT_0 + count
+    }
+}
+
+impl Drop for DirWatch {
+    fn drop(&mut self) {
+        for &handle in &self.handles {
+            unsafe { FindCloseChangeNotification(handle) };
+        }
+    }
+}
+
+/// Block, calling `on_change` whenever any file listed by `files` is created,
+/// modified or deleted. The list is re-read after every change, so adding an
+/// `import` starts watching the new pack. Returns only if no directory can be
+/// watched.
+pub fn watch<F, C>(files: F, mut on_change: C)
+where
+    F: Fn() -> Vec<PathBuf>,
+    C: FnMut(),
+{
+    let mut before = snapshot(&files());
+    loop {
+        let watched = files();
+        // Open the notifications before comparing, so a change landing in
+        // between is caught by one or the other
+        let dirs = DirWatch::open(&watched);
+        let now = snapshot(&watched);
+        if now != before {
+            before = now;
+            on_change();
+            continue;
+        }
+        if !dirs.wait() {
+            return;
+        }
+        drop(dirs);
+        thread::sleep(SETTLE);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::snapshot;
+    use std::fs;
+
+    #[test]
+    fn snapshot_sees_files_appear_and_disappear() {
+        let path = std::env::temp_dir().join(format!("windmenu-watch-{}.toml", std::process::id()));
+        let _ = fs::remove_file(&path);
+        let files = vec![path.clone()];
+
+        let missing = snapshot(&files);
+        assert_eq!(missing[0].1, None);
+        fs::write(&path, "lines = 5").unwrap();
+        let present = snapshot(&files);
+        assert!(present[0].1.is_some());
+        assert_ne!(missing, present);
+        fs::remove_file(&path).unwrap();
+        assert_eq!(snapshot(&files), missing);
+    }
+}
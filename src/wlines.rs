@@ -3,35 +3,39 @@
 //! returns the user's selection, replacing the old wlines-daemon named-pipe
 //! IPC and wlines.exe subprocess fallback.
 
+use std::collections::HashMap;
 use std::ffi::OsStr;
 use std::mem;
 use std::os::windows::ffi::OsStrExt;
 use std::ptr;
 
-use winapi::shared::minwindef::{LPARAM, LRESULT, UINT, WPARAM};
-use winapi::shared::windef::{COLORREF, HBITMAP, HDC, HFONT, HWND, POINT, RECT, HBRUSH, HMENU};
+use winapi::ctypes::c_int;
+use winapi::shared::minwindef::{DWORD, LPARAM, LRESULT, UINT, WPARAM};
+use winapi::shared::windef::{COLORREF, HBITMAP, HDC, HFONT, HWND, POINT, RECT, SIZE, HBRUSH, HMENU};
 use winapi::um::errhandlingapi::GetLastError;
 use winapi::um::shellscalingapi::{GetDpiForMonitor, MDT_EFFECTIVE_DPI};
 use winapi::um::wingdi::{
     BitBlt, CreateCompatibleBitmap, CreateCompatibleDC, CreateFontW, DeleteDC, DeleteObject,
-    GetStockObject, Rectangle, SelectObject, SetBkColor, SetBkMode, SetDCBrushColor,
-    SetDCPenColor, SetTextColor, DC_BRUSH, DC_PEN, FW_NORMAL, SRCCOPY, TRANSPARENT,
+    EnumFontFamiliesExW, GetStockObject, GetTextExtentExPointW, GetTextExtentPoint32W, Rectangle,
+    SelectObject, SetBkColor, SetBkMode, SetDCBrushColor, SetDCPenColor, SetTextColor, TextOutW,
+    ANTIALIASED_QUALITY, CLEARTYPE_QUALITY, DC_BRUSH, DC_PEN, DEFAULT_CHARSET, FW_NORMAL, LOGFONTW,
+    NONANTIALIASED_QUALITY, SRCCOPY, TEXTMETRICW, TRANSPARENT,
 };
 use winapi::um::winuser::{
     AttachThreadInput, BeginPaint, BringWindowToTop, CallWindowProcW, CreateWindowExW,
     DefWindowProcW, DestroyWindow, DispatchMessageW, DrawTextW, EndPaint, GetForegroundWindow,
-    GetCursorPos, GetKeyState, GetMessageW, GetMonitorInfoW, GetSystemMetrics,
+    GetCursorPos, GetDC, GetKeyState, GetMessageW, GetMonitorInfoW, GetSystemMetrics,
     GetWindowLongPtrW, GetWindowLongW,
     GetWindowTextLengthW, GetWindowTextW, GetWindowThreadProcessId, KillTimer, LoadCursorW,
-    MonitorFromPoint, PostQuitMessage, RedrawWindow, RegisterClassExW, SendMessageW, SetFocus,
+    MonitorFromPoint, PostMessageW, PostQuitMessage, RedrawWindow, RegisterClassExW, ReleaseDC, SendMessageW, SetFocus,
     SetForegroundWindow,
     SetTimer, SetWindowLongPtrW, SetWindowLongW, SetWindowTextW, ShowWindow, TranslateMessage,
     UpdateWindow, COLOR_WINDOW, DT_CALCRECT, DT_END_ELLIPSIS, DT_NOCLIP, DT_NOPREFIX, DT_SINGLELINE,
     EC_LEFTMARGIN, EC_RIGHTMARGIN, EM_GETSEL, EM_SETMARGINS, EM_SETSEL, ES_AUTOHSCROLL,
     ES_AUTOVSCROLL, ES_LEFT, GWLP_USERDATA, GWLP_WNDPROC, GWL_STYLE, IDC_ARROW,
     MONITORINFO, MONITOR_DEFAULTTONEAREST, MSG,
-    RDW_INVALIDATE, SM_CXSCREEN, SM_CYSCREEN, SW_HIDE, SW_SHOW, VK_CONTROL, VK_DOWN, VK_END,
-    VK_ESCAPE, VK_HOME, VK_LEFT, VK_NEXT, VK_PRIOR, VK_RETURN, VK_RIGHT, VK_SHIFT, VK_UP, WM_CHAR,
+    RDW_INVALIDATE, SM_CXSCREEN, SM_CYSCREEN, SW_HIDE, SW_SHOW, VK_CONTROL, VK_LEFT, VK_SHIFT,
+    WM_APP, WM_CHAR,
     WM_CLOSE, WM_CTLCOLOREDIT, WM_KEYDOWN, WM_KEYUP, WM_KILLFOCUS, WM_LBUTTONDOWN,
     WM_MOUSEWHEEL, WM_PAINT, WM_SETFONT, WM_TIMER, WNDCLASSEXW, WS_CHILD, WS_EX_TOOLWINDOW,
     WS_EX_TOPMOST, WS_OVERLAPPEDWINDOW, WS_POPUP, WS_VISIBLE, PAINTSTRUCT,
@@ -40,53 +44,16 @@ use winapi::um::winuser::{
 const WND_CLASS: &str = "windmenu_wlines_window";
 const FOREGROUND_TIMER_ID: usize = 1;
 const ERROR_CLASS_ALREADY_EXISTS: u32 = 1410;
+/// Posted by the filter worker when results are ready.
+const WM_APP_FILTERED: UINT = WM_APP + 1;
+/// Entry count from which filtering moves off the UI thread. Below it a
+/// keystroke filters in well under a frame, so it stays synchronous.
+const BACKGROUND_FILTER_MIN: usize = 5000;
 const DRAWTEXT_PARAMS: UINT = DT_NOCLIP | DT_NOPREFIX | DT_END_ELLIPSIS;
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
-pub enum FilterMode {
-    Complete,
-    Keywords,
-    Fuzzy,
-}
-
-impl FilterMode {
-    pub fn parse(s: &str) -> FilterMode {
-        match s.to_ascii_lowercase().as_str() {
-            "complete" | "0" => FilterMode::Complete,
-            "keywords" | "1" => FilterMode::Keywords,
-            "fuzzy" | "2" => FilterMode::Fuzzy,
-            _ => FilterMode::Keywords,
-        }
-    }
-}
-
-/// A navigation keybinding: Ctrl/Shift modifiers plus a virtual-key code.
-/// Alt/Win are intentionally unsupported — those keydowns arrive as WM_SYSKEYDOWN,
-/// which the edit proc does not handle.
-#[derive(Debug, Clone, Copy, PartialEq)]
-pub struct KeyCombo {
-    pub ctrl: bool,
-    pub shift: bool,
-    pub vk: u16,
-}
-
-impl KeyCombo {
-    /// True if this combo is satisfied by the current key + modifier state.
-    fn matches(&self, vk: u16, ctrl: bool, shift: bool) -> bool {
-        self.vk == vk && self.ctrl == ctrl && self.shift == shift
-    }
-
-    /// The control character the EDIT control would insert for a Ctrl+<letter>
-    /// combo, so WM_CHAR can swallow it (Ctrl+A=1 .. Ctrl+Z=26; e.g. Ctrl+J=0x0A,
-    /// Ctrl+K=0x0B). None when this isn't a Ctrl+letter combo.
-    pub(crate) fn char_to_swallow(&self) -> Option<u32> {
-        if self.ctrl && (0x41..=0x5A).contains(&self.vk) {
-            Some((self.vk & 0x1F) as u32)
-        } else {
-            None
-        }
-    }
-}
+use crate::color;
+use crate::engine::{Behavior, Engine, Input, Mods, Outcome, Paging};
+pub use crate::engine::{Adaptive, FilterMode, KeyCombo};
 
 #[derive(Debug, Clone)]
 pub struct Settings {
@@ -98,15 +65,28 @@ pub struct Settings {
     pub padding: i32,
     pub width: i32, // 0 = full screen width
     pub center_window: bool,
+    pub bottom: bool, // Pin to the monitor bottom instead of the top (ignored when centered)
     pub case_sensitive: bool,
+    pub normalize: bool, // Accent/width/ligature-insensitive matching
     pub bg: COLORREF,
     pub fg: COLORREF,
     pub bg_select: COLORREF,
     pub fg_select: COLORREF,
     pub bg_edit: COLORREF,
     pub fg_edit: COLORREF,
+    pub fg_match: COLORREF,        // Matched characters
+    pub fg_match_select: COLORREF, // Matched characters in the selected entry
+    pub bg_prompt: Option<COLORREF>, // Prompt box; None = bg_select
+    pub fg_prompt: Option<COLORREF>, // Prompt text; None = fg_select
+    pub border: Option<COLORREF>,    // Window border; None = bg_select
+    pub border_width: i32,           // 0 = no border
+    pub separator: Option<COLORREF>, // Line between input and entries (vertical mode); None = no line
     pub font_name: String,
+    pub font_fallbacks: Vec<String>, // Used in order when font_name isn't installed
     pub font_size: i32,
+    pub font_weight: i32, // GDI weight, 100-900 (400 = regular)
+    pub font_italic: bool,
+    pub font_quality: FontQuality,
     pub next: KeyCombo, // Move selection down (default Ctrl+J)
     pub prev: KeyCombo, // Move selection up (default Ctrl+K)
 }
@@ -122,42 +102,88 @@ impl Default for Settings {
             padding: 4,
             width: 0,
             center_window: false,
+            bottom: false,
             case_sensitive: false,
+            normalize: false,
             bg: parse_color("#000000").unwrap(),
             fg: parse_color("#ffffff").unwrap(),
             bg_select: parse_color("#ffffff").unwrap(),
             fg_select: parse_color("#000000").unwrap(),
             bg_edit: parse_color("#111111").unwrap(),
             fg_edit: parse_color("#ffffff").unwrap(),
+            fg_match: parse_color("#ffff00").unwrap(),
+            fg_match_select: parse_color("#0000ff").unwrap(),
+            bg_prompt: None,
+            fg_prompt: None,
+            border: None,
+            border_width: 0,
+            separator: None,
             font_name: "Courier New".to_string(),
+            font_fallbacks: Vec::new(),
             font_size: 24,
+            font_weight: FW_NORMAL,
+            font_italic: false,
+            font_quality: FontQuality::Antialiased,
             next: KeyCombo { ctrl: true, shift: false, vk: 0x4A }, // Ctrl+J
             prev: KeyCombo { ctrl: true, shift: false, vk: 0x4B }, // Ctrl+K
         }
     }
 }
 
-/// Parse a `#rrggbb` (or `rrggbb`) hex color into a Windows COLORREF (BGR).
-pub fn parse_color(s: &str) -> Option<COLORREF> {
-    let hex = s.strip_prefix('#').unwrap_or(s);
-    if hex.len() != 6 {
-        return None;
+/// How glyph edges are smoothed (`font_quality`).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FontQuality {
+    ClearType,
+    Antialiased,
+    Aliased,
+}
+
+impl FontQuality {
+    /// The config names, as `parse` reads them.
+    pub const NAMES: [&'static str; 3] = ["cleartype", "antialiased", "none"];
+
+    pub fn parse(s: &str) -> Option<FontQuality> {
+        match s.to_ascii_lowercase().as_str() {
+            "cleartype" => Some(FontQuality::ClearType),
+            "antialiased" => Some(FontQuality::Antialiased),
+            "none" => Some(FontQuality::Aliased),
+            _ => None,
+        }
+    }
+
+    /// The config name of the quality, as `parse` reads it.
+    pub fn name(self) -> &'static str {
+        match self {
+            FontQuality::ClearType => "cleartype",
+            FontQuality::Antialiased => "antialiased",
+            FontQuality::Aliased => "none",
+        }
+    }
+
+    fn gdi(self) -> DWORD {
+        match self {
+            FontQuality::ClearType => CLEARTYPE_QUALITY,
+            FontQuality::Antialiased => ANTIALIASED_QUALITY,
+            FontQuality::Aliased => NONANTIALIASED_QUALITY,
+        }
     }
-    let r = u32::from_str_radix(&hex[0..2], 16).ok()?;
-    let g = u32::from_str_radix(&hex[2..4], 16).ok()?;
-    let b = u32::from_str_radix(&hex[4..6], 16).ok()?;
-    Some((b << 16) | (g << 8) | r)
 }
 
-fn to_wide(s: &str) -> Vec<u16> {
-    OsStr::new(s).encode_wide().chain(std::iter::once(0)).collect()
+/// Parse a color in any of the forms `color::parse` accepts (`#rrggbb`,
+/// `#rgb`, `rgb()`, `hsl()`, CSS names, ...) into a Windows COLORREF (BGR).
+/// GDI has no alpha, so any alpha given is dropped.
+pub fn parse_color(s: &str) -> Option<COLORREF> {
+    let ([r, g, b], _alpha) = color::parse(s)?;
+    Some((u32::from(b) << 16) | (u32::from(g) << 8) | u32::from(r))
+}
+
+/// Format a COLORREF back as `#rrggbb`.
+pub fn format_color(color: COLORREF) -> String {
+    format!("#{:02x}{:02x}{:02x}", color & 0xff, (color >> 8) & 0xff, (color >> 16) & 0xff)
 }
 
-struct Entry {
-    text: String,
-    lower: String,
-    wide: Vec<u16>,
-    width: i32, // text width in px; measured in create_window, horizontal mode only
+fn to_wide(s: &str) -> Vec<u16> {
+    OsStr::new(s).encode_wide().chain(std::iter::once(0)).collect()
 }
 
 struct State {
@@ -175,45 +201,40 @@ struct State {
     prompt_wide: Option<Vec<u16>>,
     marker_width: i32, // "<"/">" page-marker slot (horizontal mode, else 0)
 
-    entries: Vec<Entry>,
-    search_results: Vec<usize>, // indices into `entries`
-    selected: Option<usize>,    // index into `search_results`
+    // Filtering, selection and the result live in the engine; the window
+    // keeps only what drawing needs, indexed like the engine's entries
+    engine: Engine,
+    wide: Vec<Vec<u16>>,
+    text_widths: Vec<i32>, // text width in px; measured in create_window, horizontal mode only
 
     // Double-buffer for WM_PAINT (owned per invocation, unlike the C static)
     buffer_dc: HDC,
     buffer_bitmap: HBITMAP,
 
-    done: bool,
-    result: Option<String>,
+    closed: bool,
 }
 
-/// Greedily pack cell widths into pages of at most `avail` width, returning
-/// the first index of each page. Every page holds at least one cell, so a
-/// cell wider than `avail` gets a page of its own (drawn ellipsized).
-fn pack_pages(widths: &[i32], avail: i32) -> Vec<usize> {
-    let mut starts = Vec::new();
-    let mut used = 0;
-    for (i, &w) in widths.iter().enumerate() {
-        if starts.is_empty() || used + w > avail {
-            starts.push(i);
-            used = 0;
-        }
-        used += w;
-    }
-    starts
-}
-
-// Layout: all geometry questions ("what's visible, where does entry N go,
-// what's under the cursor") are answered here, so the paint and mouse code
-// stays layout-agnostic. The vertical/horizontal branch lives only in these
-// helpers.
+// Layout: all geometry questions ("where does entry N go, what's under the
+// cursor") are answered here, so the paint and mouse code stays
+// layout-agnostic. The vertical/horizontal branch lives only in these
+// helpers; which results are on the current page is the engine's call.
 impl State {
     fn font_hmargin(&self) -> i32 {
         self.settings.font_size / 6
     }
 
+    /// Thickness of the line under the input box: none in horizontal mode,
+    /// where entries sit beside the input, or without a separator color.
+    fn separator_height(&self) -> i32 {
+        if self.settings.separator.is_some() && !self.settings.horizontal {
+            (self.settings.font_size / 16).max(1)
+        } else {
+            0
+        }
+    }
+
     fn entries_top(&self) -> i32 {
-        self.settings.font_size + self.settings.padding
+        self.settings.font_size + self.settings.padding + self.separator_height()
     }
 
     /// Pixel width of the input box. Vertical mode gives it everything right
@@ -234,53 +255,35 @@ impl State {
         self.settings.padding + self.prompt_width + self.input_width() + self.marker_width
     }
 
-    /// Width of entry cell `idx` (into `search_results`): its text plus the
-    /// same side margins the vertical rows use.
+    /// Width of entry cell `idx` (into the results): its text plus the same
+    /// side margins the vertical rows use.
     fn cell_width(&self, idx: usize) -> i32 {
-        self.entries[self.search_results[idx]].width + self.font_hmargin() * 2
+        self.text_widths[self.engine.results()[idx]] + self.font_hmargin() * 2
     }
 
-    /// First result index of each page. Vertical pages are `line_count` fixed
-    /// rows; horizontal pages hold as many cells as fit in the bar. Empty when
-    /// nothing can be shown.
-    fn page_starts(&self) -> Vec<usize> {
-        let count = self.search_results.len();
-        if count == 0 {
-            return Vec::new();
-        }
+    /// How the engine should split results into pages. Vertical pages are
+    /// `line_count` fixed rows; horizontal pages hold as many cells as fit in
+    /// the bar.
+    fn paging(&self) -> Paging {
         if self.settings.horizontal {
-            let widths: Vec<i32> = (0..count).map(|i| self.cell_width(i)).collect();
+            let hmargin = self.font_hmargin();
             // The ">" marker slot on the right mirrors the "<" inside entries_left
             let avail = self.width - self.settings.padding - self.marker_width - self.entries_left();
-            pack_pages(&widths, avail)
+            let widths = self.text_widths.iter().map(|w| w + hmargin * 2).collect();
+            Paging::Cells { avail, widths }
         } else {
-            if self.line_count == 0 {
-                return Vec::new();
-            }
-            (0..count).step_by(self.line_count).collect()
-        }
-    }
-
-    /// The page of results containing the selection.
-    fn visible_range(&self) -> std::ops::Range<usize> {
-        let starts = self.page_starts();
-        if starts.is_empty() {
-            return 0..0;
+            Paging::Rows(self.line_count)
         }
-        let sel = self.selected.unwrap_or(0);
-        let page = starts.partition_point(|&s| s <= sel) - 1;
-        let end = starts.get(page + 1).copied().unwrap_or(self.search_results.len());
-        starts[page]..end
     }
 
-    /// Selection box of entry `idx`, which must lie in `visible_range()`. Text
+    /// Selection box of entry `idx`, which must lie in the visible range. Text
     /// is drawn inset by `font_hmargin` on each side.
     fn entry_rect(&self, idx: usize) -> RECT {
         let padding = self.settings.padding;
         let font_size = self.settings.font_size;
         if self.settings.horizontal {
             let mut left = self.entries_left();
-            for i in self.visible_range().start..idx {
+            for i in self.engine.visible_range().start..idx {
                 left += self.cell_width(i);
             }
             RECT {
@@ -290,7 +293,7 @@ impl State {
                 bottom: padding + font_size,
             }
         } else {
-            let row = (idx - self.visible_range().start) as i32;
+            let row = (idx - self.engine.visible_range().start) as i32;
             let top = self.entries_top() + row * font_size;
             RECT {
                 left: padding,
@@ -304,11 +307,12 @@ impl State {
     /// Result index under a client-area point, if any. Vertical mode keeps the
     /// C behavior of clamping clicks past the end to the last entry.
     fn hit_test(&self, x: i32, y: i32) -> Option<usize> {
-        if self.search_results.is_empty() {
+        let count = self.engine.results().len();
+        if count == 0 {
             return None;
         }
         if self.settings.horizontal {
-            self.visible_range().find(|&idx| {
+            self.engine.visible_range().find(|&idx| {
                 let r = self.entry_rect(idx);
                 x >= r.left && x < r.right && y >= r.top
             })
@@ -318,186 +322,142 @@ impl State {
                 return None;
             }
             let offset = ((y - entries_top) / self.settings.font_size).max(0) as usize;
-            Some((self.visible_range().start + offset).min(self.search_results.len() - 1))
+            Some((self.engine.visible_range().start + offset).min(count - 1))
         }
     }
 }
 
-/// Show the menu and block until the user selects an entry, submits custom
-/// text (Shift+Enter), or cancels (Escape / focus loss / close). Must be
-/// called from a thread that can own a window and pump messages.
-pub fn show(settings: &Settings, entries: &[String]) -> Option<String> {
-    unsafe { show_inner(settings, entries) }
-}
-
-unsafe fn finish(state: &mut State, result: Option<String>) {
-    if !state.done {
-        state.done = true;
-        state.result = result;
-        ShowWindow(state.main_wnd, SW_HIDE);
-        PostQuitMessage(0);
-    }
-}
-
-unsafe fn get_edit_text(state: &State) -> String {
-    let len = GetWindowTextLengthW(state.edit_wnd);
-    if len <= 0 {
-        return String::new();
-    }
-    let mut buf = vec![0u16; len as usize + 1];
-    let read = GetWindowTextW(state.edit_wnd, buf.as_mut_ptr(), len + 1);
-    String::from_utf16_lossy(&buf[..read.max(0) as usize])
+/// Per UTF-16 unit of `text`: whether its char is one of `positions`
+/// (ascending char indices).
+fn utf16_mask(text: &str, positions: &[usize]) -> Vec<bool> {
+    text.chars()
+        .enumerate()
+        .flat_map(|(i, c)| std::iter::repeat_n(positions.binary_search(&i).is_ok(), c.len_utf16()))
+        .collect()
 }
 
-fn filter_reduce(state: &mut State, needle: &str) {
-    if needle.is_empty() {
-        return;
-    }
-    if state.settings.case_sensitive {
-        let entries = &state.entries;
-        state.search_results.retain(|&i| entries[i].text.contains(needle));
+/// Draw `text` (no terminating NUL) at the top-left of `rect` like
+/// `DRAWTEXT_PARAMS` does, but with the units flagged in `mask` in
+/// `match_color`. Runs of one color are drawn with `TextOutW` at offsets from
+/// a single extent measurement; text that doesn't fit is cut and ends in
+/// "...", as `DT_END_ELLIPSIS` would.
+unsafe fn draw_highlighted(hdc: HDC, text: &[u16], mask: &[bool], rect: &RECT, color: COLORREF, match_color: COLORREF) {
+    let avail = rect.right - rect.left;
+    // extents[i]: width of text[..=i]
+    let mut extents = vec![0i32; text.len()];
+    let mut size: SIZE = mem::zeroed();
+    GetTextExtentExPointW(hdc, text.as_ptr(), text.len() as i32, 0, ptr::null_mut(),
+        extents.as_mut_ptr(), &mut size);
+    let offset = |i: usize| if i == 0 { 0 } else { extents[i - 1] };
+
+    let dots: Vec<u16> = "...".encode_utf16().collect();
+    let shown = if size.cx <= avail {
+        text.len()
     } else {
-        let needle = needle.to_lowercase();
-        let entries = &state.entries;
-        state.search_results.retain(|&i| entries[i].lower.contains(&needle));
-    }
-}
+        let mut dots_size: SIZE = mem::zeroed();
+        GetTextExtentPoint32W(hdc, dots.as_ptr(), dots.len() as i32, &mut dots_size);
+        extents.partition_point(|&x| x + dots_size.cx <= avail)
+    };
 
-/// fzf-style subsequence scorer. Returns None when `needle` is not a
-/// subsequence of `haystack`, otherwise the score of the best-scoring
-/// alignment (dynamic programming, not greedy first-occurrence), favoring
-/// matches at word boundaries and camelCase humps, consecutive runs, and
-/// short gaps. Leading and trailing gaps are free.
-fn fuzzy_score(needle: &str, haystack: &str, case_sensitive: bool) -> Option<i32> {
-    const SCORE_MATCH: i32 = 16;
-    const BONUS_BOUNDARY: i32 = 16;
-    const BONUS_CAMEL: i32 = 12;
-    const BONUS_CONSECUTIVE: i32 = 8;
-    const PENALTY_GAP_START: i32 = -3;
-    const PENALTY_GAP_EXTEND: i32 = -1;
-    const UNMATCHED: i32 = i32::MIN / 2; // headroom so additions can't overflow
-
-    fn is_camel(prev: char, cur: char) -> bool {
-        (prev.is_lowercase() && cur.is_uppercase())
-            || (prev.is_alphabetic() && cur.is_numeric())
+    let mut start = 0;
+    while start < shown {
+        let matched = mask[start];
+        let end = (start..shown).find(|&i| mask[i] != matched).unwrap_or(shown);
+        SetTextColor(hdc, if matched { match_color } else { color });
+        TextOutW(hdc, rect.left + offset(start), rect.top, text[start..].as_ptr(), (end - start) as i32);
+        start = end;
     }
-
-    if needle.is_empty() {
-        return Some(0);
+    if shown < text.len() {
+        SetTextColor(hdc, color);
+        TextOutW(hdc, rect.left + offset(shown), rect.top, dots.as_ptr(), dots.len() as i32);
     }
-    let hay: Vec<char> = haystack.chars().collect();
-
-    // Positional bonus for a match at each haystack index
-    let bonus: Vec<i32> = hay
-        .iter()
-        .enumerate()
-        .map(|(j, &c)| match if j == 0 { None } else { Some(hay[j - 1]) } {
-            None => BONUS_BOUNDARY,
-            Some(p) if !p.is_alphanumeric() => BONUS_BOUNDARY,
-            Some(p) if is_camel(p, c) => BONUS_CAMEL,
-            _ => 0,
-        })
-        .collect();
-
-    let matches = |nc: char, hc: char| {
-        if case_sensitive {
-            hc == nc
-        } else {
-            hc.to_lowercase().eq(nc.to_lowercase())
-        }
-    };
+}
 
-    // ending[j]: best score matching the needle prefix so far with its last
-    // char matched exactly at haystack index j
-    let mut ending = vec![UNMATCHED; hay.len()];
-    let mut first_row = true;
-    for nc in needle.chars() {
-        let mut next = vec![UNMATCHED; hay.len()];
-        // Best previous-row score ending strictly before j-1, with affine gap
-        // penalties applied for the unmatched span up to j-1
-        let mut gapped = UNMATCHED;
-        for (j, &hc) in hay.iter().enumerate() {
-            if matches(nc, hc) {
-                if first_row {
-                    next[j] = SCORE_MATCH + bonus[j];
-                } else {
-                    let diag = if j > 0 { ending[j - 1] } else { UNMATCHED };
-                    let best = (diag + BONUS_CONSECUTIVE).max(gapped);
-                    if best > UNMATCHED {
-                        next[j] = best + SCORE_MATCH + bonus[j];
-                    }
-                }
-            }
-            if j > 0 {
-                gapped = (gapped + PENALTY_GAP_EXTEND).max(ending[j - 1] + PENALTY_GAP_START);
-            }
-        }
-        ending = next;
-        first_row = false;
-    }
+/// What the menu returned: the chosen entry (or Shift+Enter custom text) and
+/// the query that was in the input box at the time.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Selection {
+    pub text: String,
+    pub query: String,
+}
 
-    let best = ending.into_iter().max()?;
-    if best > UNMATCHED / 2 { Some(best) } else { None }
+/// Show the menu and block until the user selects an entry, submits custom
+/// text (Shift+Enter), or cancels (Escape / focus loss / close). `aliases`
+/// are hidden search terms by entry; `adaptive` boosts entries learned for
+/// the typed query. Must be called from a thread that can own a window and
+/// pump messages.
+pub fn show(
+    settings: &Settings,
+    entries: &[String],
+    aliases: &HashMap<String, Vec<String>>,
+    adaptive: Option<Adaptive>,
+) -> Option<Selection> {
+    unsafe { show_inner(settings, entries, aliases, adaptive) }
 }
 
-fn filter_fuzzy(state: &mut State, needle: &str) {
-    if needle.is_empty() {
-        return;
+/// Hide the window and leave the message loop once the engine is done.
+unsafe fn close(state: &mut State) {
+    if !state.closed {
+        state.closed = true;
+        ShowWindow(state.main_wnd, SW_HIDE);
+        PostQuitMessage(0);
     }
-    let case_sensitive = state.settings.case_sensitive;
-    let entries = &state.entries;
-    let mut scored: Vec<(i32, usize)> = state
-        .search_results
-        .iter()
-        .filter_map(|&i| fuzzy_score(needle, &entries[i].text, case_sensitive).map(|s| (s, i)))
-        .collect();
-    // Descending by score, original entry order as tie-break
-    scored.sort_by(|a, b| b.0.cmp(&a.0).then(a.1.cmp(&b.1)));
-    state.search_results = scored.into_iter().map(|(_, i)| i).collect();
 }
 
-unsafe fn update_search_results(state: &mut State) {
-    state.search_results = (0..state.entries.len()).collect();
-
-    let query = get_edit_text(state);
-    match state.settings.filter_mode {
-        FilterMode::Complete => filter_reduce(state, &query),
-        FilterMode::Keywords => {
-            for word in query.split(' ') {
-                filter_reduce(state, word);
-            }
+/// Carry out what the engine asked for after an input. Returns whether the
+/// event was consumed.
+unsafe fn apply_outcome(state: &mut State, outcome: Outcome) -> bool {
+    match outcome {
+        Outcome::Ignored => return false,
+        Outcome::Redraw => {}
+        Outcome::SetQuery => {
+            let wide = to_wide(state.engine.query());
+            let len = wide.len() - 1;
+            SetWindowTextW(state.edit_wnd, wide.as_ptr());
+            call_orig_edit(state, state.edit_wnd, EM_SETSEL as UINT, len, len as LPARAM);
+        }
+        Outcome::Done => {
+            close(state);
+            return true;
         }
-        FilterMode::Fuzzy => filter_fuzzy(state, &query),
     }
-
-    state.selected = if state.search_results.is_empty() { None } else { Some(0) };
     RedrawWindow(state.main_wnd, ptr::null(), ptr::null_mut(), RDW_INVALIDATE);
+    true
 }
 
-/// Move the selection by `delta` (wrapping), matching the C modulo behavior.
-unsafe fn move_selection(state: &mut State, delta: isize) {
-    let count = state.search_results.len();
-    if count == 0 {
-        return;
+unsafe fn get_edit_text(state: &State) -> String {
+    let len = GetWindowTextLengthW(state.edit_wnd);
+    if len <= 0 {
+        return String::new();
     }
-    let cur = state.selected.unwrap_or(0) as isize;
-    let next = (cur + delta).rem_euclid(count as isize) as usize;
-    state.selected = Some(next);
-    RedrawWindow(state.main_wnd, ptr::null(), ptr::null_mut(), RDW_INVALIDATE);
+    let mut buf = vec![0u16; len as usize + 1];
+    let read = GetWindowTextW(state.edit_wnd, buf.as_mut_ptr(), len + 1);
+    String::from_utf16_lossy(&buf[..read.max(0) as usize])
 }
 
-unsafe fn set_selection(state: &mut State, index: usize) {
-    let count = state.search_results.len();
-    if count == 0 {
-        return;
+/// Push the EDIT control's text and caret into the engine. EDIT reports
+/// positions in UTF-16 units; the engine counts chars. Redraws when the
+/// results changed.
+unsafe fn sync_query(state: &mut State) {
+    let text = get_edit_text(state);
+    let (start, end) = edit_caret(state, state.edit_wnd);
+    let to_chars = |units: u32| {
+        let mut seen = 0;
+        text.chars()
+            .take_while(|c| {
+                seen += c.len_utf16() as u32;
+                seen <= units
+            })
+            .count()
+    };
+    let changed = text != state.engine.query();
+    // EM_GETSEL doesn't say which end holds the caret; the edge-navigation
+    // rule only needs both ends, so report start as the anchor
+    let (caret, anchor) = (to_chars(end), to_chars(start));
+    state.engine.set_query(&text, caret, anchor);
+    if changed {
+        RedrawWindow(state.main_wnd, ptr::null(), ptr::null_mut(), RDW_INVALIDATE);
     }
-    state.selected = Some(index.min(count - 1));
-    RedrawWindow(state.main_wnd, ptr::null(), ptr::null_mut(), RDW_INVALIDATE);
-}
-
-unsafe fn selected_entry_text(state: &State) -> Option<String> {
-    let sel = state.selected?;
-    Some(state.entries[state.search_results[sel]].text.clone())
 }
 
 /// Use trick from https://stackoverflow.com/a/59659421
@@ -538,10 +498,11 @@ unsafe extern "system" fn edit_wnd_proc(wnd: HWND, msg: UINT, wparam: WPARAM, lp
     match msg {
         WM_KILLFOCUS => {
             // Losing focus cancels the menu to avoid lingering windows
-            finish(state, None);
+            state.engine.cancel();
+            close(state);
         }
         WM_CHAR => {
-            let mut result: LRESULT = 0;
+            let result: LRESULT;
             match wparam {
                 0x01 => {
                     // Ctrl+A - Select everything
@@ -555,20 +516,12 @@ unsafe extern "system" fn edit_wnd_proc(wnd: HWND, msg: UINT, wparam: WPARAM, lp
                     call_orig_edit(state, wnd, WM_KEYUP, VK_LEFT as WPARAM, 0);
                     let (start_sel, _) = edit_caret(state, wnd);
                     call_orig_edit(state, wnd, EM_SETSEL as UINT, start_sel as WPARAM, end_sel as LPARAM);
-                    call_orig_edit(state, wnd, WM_CHAR, 0x08, 0); // Backspace
-                }
-                0x09 => {
-                    // Tab - Autocomplete with the selected entry
-                    if let Some(sel) = state.selected {
-                        let entry = &state.entries[state.search_results[sel]];
-                        let len = entry.text.encode_utf16().count();
-                        SetWindowTextW(wnd, entry.wide.as_ptr());
-                        call_orig_edit(state, wnd, EM_SETSEL as UINT, len, len as LPARAM);
-                    }
+                    result = call_orig_edit(state, wnd, WM_CHAR, 0x08, 0); // Backspace
                 }
-                // Swallow CR (handled in WM_KEYDOWN) plus the control char of any
-                // Ctrl+<letter> navigation combo, so it isn't typed into the box.
-                0x0D => return 0,
+                // Swallow Tab and CR (handled as keys in WM_KEYDOWN) plus the
+                // control char of any Ctrl+<letter> navigation combo, so they
+                // aren't typed into the box.
+                0x09 | 0x0D => return 0,
                 c if Some(c as u32) == state.settings.next.char_to_swallow()
                     || Some(c as u32) == state.settings.prev.char_to_swallow() =>
                 {
@@ -578,107 +531,25 @@ unsafe extern "system" fn edit_wnd_proc(wnd: HWND, msg: UINT, wparam: WPARAM, lp
                     result = call_orig_edit(state, wnd, msg, wparam, lparam);
                 }
             }
-            update_search_results(state);
+            sync_query(state);
             return result;
         }
         WM_KEYDOWN => {
-            let ctrl_pressed = GetKeyState(VK_CONTROL) & 0x8000u16 as i16 != 0;
-            let shift_pressed = GetKeyState(VK_SHIFT) & 0x8000u16 as i16 != 0;
-
-            // Configurable next/prev navigation combos take precedence over the
-            // hardcoded keys below (arrows/Home/End/PageUp/PageDown stay fixed).
-            let vk = wparam as u16;
-            if state.settings.next.matches(vk, ctrl_pressed, shift_pressed) {
-                move_selection(state, 1);
-                return 0;
-            }
-            if state.settings.prev.matches(vk, ctrl_pressed, shift_pressed) {
-                move_selection(state, -1);
+            let mods = Mods {
+                ctrl: GetKeyState(VK_CONTROL) & 0x8000u16 as i16 != 0,
+                shift: GetKeyState(VK_SHIFT) & 0x8000u16 as i16 != 0,
+            };
+            // The engine needs the current caret for edge-triggered Left/Right
+            sync_query(state);
+            let outcome = state.engine.handle(Input::Key(wparam as u16, mods));
+            if apply_outcome(state, outcome) {
                 return 0;
             }
-
-            match wparam as i32 {
-                VK_RETURN => {
-                    // If no results or shift is held: return input, else: return selection
-                    let result = if shift_pressed {
-                        get_edit_text(state)
-                    } else {
-                        match selected_entry_text(state) {
-                            Some(text) => text,
-                            None => get_edit_text(state),
-                        }
-                    };
-                    finish(state, Some(result));
-                    return 0;
-                }
-                VK_ESCAPE => {
-                    finish(state, None);
-                    return 0;
-                }
-                VK_UP => {
-                    move_selection(state, -1);
-                    return 0;
-                }
-                VK_DOWN => {
-                    move_selection(state, 1);
-                    return 0;
-                }
-                // Edge-triggered navigation (dmenu's rule): an unmodified
-                // Left/Right moves the selection once the caret can't travel
-                // any further in that direction, and keeps editing text
-                // otherwise. Ctrl/Shift+arrows stay pure text operations.
-                VK_LEFT if !ctrl_pressed && !shift_pressed => {
-                    if edit_caret(state, wnd) == (0, 0) {
-                        move_selection(state, -1);
-                        return 0;
-                    }
-                }
-                VK_RIGHT if !ctrl_pressed && !shift_pressed => {
-                    let len = GetWindowTextLengthW(state.edit_wnd) as u32;
-                    if edit_caret(state, wnd) == (len, len) {
-                        move_selection(state, 1);
-                        return 0;
-                    }
-                }
-                VK_HOME => {
-                    set_selection(state, 0);
-                    return 0;
-                }
-                VK_END => {
-                    let count = state.search_results.len();
-                    if count > 0 {
-                        set_selection(state, count - 1);
-                    }
-                    return 0;
-                }
-                VK_PRIOR => {
-                    // Page Up - Start of the previous page
-                    if let Some(sel) = state.selected {
-                        let starts = state.page_starts();
-                        if !starts.is_empty() {
-                            let page = starts.partition_point(|&s| s <= sel) - 1;
-                            set_selection(state, starts[page.saturating_sub(1)]);
-                        }
-                    }
-                    return 0;
-                }
-                VK_NEXT => {
-                    // Page Down - Start of the next page (or the last entry)
-                    if let Some(sel) = state.selected {
-                        let starts = state.page_starts();
-                        if !starts.is_empty() {
-                            let page = starts.partition_point(|&s| s <= sel) - 1;
-                            let target = starts
-                                .get(page + 1)
-                                .copied()
-                                .unwrap_or(state.search_results.len() - 1);
-                            set_selection(state, target);
-                        }
-                    }
-                    return 0;
-                }
-                _ => {}
-            }
+            // Not a menu key: let the EDIT control edit, then pick up any
+            // change it made without a WM_CHAR (e.g. Delete)
+            let result = call_orig_edit(state, wnd, msg, wparam, lparam);
+            sync_query(state);
+            return result;
         }
         _ => {}
     }
@@ -693,17 +564,16 @@ unsafe extern "system" fn main_wnd_proc(wnd: HWND, msg: UINT, wparam: WPARAM, lp
     };
 
     match msg {
-        WM_TIMER => {
-            // Repeating timer to make sure we're the foreground window
-            if wparam == FOREGROUND_TIMER_ID {
-                if GetForegroundWindow() == wnd {
-                    state.had_foreground = true;
-                } else if state.had_foreground {
-                    // Focus lost after being in front: cancel
-                    finish(state, None);
-                } else {
-                    force_foreground(state.main_wnd);
-                }
+        // Repeating timer to make sure we're the foreground window
+        WM_TIMER if wparam == FOREGROUND_TIMER_ID => {
+            if GetForegroundWindow() == wnd {
+                state.had_foreground = true;
+            } else if state.had_foreground {
+                // Focus lost after being in front: cancel
+                state.engine.cancel();
+                close(state);
+            } else {
+                force_foreground(state.main_wnd);
             }
         }
         WM_PAINT => {
@@ -722,15 +592,33 @@ unsafe extern "system" fn main_wnd_proc(wnd: HWND, msg: UINT, wparam: WPARAM, lp
             }
             let hdc = state.buffer_dc;
 
-            // Clear window
+            // Clear window, inside the border if there is one
+            let border = state.settings.border_width;
+            if border > 0 {
+                let color = state.settings.border.unwrap_or(state.settings.bg_select);
+                SetDCPenColor(hdc, color);
+                SetDCBrushColor(hdc, color);
+                Rectangle(hdc, 0, 0, state.width, state.height);
+            }
             SetDCPenColor(hdc, state.settings.bg);
             SetDCBrushColor(hdc, state.settings.bg);
-            Rectangle(hdc, 0, 0, state.width, state.height);
+            Rectangle(hdc, border, border, state.width - border, state.height - border);
 
             let padding = state.settings.padding;
             let font_size = state.settings.font_size;
             let hmargin = state.font_hmargin();
 
+            // Separator between the input box and the entries
+            if let Some(color) = state.settings.separator {
+                let top = padding + font_size;
+                let separator_height = state.separator_height();
+                if separator_height > 0 {
+                    SetDCPenColor(hdc, color);
+                    SetDCBrushColor(hdc, color);
+                    Rectangle(hdc, padding, top, state.width - padding, top + separator_height);
+                }
+            }
+
             // Draw prompt
             if let Some(ref prompt_wide) = state.prompt_wide {
                 let mut prompt_rect = RECT {
@@ -740,27 +628,28 @@ unsafe extern "system" fn main_wnd_proc(wnd: HWND, msg: UINT, wparam: WPARAM, lp
                     bottom: padding + font_size * 2,
                 };
 
-                SetDCPenColor(hdc, state.settings.bg_select);
-                SetDCBrushColor(hdc, state.settings.bg_select);
+                let bg_prompt = state.settings.bg_prompt.unwrap_or(state.settings.bg_select);
+                SetDCPenColor(hdc, bg_prompt);
+                SetDCBrushColor(hdc, bg_prompt);
                 Rectangle(hdc, padding, prompt_rect.top,
                         padding + state.prompt_width,
                         prompt_rect.top + font_size);
 
-                SetTextColor(hdc, state.settings.fg_select);
+                SetTextColor(hdc, state.settings.fg_prompt.unwrap_or(state.settings.fg_select));
                 DrawTextW(hdc, prompt_wide.as_ptr(), -1, &mut prompt_rect, DRAWTEXT_PARAMS);
             }
 
             // Draw entries
-            SetTextColor(hdc, state.settings.fg);
-            for idx in state.visible_range() {
-                let rect = state.entry_rect(idx);
-                let is_selected = state.selected == Some(idx);
-                if is_selected {
+            for row in state.engine.visible_rows() {
+                let rect = state.entry_rect(row.index);
+                let (fg, fg_match) = if row.selected {
                     SetDCPenColor(hdc, state.settings.bg_select);
                     SetDCBrushColor(hdc, state.settings.bg_select);
                     Rectangle(hdc, rect.left, rect.top, rect.right, rect.bottom);
-                    SetTextColor(hdc, state.settings.fg_select);
-                }
+                    (state.settings.fg_select, state.settings.fg_match_select)
+                } else {
+                    (state.settings.fg, state.settings.fg_match)
+                };
 
                 let mut text_rect = RECT {
                     left: rect.left + hmargin,
@@ -768,18 +657,20 @@ unsafe extern "system" fn main_wnd_proc(wnd: HWND, msg: UINT, wparam: WPARAM, lp
                     right: rect.right - hmargin,
                     bottom: rect.bottom,
                 };
-                let entry = &state.entries[state.search_results[idx]];
-                DrawTextW(hdc, entry.wide.as_ptr(), -1, &mut text_rect, DRAWTEXT_PARAMS);
-
-                if is_selected {
-                    SetTextColor(hdc, state.settings.fg);
+                SetTextColor(hdc, fg);
+                let wide = &state.wide[row.entry];
+                if row.matches.is_empty() {
+                    DrawTextW(hdc, wide.as_ptr(), -1, &mut text_rect, DRAWTEXT_PARAMS);
+                } else {
+                    let mask = utf16_mask(row.text, &row.matches);
+                    draw_highlighted(hdc, &wide[..wide.len() - 1], &mask, &text_rect, fg, fg_match);
                 }
             }
 
             // Page markers (horizontal): "<"/">" in the reserved slots flanking
             // the entries, drawn only when more pages exist on that side
             if state.settings.horizontal {
-                let visible = state.visible_range();
+                let visible = state.engine.visible_range();
                 SetTextColor(hdc, state.settings.fg);
                 let draw_marker = |text: &str, left: i32| {
                     let wide = to_wide(text);
@@ -794,7 +685,7 @@ unsafe extern "system" fn main_wnd_proc(wnd: HWND, msg: UINT, wparam: WPARAM, lp
                 if visible.start > 0 {
                     draw_marker("<", state.entries_left() - state.marker_width);
                 }
-                if visible.end < state.search_results.len() {
+                if visible.end < state.engine.results().len() {
                     draw_marker(">", state.width - padding - state.marker_width);
                 }
             }
@@ -812,28 +703,30 @@ unsafe extern "system" fn main_wnd_proc(wnd: HWND, msg: UINT, wparam: WPARAM, lp
             SetDCBrushColor(hdc, state.settings.bg_edit);
             return GetStockObject(DC_BRUSH as i32) as LRESULT;
         }
+        WM_APP_FILTERED => {
+            if state.engine.poll() {
+                RedrawWindow(state.main_wnd, ptr::null(), ptr::null_mut(), RDW_INVALIDATE);
+            }
+            return 0;
+        }
         WM_CLOSE => {
-            finish(state, None);
+            state.engine.cancel();
+            close(state);
             return 0;
         }
         WM_LBUTTONDOWN => {
             let mx = (lparam & 0xffff) as i16 as i32; // GET_X_LPARAM
             let my = (lparam >> 16) as i16 as i32; // GET_Y_LPARAM
-            if let Some(new_idx) = state.hit_test(mx, my) {
-                if state.selected == Some(new_idx) {
-                    // Second click on the same entry - select it
-                    if let Some(text) = selected_entry_text(state) {
-                        finish(state, Some(text));
-                    }
-                } else {
-                    set_selection(state, new_idx);
-                }
+            if let Some(idx) = state.hit_test(mx, my) {
+                let outcome = state.engine.handle(Input::Click(idx));
+                apply_outcome(state, outcome);
             }
             return 0;
         }
         WM_MOUSEWHEEL => {
-            let delta = ((wparam >> 16) as i16 as isize) / 120; // GET_WHEEL_DELTA_WPARAM
-            move_selection(state, -delta);
+            let notches = ((wparam >> 16) as i16 as i32) / 120; // GET_WHEEL_DELTA_WPARAM
+            let outcome = state.engine.handle(Input::Wheel(notches));
+            apply_outcome(state, outcome);
             return 0;
         }
         _ => {}
@@ -871,12 +764,15 @@ unsafe fn create_window(state: &mut State) -> Result<(), String> {
     } else {
         state.settings.font_size * (state.line_count as i32 + 1)
                 + state.settings.padding * 2
+                + state.separator_height()
     };
 
     let (mut x, mut y) = (mon.left, mon.top);
     if state.settings.center_window {
         x = mon.left + (display_width - state.width) / 2;
         y = mon.top + (display_height - state.height) / 2;
+    } else if state.settings.bottom {
+        y = mon.bottom - state.height;
     }
 
     let title = to_wide("wlines");
@@ -905,11 +801,11 @@ unsafe fn create_window(state: &mut State) -> Result<(), String> {
             state.prompt_width = prompt_rect.right - prompt_rect.left + state.font_hmargin() * 2;
         }
         if state.settings.horizontal {
-            for entry in &mut state.entries {
+            for (wide, width) in state.wide.iter().zip(state.text_widths.iter_mut()) {
                 let mut rect = RECT { left: 0, top: 0, right: 0, bottom: 0 };
-                DrawTextW(tmp_hdc, entry.wide.as_ptr(), -1, &mut rect,
+                DrawTextW(tmp_hdc, wide.as_ptr(), -1, &mut rect,
                         DT_SINGLELINE | DT_NOPREFIX | DT_CALCRECT);
-                entry.width = rect.right - rect.left;
+                *width = rect.right - rect.left;
             }
             // Fixed slots flanking the entries for the "<"/">" page markers,
             // reserved on both sides regardless of page so cells don't shift
@@ -925,6 +821,8 @@ unsafe fn create_window(state: &mut State) -> Result<(), String> {
         }
         DeleteDC(tmp_hdc);
     }
+    let paging = state.paging();
+    state.engine.set_paging(paging);
 
     // Create textbox
     let edit_class = to_wide("EDIT");
@@ -997,14 +895,54 @@ unsafe fn cursor_monitor() -> (RECT, u32) {
     (rect, dpi)
 }
 
-unsafe fn show_inner(settings: &Settings, entries: &[String]) -> Option<String> {
-    let entries: Vec<Entry> = entries.iter().map(|text| Entry {
-        lower: text.to_lowercase(),
-        wide: to_wide(text),
-        text: text.clone(),
-        width: 0,
-    }).collect();
+/// The first of the font families that is installed, else the first: GDI
+/// quietly substitutes another font for a missing family rather than failing.
+unsafe fn installed_family(settings: &Settings) -> &str {
+    if settings.font_fallbacks.is_empty() {
+        return &settings.font_name;
+    }
+    let hdc = GetDC(ptr::null_mut());
+    let found = std::iter::once(&settings.font_name)
+        .chain(&settings.font_fallbacks)
+        .find(|family| family_installed(hdc, family));
+    ReleaseDC(ptr::null_mut(), hdc);
+    found.unwrap_or(&settings.font_name)
+}
+
+/// Whether a font family of this name is installed.
+pub fn font_installed(family: &str) -> bool {
+    unsafe {
+        let hdc = GetDC(ptr::null_mut());
+        let found = family_installed(hdc, family);
+        ReleaseDC(ptr::null_mut(), hdc);
+        found
+    }
+}
+
+unsafe fn family_installed(hdc: HDC, family: &str) -> bool {
+    let mut logfont: LOGFONTW = mem::zeroed();
+    let name = to_wide(family);
+    if name.len() > logfont.lfFaceName.len() {
+        return false;
+    }
+    logfont.lfFaceName[..name.len()].copy_from_slice(&name);
+    logfont.lfCharSet = DEFAULT_CHARSET as u8;
+    let mut found = false;
+    EnumFontFamiliesExW(hdc, &mut logfont, Some(family_found), &mut found as *mut bool as LPARAM, 0);
+    found
+}
 
+unsafe extern "system" fn family_found(_: *const LOGFONTW, _: *const TEXTMETRICW, _: DWORD, found: LPARAM) -> c_int {
+    *(found as *mut bool) = true;
+    0 // Stop at the first match
+}
+
+unsafe fn show_inner(
+    settings: &Settings,
+    entries: &[String],
+    aliases: &HashMap<String, Vec<String>>,
+    adaptive: Option<Adaptive>,
+) -> Option<Selection> {
     // Config sizes are 96-DPI logical pixels; scale for the target monitor
     let (monitor_rect, dpi) = cursor_monitor();
     let scale = |v: i32| (v * dpi as i32 + 48) / 96;
@@ -1012,22 +950,33 @@ unsafe fn show_inner(settings: &Settings, entries: &[String]) -> Option<String>
     settings.font_size = scale(settings.font_size);
     settings.padding = scale(settings.padding);
     settings.width = scale(settings.width);
+    // The border is drawn inside the window, in what becomes extra padding,
+    // so everything else keeps its place relative to it
+    settings.border_width = scale(settings.border_width.max(0));
+    settings.padding += settings.border_width;
 
-    let font_name = to_wide(&settings.font_name);
+    let font_name = to_wide(installed_family(&settings));
     let font = CreateFontW(settings.font_size, 0, 0, 0,
-        FW_NORMAL, 0, 0, 0, 0, 0, 0, 0x04, 0, font_name.as_ptr());
+        settings.font_weight, DWORD::from(settings.font_italic), 0, 0, 0, 0, 0,
+        settings.font_quality.gdi(), 0, font_name.as_ptr());
     if font.is_null() {
         eprintln!("wlines: CreateFontW failed: error {}", GetLastError());
         return None;
     }
 
     let line_count = settings.line_count.min(entries.len());
-    let search_results: Vec<usize> = (0..entries.len()).collect();
-    let selected = if search_results.is_empty() {
-        None
-    } else {
-        Some(settings.initial_index.min(search_results.len() - 1))
-    };
+    let mut engine = Engine::new(entries, Behavior {
+        filter_mode: settings.filter_mode,
+        case_sensitive: settings.case_sensitive,
+        normalize: settings.normalize,
+        next: settings.next,
+        prev: settings.prev,
+    });
+    engine.set_aliases(aliases);
+    if let Some(adaptive) = adaptive {
+        engine.set_adaptive(adaptive);
+    }
+    engine.select(settings.initial_index);
 
     let mut state = Box::new(State {
         prompt_wide: settings.prompt.as_deref().map(to_wide),
@@ -1043,13 +992,12 @@ unsafe fn show_inner(settings: &Settings, entries: &[String]) -> Option<String>
         had_foreground: false,
         prompt_width: 0,
         marker_width: 0,
-        entries,
-        search_results,
-        selected,
+        engine,
+        wide: entries.iter().map(|text| to_wide(text)).collect(),
+        text_widths: vec![0; entries.len()],
         buffer_dc: ptr::null_mut(),
         buffer_bitmap: ptr::null_mut(),
-        done: false,
-        result: None,
+        closed: false,
     });
 
     if let Err(e) = create_window(&mut state) {
@@ -1058,7 +1006,16 @@ unsafe fn show_inner(settings: &Settings, entries: &[String]) -> Option<String>
         return None;
     }
 
-    // Message loop - runs until finish() posts WM_QUIT
+    if entries.len() >= BACKGROUND_FILTER_MIN {
+        // HWNDs are plain handles, valid from any thread; posting to a
+        // destroyed window just fails
+        let wnd = state.main_wnd as usize;
+        state.engine.spawn_worker(move || {
+            PostMessageW(wnd as HWND, WM_APP_FILTERED, 0, 0);
+        });
+    }
+
+    // Message loop - runs until close() posts WM_QUIT
     let mut msg: MSG = mem::zeroed();
     while GetMessageW(&mut msg, ptr::null_mut(), 0, 0) > 0 {
         TranslateMessage(&msg);
@@ -1081,97 +1038,6 @@ unsafe fn show_inner(settings: &Settings, entries: &[String]) -> Option<String>
     }
     DeleteObject(state.font as _);
 
-    state.result.take()
-}
-
-#[cfg(test)]
-mod tests {
-    use super::{fuzzy_score, pack_pages};
-
-    #[test]
-    fn pack_empty() {
-        assert_eq!(pack_pages(&[], 100), Vec::<usize>::new());
-    }
-
-    #[test]
-    fn pack_fills_pages_greedily() {
-        assert_eq!(pack_pages(&[10, 10, 10], 25), vec![0, 2]);
-    }
-
-    #[test]
-    fn pack_exact_fit_stays_on_page() {
-        assert_eq!(pack_pages(&[10, 10], 20), vec![0]);
-    }
-
-    #[test]
-    fn pack_oversized_cell_gets_own_page() {
-        // A cell wider than the bar still lands on a page of its own
-        // (it gets ellipsized when drawn), and never stalls the packing.
-        assert_eq!(pack_pages(&[50, 10], 30), vec![0, 1]);
-        assert_eq!(pack_pages(&[10, 50, 10], 30), vec![0, 1, 2]);
-    }
-
-    #[test]
-    fn non_subsequence_rejected() {
-        assert_eq!(fuzzy_score("xyz", "Google Chrome", false), None);
-        assert_eq!(fuzzy_score("chromee", "Chrome", false), None);
-    }
-
-    #[test]
-    fn empty_needle_matches_everything() {
-        assert_eq!(fuzzy_score("", "anything", false), Some(0));
-    }
-
-    #[test]
-    fn case_insensitive_by_default() {
-        assert!(fuzzy_score("chrome", "Google Chrome", false).is_some());
-        assert_eq!(fuzzy_score("chrome", "Google Chrome", true), None);
-        assert!(fuzzy_score("Chrome", "Google Chrome", true).is_some());
-    }
-
-    #[test]
-    fn boundary_match_beats_mid_word_match() {
-        let boundary = fuzzy_score("code", "Visual Studio Code", false).unwrap();
-        let mid_word = fuzzy_score("code", "Unicodex", false).unwrap();
-        assert!(boundary > mid_word);
-    }
-
-    #[test]
-    fn consecutive_run_beats_scattered_match() {
-        let run = fuzzy_score("term", "Terminal", false).unwrap();
-        let scattered = fuzzy_score("term", "Text Formatter", false).unwrap();
-        assert!(run > scattered);
-    }
-
-    #[test]
-    fn camel_hump_scores_above_plain_mid_word() {
-        let camel = fuzzy_score("pp", "PowerPoint", false).unwrap();
-        let plain = fuzzy_score("pp", "clipper", false).unwrap();
-        assert!(camel > plain);
-    }
-
-    #[test]
-    fn acronym_style_matching_works() {
-        // Classic fzf use case: initials of a multi-word entry beat a
-        // scattered mid-word match
-        let acronym = fuzzy_score("vsc", "Visual Studio Code", false).unwrap();
-        let scattered = fuzzy_score("vsc", "vesicular", false).unwrap();
-        assert!(acronym > scattered);
-    }
-
-    #[test]
-    fn picks_best_alignment_not_first_occurrence() {
-        // Greedy matching would take the 's' in "Visual" and miss the
-        // word-boundary 'S' of "Studio"; the DP must find the better path
-        let boundary = fuzzy_score("st", "Visual Studio", false).unwrap();
-        let mid_word = fuzzy_score("st", "Restart", false).unwrap();
-        assert!(boundary > mid_word);
-    }
-
-    #[test]
-    fn shorter_gap_scores_higher() {
-        let short_gap = fuzzy_score("ab", "acb", false).unwrap();
-        let long_gap = fuzzy_score("ab", "acccccb", false).unwrap();
-        assert!(short_gap > long_gap);
-    }
+    let text = state.engine.take_result()?;
+    Some(Selection { text, query: state.engine.query().to_string() })
 }